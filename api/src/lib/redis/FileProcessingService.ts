@@ -4,7 +4,9 @@ import { BaseRedisService } from './BaseRedisService';
 export enum FileStatus {
     PENDING = "pending",
     DONE = "done",
-    FAILED = "failed"
+    FAILED = "failed",
+    PASSWORD_REQUIRED = "password_required",
+    WRONG_PASSWORD = "wrong_password"
 }
 
 export class FileProcessingService extends BaseRedisService {
@@ -30,6 +32,10 @@ export class FileProcessingService extends BaseRedisService {
                 return FileStatus.DONE;
             case "failed":
                 return FileStatus.FAILED;
+            case "password_required":
+                return FileStatus.PASSWORD_REQUIRED;
+            case "wrong_password":
+                return FileStatus.WRONG_PASSWORD;
             case 'pending':
                 return FileStatus.PENDING;
             default:
@@ -38,6 +44,7 @@ export class FileProcessingService extends BaseRedisService {
     }
 
     async startFileProcess(fileId: string, ttl: number = 3600): Promise<void> {
+        await this.redis.del(`${this.prefix}:error:${fileId}`);
         await this.setWithTTL(
             `${this.prefix}:${fileId}`,
             FileStatus.PENDING,
@@ -68,6 +75,10 @@ export class FileProcessingService extends BaseRedisService {
         }
     }
 
+    async getFileError(fileId: string): Promise<string | null> {
+        return await this.redis.get(`${this.prefix}:error:${fileId}`);
+    }
+
     async getFileProgress(fileId: string): Promise<number> {
         return await this.getProgress(fileId);
     }
@@ -4,7 +4,7 @@ import bodyParser from 'body-parser';
 import { upload, uploadExists, processedExists, getProcessedFilePath } from '@/helpers/uploadhelper';
 import { ResponseHelper } from '@/helpers/response';
 import mqConnection, { Queue } from '@/lib/rabbitmq';
-import { NewFileProcessQueue, OllamaModelPull, ProcessedFile } from '@/types/queue';
+import { NewFileProcessQueue, OllamaModelPull, ProcessedFile, Status } from '@/types/queue';
 import { ProcessResponse, UploadResponse, ProgressResponse, FinalResponse } from '@/types/response';
 import { ProcessOptions } from '@/types/request';
 import {
@@ -16,6 +16,7 @@ import {
     getModelProgress,
     getModelStatus,
     ModelStatus,
+    FileStatus,
     modelDownloadService,
     fileProcessingService
 } from '@/lib/redis';
@@ -82,6 +83,7 @@ app.post('/process/:id', async (req: Request, res: Response) => {
         const { id } = req.params;
         const { startPage = 1, pageCount = 0, priority = 1, engine = 'tesseract' } = req.body as ProcessOptions;
         let model = (req.body as ProcessOptions).model;
+        const { pages, password, renderMode, renderDpi, ocrMode, pageConcurrency, processAttachments, attachmentDepth, layout, readingOrder, normalize, languages, formats, keepImageAlpha, decryptSearchablePdf } = req.body as ProcessOptions;
 
         if (!uploadExists(`${id}.pdf`)) {
             throw new Error('File not found');
@@ -126,9 +128,24 @@ app.post('/process/:id', async (req: Request, res: Response) => {
             file: `${id}.pdf`,
             start_page: startPage,
             page_count: pageCount,
+            pages,
             format: 'text',
             engine,
-            model
+            model,
+            password,
+            render_mode: renderMode,
+            render_dpi: renderDpi,
+            ocr_mode: ocrMode,
+            page_concurrency: pageConcurrency,
+            process_attachments: processAttachments,
+            attachment_depth: attachmentDepth,
+            layout,
+            reading_order: readingOrder,
+            normalize,
+            languages,
+            formats,
+            keep_image_alpha: keepImageAlpha,
+            decrypt_searchable_pdf: decryptSearchablePdf
         });
 
         if (!d) {
@@ -163,13 +180,18 @@ app.get('/progress/:id', async (req: Request, res: Response) => {
         }
 
         const progress = await fileProcessingService.getFileProgress(id);
-        const status = await fileProcessingService.isFileInProcessing(id) ? 'processing' : 'completed';
+        const fileStatus = await fileProcessingService.getFileStatus(id);
+        const status = fileStatusToStatus(fileStatus);
+        const error = status === 'completed' || status === 'processing'
+            ? undefined
+            : await fileProcessingService.getFileError(id) ?? undefined;
 
         ResponseHelper.success<ProgressResponse>({
             id,
             progress: progress ?? 0,
             status,
-            message: 'Progress retrieved successfully'
+            message: 'Progress retrieved successfully',
+            error
         });
     } catch (error) {
         ResponseHelper.error(
@@ -206,6 +228,40 @@ app.get('/content/:id', async (req: Request, res: Response) => {
     }
 });
 
+app.get('/content/:id/markdown', async (req: Request, res: Response) => {
+    try {
+        const { id } = req.params;
+
+        if (!processedExists(`${id}.md`)) {
+            throw new Error('Processed Markdown not found, process the file with "markdown" in formats');
+        }
+
+        res.type('text/markdown').send(fs.readFileSync(getProcessedFilePath(`${id}.md`), 'utf-8'));
+    } catch (error) {
+        ResponseHelper.error(
+            (error as Error).message ?? 'Failed to retrieve processed Markdown',
+            { message: (error as Error).message ?? 'Failed to retrieve processed Markdown' }
+        );
+    }
+});
+
+app.get('/content/:id/pdf', async (req: Request, res: Response) => {
+    try {
+        const { id } = req.params;
+
+        if (!processedExists(`${id}.pdf`)) {
+            throw new Error('Searchable PDF not found, process the file with "searchable_pdf" in formats');
+        }
+
+        res.type('application/pdf').sendFile(path.resolve(getProcessedFilePath(`${id}.pdf`)));
+    } catch (error) {
+        ResponseHelper.error(
+            (error as Error).message ?? 'Failed to retrieve searchable PDF',
+            { message: (error as Error).message ?? 'Failed to retrieve searchable PDF' }
+        );
+    }
+});
+
 app.post('/model/pull', async (req: Request, res: Response) => {
     try {
         let { model } = req.body;
@@ -323,6 +379,22 @@ app.get('/models', async (req: Request, res: Response) => {
     }
 });
 
+function fileStatusToStatus(fileStatus: FileStatus | null): Status {
+    switch (fileStatus) {
+        case FileStatus.PENDING:
+        case null:
+            return 'processing';
+        case FileStatus.FAILED:
+            return 'failed';
+        case FileStatus.PASSWORD_REQUIRED:
+            return 'password_required';
+        case FileStatus.WRONG_PASSWORD:
+            return 'wrong_password';
+        default:
+            return 'completed';
+    }
+}
+
 // Start server only after establishing connections
 async function startServer() {
     const isConnected = await initializeConnections();
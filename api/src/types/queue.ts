@@ -2,22 +2,189 @@
         file: string; // relative part to shared_storage
         start_page: number; // page number to start processing
         page_count: number; // number of pages to process use 0 for all
+        pages?: string; // page selection such as "1-3,7,10-", "odd" or "last 5", overrides start_page/page_count
         piority?: 0 | 1 | 2; // 0 - low, 1 - medium, 2 - high
         format: 'text' | 'json'; // output format
         engine: 'tesseract'| 'ollama'; // processing engine
         model?: string;
+        password?: string; // user password for encrypted PDFs
+        render_mode?: 'images' | 'page' | 'both'; // OCR embedded images, full page renders or both
+        render_dpi?: number; // resolution used for full page renders
+        ocr_mode?: 'always' | 'auto'; // auto only OCRs pages without a usable text layer
+        page_concurrency?: number; // pages of this document processed in parallel, defaults to the worker's PAGE_CONCURRENCY
+        process_attachments?: boolean; // also extract attached PDFs and images
+        attachment_depth?: number; // levels of nested attachments to process, 1 by default, at most 3
+        layout?: boolean; // keep blocks, lines and words with their bounding boxes
+        reading_order?: boolean; // order text by columns and blocks, true by default
+        normalize?: TextNormalization; // clean-up steps applied to text layer and OCR text
+        languages?: string; // OCR languages such as "deu+fra", detected per page when missing
+        formats?: OutputFormat[]; // files written to the processed directory, ['json'] by default
+        keep_image_alpha?: boolean; // also save embedded images that have transparency with their alpha channel
+        decrypt_searchable_pdf?: boolean; // allow the searchable PDF of an encrypted input, which is written decrypted
+    }
+
+    export type OutputFormat = 'json' | 'markdown' | 'hocr' | 'alto' | 'searchable_pdf';
+
+    export type TextNormalization = {
+        ligatures?: boolean; // expand ﬁ, ﬂ and the other latin ligatures
+        dehyphenate?: boolean; // join words hyphenated across line breaks
+        unicode?: 'nfc' | 'nfkc'; // unicode normalisation form
+        mojibake?: boolean; // repair UTF-8 that was decoded as Windows-1252
+        whitespace?: boolean; // collapse runs of spaces and blank lines
+    }
+
+    export type ProcessedFileImage = {
+        image_name: string,
+        kind: 'embedded' | 'page_render' | 'attachment',
+        text: string,
+        engine: string,
+        error: string | null,
+        languages: string | null, // traineddata the image was read with, such as "fra+eng"
+        hocr: string | null, // relative to SHARED_STORAGE_PATH, with the hocr format and Tesseract
+        alto: string | null, // relative to SHARED_STORAGE_PATH, with the alto format and Tesseract
+        rgba: string | null, // relative to SHARED_STORAGE_PATH, with keep_image_alpha and a transparent image
+    }
+
+    export type ProcessedFileLanguage = {
+        language: string, // ISO 639-3
+        script: string,
+        confidence: number, // 0 to 1
+        reliable: boolean,
+    }
+
+    export type ProcessedFileFormField = {
+        name: string, // fully qualified, parent names joined with dots
+        field_type: 'text' | 'checkbox' | 'radio' | 'push_button' | 'choice' | 'signature' | 'unknown',
+        value: string | null,
+        checked: boolean | null, // checkboxes and radio buttons only
+        page_num: number | null,
+    }
+
+    export type ProcessedFileAnnotation = {
+        kind: string, // annotation subtype in snake case, e.g. 'text', 'highlight', 'stamp', 'link'
+        contents: string | null,
+        author: string | null,
+        covered_text: string | null, // text under highlight, underline, strike out and squiggly marks
+        stamp: string | null,
+        link: { uri: string | null, page_num: number | null } | null,
+        rect: [number, number, number, number] | null,
+    }
+
+    // [x0, y0, x1, y1] in PDF user space, points from the bottom left of the page
+    export type ProcessedFileBox = [number, number, number, number];
+
+    export type ProcessedFileWord = {
+        text: string,
+        bbox: ProcessedFileBox,
+        font_name: string | null, // text layer only, without the subset tag
+        font_size: number | null,
+        confidence: number | null, // OCR only, 0 to 100
+    }
+
+    export type ProcessedFileBlock = {
+        bbox: ProcessedFileBox,
+        source: 'text_layer' | 'ocr',
+        lines: { bbox: ProcessedFileBox, words: ProcessedFileWord[] }[],
     }
 
     export type ProcessedFilePage = {
         page_num: number,
         text: String, 
+        text_quality: {
+            char_count: number,
+            printable_ratio: number,
+            alphanumeric_ratio: number,
+            garbage_ratio: number,
+        },
+        source: 'text_layer' | 'ocr' | 'merged' | null,
+        images: ProcessedFileImage[],
+        full_text: string,
+        sections: string[], // outline titles from chapter down to the innermost section
+        form_fields: ProcessedFileFormField[],
+        annotations: ProcessedFileAnnotation[],
+        header: string | null, // lines repeated at the top of most pages, removed from text and full_text
+        footer: string | null, // lines repeated at the bottom of most pages, such as page numbers
+        layout: { blocks: ProcessedFileBlock[] } | null, // only with the layout option
+        language: ProcessedFileLanguage | null, // null when there is too little text to tell
+    }
+
+    export type ProcessedFileLoader = {
+        loader: 'lopdf' | 'lopdf_repaired' | 'pdfium', // which loader managed to open the file
+        repairs: string[], // what was fixed to make the file readable
+        errors: string[], // why the loaders tried before it failed
+    }
+
+    export type ProcessedFileEncryption = {
+        filter: string | null,
+        version: number | null,
+        revision: number | null,
+        key_length: number | null,
+        encrypt_metadata: boolean | null,
+        permissions: {
+            print: boolean,
+            print_high_quality: boolean,
+            modify: boolean,
+            copy: boolean,
+            annotate: boolean,
+            fill_forms: boolean,
+            extract_for_accessibility: boolean,
+            assemble: boolean,
+        },
+    }
+
+    export type ProcessedFileMetadata = {
+        pdf_version: string | null,
+        page_count: number,
+        title: string | null,
+        author: string | null,
+        subject: string | null,
+        keywords: string | null,
+        creator: string | null,
+        producer: string | null,
+        creation_date: string | null, // RFC 3339 when parseable, raw PDF date otherwise
+        modification_date: string | null,
+        xmp: string | null, // raw XMP packet
+        encryption: ProcessedFileEncryption | null,
+        pages: {
+            page_num: number,
+            width: number, // points
+            height: number,
+            rotation: number,
+            has_text_layer: boolean,
+        }[],
+    }
+
+    export type ProcessedFileOutlineItem = {
+        title: string,
+        page_num: number | null,
+        children: ProcessedFileOutlineItem[],
+    }
+
+    export type ProcessedFileAttachment = {
+        name: string,
+        description: string | null,
+        mime_type: string | null,
+        kind: 'pdf' | 'image' | 'other',
+        source: 'embedded_files' | 'annotation',
+        page_num: number | null, // page of the file attachment annotation
+        size: number,
+        saved_as: string | null, // relative to SHARED_STORAGE_PATH
+        document: ProcessedFile | null, // processed PDF attachments
+        image: ProcessedFileImage | null, // processed image attachments
+        error: string | null,
     }
 
-    export type ProcessedFile = ProcessedFilePage[];
+    export type ProcessedFile = {
+        loader: ProcessedFileLoader,
+        metadata: ProcessedFileMetadata,
+        outline: ProcessedFileOutlineItem[],
+        pages: ProcessedFilePage[],
+        attachments: ProcessedFileAttachment[],
+    };
 
 
 
-    export type Status = 'queued' | 'processing' | 'completed' | 'failed'
+    export type Status = 'queued' | 'processing' | 'completed' | 'failed' | 'password_required' | 'wrong_password'
 
 
 
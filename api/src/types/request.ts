@@ -1,7 +1,28 @@
 export interface ProcessOptions {
     startPage?: number;
     pageCount?: number;
+    pages?: string;
     priority?: 0 | 1 | 2;
     engine: 'tesseract' | 'ollama', 
     model ?: string,
+    password?: string,
+    renderMode?: 'images' | 'page' | 'both',
+    renderDpi?: number,
+    ocrMode?: 'always' | 'auto',
+    pageConcurrency?: number,
+    processAttachments?: boolean,
+    attachmentDepth?: number,
+    layout?: boolean,
+    readingOrder?: boolean,
+    normalize?: {
+        ligatures?: boolean,
+        dehyphenate?: boolean,
+        unicode?: 'nfc' | 'nfkc',
+        mojibake?: boolean,
+        whitespace?: boolean,
+    },
+    languages?: string,
+    formats?: ('json' | 'markdown' | 'hocr' | 'alto' | 'searchable_pdf')[],
+    keepImageAlpha?: boolean,
+    decryptSearchablePdf?: boolean,
 }
\ No newline at end of file
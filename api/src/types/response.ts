@@ -40,6 +40,7 @@ export interface ProgressResponse {
     progress: number;
     status: Status;
     message?: string;
+    error?: string;
 }
 
 export interface FinalResponse {
@@ -4,7 +4,7 @@ use futures_lite::StreamExt;
 use ollama_rs::{models::pull::PullModelStatusStream, Ollama};
 use tokio::sync::Semaphore;
 
-use crate::{libs::redis::{get_redis_client, mark_model_as_completed, mark_model_as_failed, update_model_progress}, types::engine_handler::Engines, worker::{NewFileProcessQueue, OllamaModelPull}};
+use crate::{helper::file_helper, libs::redis::{get_redis_client, mark_model_as_completed, mark_model_as_failed, update_model_progress}, types::engine_handler::Engines, worker::{NewFileProcessQueue, OllamaModelPull}};
 
 pub struct RabbitMQFileProcessor {
     rabbit_mq_conn: Connection,
@@ -42,6 +42,15 @@ impl RabbitMQFileProcessor {
 
         let semaphore = Arc::new(Semaphore::new(5));
 
+        // jobs are acked on receipt, so anything interrupted by a crash or restart has to be
+        // picked up from its checkpoint instead of waiting for a redelivery
+        for message in file_helper::list_interrupted_jobs() {
+            if let Some(engine) = Engines::from(message.engine.as_str()) {
+                println!("Resuming interrupted job for {}", message.file);
+                engine.handle(message, &semaphore).await;
+            }
+        }
+
         for message in file_extract_queue_consumer.receiver().iter() {
             match message {
                 ConsumerMessage::Delivery(delivery) => {
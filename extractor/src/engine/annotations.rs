@@ -0,0 +1,150 @@
+use std::{collections::HashMap, path::Path};
+
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use pdfium_render::prelude::{PdfDocument, PdfRect};
+
+use crate::engine::{outline, render};
+
+// Comments, text markup, stamps and links placed on top of the page content. Widgets are left to
+// the form field extraction and popups only repeat the contents of the annotation they belong to.
+
+const SKIPPED_SUBTYPES: [&[u8]; 2] = [b"Widget", b"Popup"];
+const TEXT_MARKUP_SUBTYPES: [&[u8]; 4] = [b"Highlight", b"Underline", b"StrikeOut", b"Squiggly"];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Annotation {
+    // the annotation subtype in snake case, for example `highlight`, `free_text` or `link`
+    pub kind: String,
+    pub contents: Option<String>,
+    pub author: Option<String>,
+    // text under the quads of highlight, underline, strike out and squiggly annotations
+    pub covered_text: Option<String>,
+    // icon name of stamps, such as `Approved` or `Confidential`
+    pub stamp: Option<String>,
+    pub link: Option<LinkTarget>,
+    // [left, bottom, right, top] in points
+    pub rect: Option<[f32; 4]>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LinkTarget {
+    pub uri: Option<String>,
+    pub page_num: Option<u32>,
+}
+
+pub fn read_annotations(doc: &Document, pdf_path: &Path, password: Option<&str>, page_nums: &[u32]) -> HashMap<u32, Vec<Annotation>> {
+    let pages = doc.get_pages();
+    let page_numbers = outline::page_numbers(doc);
+    // pdfium is only needed to find the text under markup annotations
+    let mut pdfium_document: Option<Option<PdfDocument>> = None;
+    let mut by_page = HashMap::new();
+
+    for page_num in page_nums {
+        let Some(page_id) = pages.get(page_num) else {
+            continue;
+        };
+        let mut annotations = Vec::new();
+        for annotation in page_annotations(doc, *page_id) {
+            let subtype = annotation.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+            if SKIPPED_SUBTYPES.contains(&subtype) {
+                continue;
+            }
+
+            let covered_text = if TEXT_MARKUP_SUBTYPES.contains(&subtype) {
+                let document = pdfium_document.get_or_insert_with(|| render::open_document(pdf_path, password)
+                    .map_err(|e| println!("Failed to open {:?} with pdfium for annotation text: {}", pdf_path, e))
+                    .ok());
+                document.as_ref().and_then(|document| covered_text(document, *page_num, annotation))
+            } else {
+                None
+            };
+
+            annotations.push(Annotation {
+                kind: snake_case(subtype),
+                contents: text_entry(doc, annotation, b"Contents"),
+                author: text_entry(doc, annotation, b"T"),
+                covered_text,
+                stamp: (subtype == b"Stamp")
+                    .then(|| annotation.get(b"Name").and_then(Object::as_name_str).ok().map(str::to_string))
+                    .flatten(),
+                link: (subtype == b"Link").then(|| link_target(doc, annotation, &page_numbers)),
+                rect: numbers(annotation.get(b"Rect").ok()).and_then(|rect| rect.try_into().ok()),
+            });
+        }
+        if !annotations.is_empty() {
+            by_page.insert(*page_num, annotations);
+        }
+    }
+    by_page
+}
+
+fn page_annotations(doc: &Document, page_id: ObjectId) -> Vec<&Dictionary> {
+    doc.get_dictionary(page_id).ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .and_then(|annotations| doc.dereference(annotations).ok())
+        .and_then(|(_, annotations)| annotations.as_array().ok())
+        .map(|annotations| annotations.iter()
+            .filter_map(|annotation| doc.dereference(annotation).ok())
+            .filter_map(|(_, annotation)| annotation.as_dict().ok())
+            .collect())
+        .unwrap_or_default()
+}
+
+fn text_entry(doc: &Document, annotation: &Dictionary, key: &[u8]) -> Option<String> {
+    let (_, value) = doc.dereference(annotation.get(key).ok()?).ok()?;
+    Some(decode_text_string(value).ok()?.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn link_target(doc: &Document, annotation: &Dictionary, page_numbers: &HashMap<ObjectId, u32>) -> LinkTarget {
+    let uri = annotation.get(b"A").ok()
+        .and_then(|action| doc.dereference(action).ok())
+        .and_then(|(_, action)| action.as_dict().ok())
+        .filter(|action| action.get(b"S").and_then(Object::as_name).is_ok_and(|kind| kind == b"URI"))
+        .and_then(|action| action.get(b"URI").ok())
+        .and_then(|uri| doc.dereference(uri).ok())
+        .and_then(|(_, uri)| uri.as_str().ok())
+        .map(|uri| String::from_utf8_lossy(uri).into_owned());
+
+    LinkTarget {
+        uri,
+        page_num: outline::item_target(doc, annotation, page_numbers),
+    }
+}
+
+// QuadPoints hold four corners per marked run of text, which can span several lines
+fn covered_text(document: &PdfDocument, page_num: u32, annotation: &Dictionary) -> Option<String> {
+    let quads = numbers(annotation.get(b"QuadPoints").ok())
+        .or_else(|| numbers(annotation.get(b"Rect").ok()).map(|rect| {
+            vec![rect[0], rect[3], rect[2], rect[3], rect[0], rect[1], rect[2], rect[1]]
+        }))?;
+    let page = document.pages().get(u16::try_from(page_num.checked_sub(1)?).ok()?).ok()?;
+    let text = page.text().ok()?;
+
+    let parts: Vec<String> = quads.chunks_exact(8)
+        .map(|quad| {
+            let xs = [quad[0], quad[2], quad[4], quad[6]];
+            let ys = [quad[1], quad[3], quad[5], quad[7]];
+            let min = |values: [f32; 4]| values.into_iter().fold(f32::MAX, f32::min);
+            let max = |values: [f32; 4]| values.into_iter().fold(f32::MIN, f32::max);
+            text.inside_rect(PdfRect::new_from_values(min(ys), min(xs), max(ys), max(xs)))
+        })
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect();
+    Some(parts.join(" ")).filter(|text| !text.is_empty())
+}
+
+fn numbers(value: Option<&Object>) -> Option<Vec<f32>> {
+    value?.as_array().ok()?.iter().map(|n| n.as_float().ok()).collect()
+}
+
+fn snake_case(subtype: &[u8]) -> String {
+    let mut kind = String::new();
+    for (i, c) in String::from_utf8_lossy(subtype).chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kind.push('_');
+        }
+        kind.push(c.to_ascii_lowercase());
+    }
+    kind
+}
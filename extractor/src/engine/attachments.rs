@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+
+// Files embedded in a PDF, either through the /EmbeddedFiles name tree of the catalog or through
+// file attachment annotations on a page. Both point at a file specification whose /EF entry
+// holds the embedded file stream.
+
+const MAX_NAME_TREE_DEPTH: usize = 32;
+// how far into an attachment to look for the %PDF header, like the loader's repair step
+const PDF_HEADER_SEARCH_LIMIT: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentSource {
+    EmbeddedFiles,
+    Annotation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Pdf,
+    Image,
+    Other,
+}
+
+pub struct EmbeddedFile {
+    pub name: String,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+    pub source: AttachmentSource,
+    pub page_num: Option<u32>,
+    pub data: Result<Vec<u8>, String>,
+}
+
+impl EmbeddedFile {
+    pub fn kind(&self) -> AttachmentKind {
+        let Ok(data) = &self.data else {
+            return AttachmentKind::Other;
+        };
+        let header_area = &data[..data.len().min(PDF_HEADER_SEARCH_LIMIT)];
+        if header_area.windows(5).any(|window| window == b"%PDF-") {
+            AttachmentKind::Pdf
+        } else if image::guess_format(data).is_ok() {
+            AttachmentKind::Image
+        } else {
+            AttachmentKind::Other
+        }
+    }
+
+    // only the final path component, with anything unsafe in a file name replaced
+    pub fn file_name(&self) -> String {
+        let base = self.name.rsplit(['/', '\\']).next().unwrap_or("");
+        let cleaned: String = base.chars()
+            .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+            .collect();
+        match cleaned.trim_matches('.') {
+            "" => "attachment".to_string(),
+            cleaned => cleaned.to_string(),
+        }
+    }
+}
+
+pub fn list_attachments(doc: &Document) -> Vec<EmbeddedFile> {
+    // the same file specification can be reachable both ways, list it once
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+
+    let mut entries = Vec::new();
+    if let Some(tree) = embedded_files_tree(doc) {
+        name_tree_entries(doc, tree, &mut entries, 0);
+    }
+    for (key, spec) in entries {
+        if let Some(file) = read_file_spec(doc, spec, Some(key), AttachmentSource::EmbeddedFiles, None, &mut seen) {
+            files.push(file);
+        }
+    }
+
+    for (page_num, page_id) in doc.get_pages() {
+        for annotation in page_file_attachments(doc, page_id) {
+            if let Ok(spec) = annotation.get(b"FS") {
+                if let Some(file) = read_file_spec(doc, spec, None, AttachmentSource::Annotation, Some(page_num), &mut seen) {
+                    files.push(file);
+                }
+            }
+        }
+    }
+    files
+}
+
+fn embedded_files_tree(doc: &Document) -> Option<&Dictionary> {
+    let (_, names) = doc.dereference(doc.catalog().ok()?.get(b"Names").ok()?).ok()?;
+    let (_, tree) = doc.dereference(names.as_dict().ok()?.get(b"EmbeddedFiles").ok()?).ok()?;
+    tree.as_dict().ok()
+}
+
+fn name_tree_entries<'a>(doc: &'a Document, node: &'a Dictionary, entries: &mut Vec<(String, &'a Object)>, depth: usize) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks_exact(2) {
+            let key = decode_text_string(&pair[0]).unwrap_or_default();
+            entries.push((key, &pair[1]));
+        }
+    }
+    for kid in node.get(b"Kids").and_then(Object::as_array).into_iter().flatten() {
+        if let Some(kid) = doc.dereference(kid).ok().and_then(|(_, kid)| kid.as_dict().ok()) {
+            name_tree_entries(doc, kid, entries, depth + 1);
+        }
+    }
+}
+
+fn page_file_attachments(doc: &Document, page_id: ObjectId) -> Vec<&Dictionary> {
+    doc.get_dictionary(page_id).ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .and_then(|annotations| doc.dereference(annotations).ok())
+        .and_then(|(_, annotations)| annotations.as_array().ok())
+        .map(|annotations| annotations.iter()
+            .filter_map(|annotation| doc.dereference(annotation).ok())
+            .filter_map(|(_, annotation)| annotation.as_dict().ok())
+            .filter(|annotation| annotation.get(b"Subtype").and_then(Object::as_name).is_ok_and(|subtype| subtype == b"FileAttachment"))
+            .collect())
+        .unwrap_or_default()
+}
+
+fn read_file_spec(
+    doc: &Document,
+    spec: &Object,
+    key: Option<String>,
+    source: AttachmentSource,
+    page_num: Option<u32>,
+    seen: &mut HashSet<ObjectId>,
+) -> Option<EmbeddedFile> {
+    let (_, spec) = doc.dereference(spec).ok()?;
+    let spec = spec.as_dict().ok()?;
+    let embedded = spec.get(b"EF").ok()
+        .and_then(|ef| doc.dereference(ef).ok())
+        .and_then(|(_, ef)| ef.as_dict().ok())?;
+    // /UF is the unicode file name, /F the older byte string one
+    let stream_ref = embedded.get(b"UF").or_else(|_| embedded.get(b"F")).ok()?;
+    if let Ok(stream_id) = stream_ref.as_reference() {
+        if !seen.insert(stream_id) {
+            return None;
+        }
+    }
+    let (_, stream) = doc.dereference(stream_ref).ok()?;
+    let stream = stream.as_stream().ok()?;
+
+    let text = |key: &[u8]| spec.get(key).ok()
+        .and_then(|value| decode_text_string(value).ok())
+        .filter(|value| !value.trim().is_empty());
+    let name = text(b"UF").or_else(|| text(b"F")).or(key.filter(|key| !key.is_empty()))
+        .unwrap_or_else(|| "attachment".to_string());
+    let data = if stream.dict.has(b"Filter") {
+        stream.decompressed_content().map_err(|e| format!("Failed to decode embedded file: {}", e))
+    } else {
+        Ok(stream.content.clone())
+    };
+
+    Some(EmbeddedFile {
+        name,
+        description: text(b"Desc"),
+        // /Subtype is a name such as application#2Fpdf, lopdf already decoded the #2F
+        mime_type: stream.dict.get(b"Subtype").and_then(Object::as_name_str).ok().map(str::to_string),
+        source,
+        page_num,
+        data,
+    })
+}
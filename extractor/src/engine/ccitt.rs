@@ -0,0 +1,325 @@
+use fax::{maps::{black, mode, white, Mode}, BitReader};
+use lopdf::{Dictionary, Object};
+
+// CCITTFaxDecode: Group 3 (one- or two-dimensional) and Group 4 fax data turned into rows of 1 bit
+// samples. The code tables come from the fax crate, the line decoding is done here since PDF
+// streams often lack the EOL markers the crate's decoders need, mix 1-D and 2-D lines or pad
+// every line to a byte boundary.
+
+// the length of an EOL code, 11 zeros followed by a one
+const EOL_LENGTH: u8 = 12;
+
+pub struct CcittParams {
+    // < 0 Group 4, 0 Group 3 1-D, > 0 Group 3 mixed 1-D and 2-D
+    pub k: i64,
+    pub columns: usize,
+    // 0 when the stream does not say, decoding then runs until the data ends
+    pub rows: usize,
+    pub black_is_1: bool,
+    pub encoded_byte_align: bool,
+}
+
+impl CcittParams {
+    pub fn from_dict(params: Option<&Dictionary>) -> Self {
+        let int = |key: &[u8], default: i64| params.and_then(|params| params.get(key).and_then(Object::as_i64).ok()).unwrap_or(default);
+        let flag = |key: &[u8]| params.and_then(|params| params.get(key).and_then(Object::as_bool).ok()).unwrap_or(false);
+        CcittParams {
+            k: int(b"K", 0),
+            columns: int(b"Columns", 1728).max(1) as usize,
+            rows: int(b"Rows", 0).max(0) as usize,
+            black_is_1: flag(b"BlackIs1"),
+            encoded_byte_align: flag(b"EncodedByteAlign"),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader for Reader<'_> {
+    type Error = ();
+
+    // codes near the end of the data are looked up with zeros after the last byte
+    fn peek(&self, bits: u8) -> Option<u16> {
+        if bits > 16 || self.at_end() {
+            return None;
+        }
+        let mut value = 0u16;
+        for bit in self.position..self.position + bits as usize {
+            let set = self.data.get(bit / 8).is_some_and(|byte| byte >> (7 - bit % 8) & 1 == 1);
+            value = value << 1 | set as u16;
+        }
+        Some(value)
+    }
+
+    fn consume(&mut self, bits: u8) -> Result<(), ()> {
+        self.position += bits as usize;
+        Ok(())
+    }
+
+    fn bits_to_byte_boundary(&self) -> u8 {
+        ((8 - self.position % 8) % 8) as u8
+    }
+}
+
+impl Reader<'_> {
+    fn at_end(&self) -> bool {
+        self.position >= self.data.len() * 8
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+
+    // skips an EOL and the zero fill bits before it, false when there is none
+    fn skip_eol(&mut self) -> bool {
+        let start = self.position;
+        while self.peek(1) == Some(0) {
+            self.position += 1;
+        }
+        if self.position - start >= EOL_LENGTH as usize - 1 && self.peek(1) == Some(1) {
+            self.position += 1;
+            return true;
+        }
+        self.position = start;
+        false
+    }
+
+    // in mixed Group 3 data every line starts with a bit that is 0 for a 2-D line
+    fn read_2d_tag(&mut self) -> bool {
+        let tag = self.peek(1) == Some(0);
+        self.position += 1;
+        tag
+    }
+}
+
+// rows of `columns` pixels packed 8 to a byte, black as 0 unless BlackIs1 is set
+pub fn decode(data: &[u8], params: &CcittParams, max_rows: usize) -> Result<Vec<u8>, String> {
+    let columns = params.columns;
+    let row_length = columns.div_ceil(8);
+    let rows = if params.rows > 0 { params.rows } else { max_rows };
+    let mut reader = Reader { data, position: 0 };
+    let mut output = Vec::with_capacity(row_length * rows);
+    // the line above the first one is white
+    let mut reference: Vec<usize> = Vec::new();
+
+    while output.len() / row_length < rows && !reader.at_end() {
+        if params.encoded_byte_align && params.k < 0 {
+            reader.align();
+        }
+        let two_dimensional = if params.k < 0 {
+            true
+        } else {
+            // fill bits go before the EOL when there is one, else before the line itself
+            let eol = reader.skip_eol();
+            // a second EOL straight after the first is the end of the page
+            if eol && reader.skip_eol() {
+                break;
+            }
+            if !eol && params.encoded_byte_align {
+                reader.align();
+            }
+            params.k > 0 && reader.read_2d_tag()
+        };
+
+        let line = if two_dimensional {
+            decode_2d_line(&mut reader, &reference, columns)
+        } else {
+            decode_1d_line(&mut reader, columns)
+        };
+        let Some(line) = line else {
+            // end of block, or data too broken to go on with; what was read so far is kept
+            break;
+        };
+        output.extend(pack_row(&line, columns, params.black_is_1));
+        reference = line;
+    }
+
+    if output.is_empty() {
+        return Err("Failed to decode CCITT fax data".to_string());
+    }
+    // rows missing from a truncated stream are left white
+    output.resize(row_length * rows.max(output.len() / row_length), white_byte(params.black_is_1));
+    Ok(output)
+}
+
+// a run of one colour, made up of any make-up codes and the terminating code below 64
+fn run_length(reader: &mut Reader, white_run: bool) -> Option<usize> {
+    let mut length = 0;
+    loop {
+        let code = if white_run { white::decode(reader)? } else { black::decode(reader)? } as usize;
+        length += code;
+        if code < 64 {
+            return Some(length);
+        }
+    }
+}
+
+// lines are kept as the positions where the colour changes, starting from white
+fn decode_1d_line(reader: &mut Reader, columns: usize) -> Option<Vec<usize>> {
+    let mut changes = Vec::new();
+    let mut position = 0;
+    let mut white_run = true;
+    while position < columns {
+        position = (position + run_length(reader, white_run)?).min(columns);
+        changes.push(position);
+        white_run = !white_run;
+    }
+    Some(changes)
+}
+
+fn decode_2d_line(reader: &mut Reader, reference: &[usize], columns: usize) -> Option<Vec<usize>> {
+    let mut changes: Vec<usize> = Vec::new();
+    // a0 starts just before the first pixel
+    let mut a0: Option<usize> = None;
+    let mut white = true;
+
+    while a0.map_or(true, |a0| a0 < columns) {
+        // b1 is the first change on the reference line right of a0 to the colour opposite a0's,
+        // changes to black sit at even indexes
+        let mut index = if white { 0 } else { 1 };
+        while index < reference.len() && a0.is_some_and(|a0| reference[index] <= a0) {
+            index += 2;
+        }
+        let b1 = reference.get(index).copied().unwrap_or(columns);
+        let b2 = reference.get(index + 1).copied().unwrap_or(columns);
+
+        match mode::decode(reader)? {
+            Mode::Pass => a0 = Some(b2),
+            Mode::Horizontal => {
+                let start = a0.unwrap_or(0);
+                let a1 = (start + run_length(reader, white)?).min(columns);
+                let a2 = (a1 + run_length(reader, !white)?).min(columns);
+                changes.extend([a1, a2]);
+                a0 = Some(a2);
+            }
+            Mode::Vertical(delta) => {
+                let a1 = (b1 as isize + delta as isize).clamp(0, columns as isize) as usize;
+                changes.push(a1);
+                a0 = Some(a1);
+                white = !white;
+            }
+            // an EOL where a line should start ends the block
+            Mode::EOF | Mode::Extension => return None,
+        }
+    }
+    Some(changes)
+}
+
+fn pack_row(changes: &[usize], columns: usize, black_is_1: bool) -> Vec<u8> {
+    let mut row = vec![white_byte(black_is_1); columns.div_ceil(8)];
+    let mut paint = |pixels: std::ops::Range<usize>| {
+        for pixel in pixels {
+            row[pixel / 8] ^= 0x80 >> (pixel % 8);
+        }
+    };
+    let mut start = 0;
+    for (index, end) in changes.iter().enumerate() {
+        let end = (*end).min(columns);
+        // every second run is black
+        if index % 2 == 1 {
+            paint(start..end);
+        }
+        start = start.max(end);
+    }
+    // after an odd number of changes the line ends in black
+    if changes.len() % 2 == 1 {
+        paint(start..columns);
+    }
+    row
+}
+
+fn white_byte(black_is_1: bool) -> u8 {
+    if black_is_1 { 0 } else { 0xFF }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fax::{encoder::Encoder, Color, VecWriter};
+
+    const EOL: &str = "000000000001";
+
+    // a string of 0s and 1s packed into bytes, the last one filled with zeros
+    fn bits(text: &str) -> Vec<u8> {
+        let bits: Vec<u8> = text.bytes().filter(|c| *c != b' ').map(|c| c - b'0').collect();
+        bits.chunks(8)
+            .map(|chunk| chunk.iter().enumerate().fold(0, |byte, (i, bit)| byte | bit << (7 - i)))
+            .collect()
+    }
+
+    fn params(k: i64, columns: usize, rows: usize) -> CcittParams {
+        CcittParams { k, columns, rows, black_is_1: false, encoded_byte_align: false }
+    }
+
+    #[test]
+    fn reads_the_defaults() {
+        let defaults = CcittParams::from_dict(None);
+        assert_eq!((defaults.k, defaults.columns, defaults.rows), (0, 1728, 0));
+        assert!(!defaults.black_is_1 && !defaults.encoded_byte_align);
+
+        let mut dict = Dictionary::new();
+        dict.set("K", -1);
+        dict.set("Columns", 0);
+        dict.set("Rows", -5);
+        dict.set("BlackIs1", true);
+        let params = CcittParams::from_dict(Some(&dict));
+        assert_eq!((params.k, params.columns, params.rows), (-1, 1, 0));
+        assert!(params.black_is_1);
+    }
+
+    #[test]
+    fn decodes_group_3_lines_with_eols() {
+        // white 8 then black 8, twice, then the end of the page
+        let line = format!("{} 10011 000101", EOL);
+        let data = bits(&format!("{}{}{}{}", line, line, EOL, EOL));
+        assert_eq!(decode(&data, &params(0, 16, 0), 2).unwrap(), vec![0xFF, 0x00, 0xFF, 0x00]);
+
+        let mut black_is_1 = params(0, 16, 0);
+        black_is_1.black_is_1 = true;
+        assert_eq!(decode(&data, &black_is_1, 2).unwrap(), vec![0x00, 0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn decodes_group_3_lines_without_eols() {
+        // a white row of 16, then white 8 and black 8
+        let data = bits("101010 10011 000101");
+        assert_eq!(decode(&data, &params(0, 16, 2), 10).unwrap(), vec![0xFF, 0xFF, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn leaves_missing_rows_white() {
+        let data = bits(&format!("{} 10011 000101", EOL));
+        assert_eq!(decode(&data, &params(0, 16, 3), 10).unwrap(), vec![0xFF, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn decodes_group_4() {
+        let columns = 20;
+        let rows: [&[(usize, usize)]; 4] = [&[], &[(3, 9)], &[(4, 10), (15, 20)], &[(0, 20)]];
+        let mut encoder = Encoder::new(VecWriter::new());
+        let mut expected = Vec::new();
+        for runs in rows {
+            let is_black = |pixel: usize| runs.iter().any(|(start, end)| (*start..*end).contains(&pixel));
+            encoder.encode_line((0..columns).map(|pixel| if is_black(pixel) { Color::Black } else { Color::White }), columns as u16).unwrap();
+            let changes: Vec<usize> = runs.iter().flat_map(|(start, end)| [*start, *end]).collect();
+            expected.extend(pack_row(&changes, columns, false));
+        }
+        let data = encoder.finish().unwrap().finish();
+
+        assert_eq!(decode(&data, &params(-1, columns, 0), 4).unwrap(), expected);
+        assert_eq!(&expected[3..6], &[0xE0, 0x7F, 0xFF]);
+        // without /Rows the end of block stops decoding and the rest of the image stays white
+        let padded = decode(&data, &params(-1, columns, 0), 6).unwrap();
+        assert_eq!(&padded[..12], &expected[..]);
+        assert!(padded[12..].iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn fails_on_garbage() {
+        assert!(decode(&[0x00, 0x00], &params(-1, 16, 0), 10).is_err());
+        assert!(decode(&[], &params(0, 16, 1), 10).is_err());
+    }
+}
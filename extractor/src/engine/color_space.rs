@@ -0,0 +1,227 @@
+use lopdf::{Document, Object};
+
+use crate::engine::pdf_function::Function;
+
+// Colour spaces of image samples, turned into RGB for saving. Values come in already mapped
+// through the image's Decode array: 0 to 1 for most spaces, the palette index for Indexed and
+// L*a*b* values for Lab. ICC profiles are not applied, an ICCBased space is read as its
+// alternate or as the device space with the same number of components.
+
+const MAX_DEPTH: u32 = 8;
+
+pub enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    // [a_min, a_max, b_min, b_max]
+    Lab { range: [f32; 4] },
+    Indexed { base: Box<ColorSpace>, hival: u32, palette: Vec<u8> },
+    // Separation and DeviceN, the tints of their colourants turned into the alternate space
+    DeviceN { inks: usize, alternate: Box<ColorSpace>, tint_transform: Option<Function> },
+}
+
+impl ColorSpace {
+    pub fn parse(doc: &Document, object: &Object) -> Result<ColorSpace, String> {
+        Self::parse_nested(doc, object, 0)
+    }
+
+    fn parse_nested(doc: &Document, object: &Object, depth: u32) -> Result<ColorSpace, String> {
+        if depth > MAX_DEPTH {
+            return Err("Colour spaces nested too deep".to_string());
+        }
+        let (_, object) = doc.dereference(object).map_err(|e| e.to_string())?;
+        let (family, params) = match object {
+            Object::Name(name) => (name.as_slice(), &[][..]),
+            Object::Array(array) => match array.split_first() {
+                Some((Object::Name(name), params)) => (name.as_slice(), params),
+                _ => return Err("Malformed colour space".to_string()),
+            },
+            _ => return Err("Malformed colour space".to_string()),
+        };
+
+        match family {
+            b"DeviceGray" | b"G" | b"CalGray" => Ok(ColorSpace::Gray),
+            b"DeviceRGB" | b"RGB" | b"CalRGB" => Ok(ColorSpace::Rgb),
+            b"DeviceCMYK" | b"CMYK" => Ok(ColorSpace::Cmyk),
+            b"Lab" => {
+                let range = params.first()
+                    .and_then(|dict| doc.dereference(dict).ok())
+                    .and_then(|(_, dict)| dict.as_dict().ok())
+                    .and_then(|dict| dict.get(b"Range").ok())
+                    .and_then(|range| range.as_array().ok())
+                    .map(|range| range.iter().filter_map(number).collect::<Vec<f32>>())
+                    .and_then(|range| range.try_into().ok())
+                    .unwrap_or([-100.0, 100.0, -100.0, 100.0]);
+                Ok(ColorSpace::Lab { range })
+            }
+            b"ICCBased" => {
+                let stream = params.first()
+                    .and_then(|stream| doc.dereference(stream).ok())
+                    .and_then(|(_, stream)| stream.as_stream().ok())
+                    .ok_or("ICCBased colour space without a profile")?;
+                if let Some(alternate) = stream.dict.get(b"Alternate").ok().and_then(|alternate| Self::parse_nested(doc, alternate, depth + 1).ok()) {
+                    return Ok(alternate);
+                }
+                let components = stream.dict.get(b"N").and_then(Object::as_i64).unwrap_or(0);
+                Self::from_components(components as usize).ok_or_else(|| format!("ICCBased colour space with {} components", components))
+            }
+            b"Indexed" | b"I" => {
+                let [base, hival, lookup] = params else {
+                    return Err("Malformed Indexed colour space".to_string());
+                };
+                let base = Self::parse_nested(doc, base, depth + 1)?;
+                let hival = doc.dereference(hival).and_then(|(_, hival)| hival.as_i64()).map_err(|e| e.to_string())?.clamp(0, 255) as u32;
+                let palette = match doc.dereference(lookup).map_err(|e| e.to_string())?.1 {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()),
+                    _ => return Err("Indexed colour space without a palette".to_string()),
+                };
+                Ok(ColorSpace::Indexed { base: Box::new(base), hival, palette })
+            }
+            b"Separation" | b"DeviceN" => {
+                let [names, alternate, tint_transform, ..] = params else {
+                    return Err("Malformed Separation or DeviceN colour space".to_string());
+                };
+                let inks = match doc.dereference(names).map_err(|e| e.to_string())?.1 {
+                    Object::Array(names) => names.len().max(1),
+                    _ => 1,
+                };
+                let alternate = Self::parse_nested(doc, alternate, depth + 1)?;
+                // without a transform we can evaluate, the tints are shown as ink on white
+                let tint_transform = match Function::parse(doc, tint_transform) {
+                    Ok(function) => Some(function),
+                    Err(e) => {
+                        println!("Showing tints as gray, failed to read tint transform: {}", e);
+                        None
+                    }
+                };
+                Ok(ColorSpace::DeviceN { inks, alternate: Box::new(alternate), tint_transform })
+            }
+            other => Err(format!("Unsupported colour space {}", String::from_utf8_lossy(other))),
+        }
+    }
+
+    pub fn from_components(components: usize) -> Option<ColorSpace> {
+        match components {
+            1 => Some(ColorSpace::Gray),
+            3 => Some(ColorSpace::Rgb),
+            4 => Some(ColorSpace::Cmyk),
+            _ => None,
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb | ColorSpace::Lab { .. } => 3,
+            ColorSpace::Cmyk => 4,
+            ColorSpace::DeviceN { inks, .. } => *inks,
+        }
+    }
+
+    // what sample values map onto without a Decode array, per component
+    pub fn default_decode(&self, bits: u32) -> Vec<[f32; 2]> {
+        match self {
+            ColorSpace::Indexed { .. } => vec![[0.0, ((1u32 << bits) - 1) as f32]],
+            ColorSpace::Lab { range } => vec![[0.0, 100.0], [range[0], range[1]], [range[2], range[3]]],
+            _ => vec![[0.0, 1.0]; self.components()],
+        }
+    }
+
+    pub fn is_gray(&self) -> bool {
+        match self {
+            ColorSpace::Gray => true,
+            ColorSpace::Indexed { base, .. } => base.is_gray(),
+            ColorSpace::DeviceN { alternate, tint_transform, .. } => tint_transform.is_none() || alternate.is_gray(),
+            _ => false,
+        }
+    }
+
+    // RGB from 0 to 1
+    pub fn to_rgb(&self, values: &[f32]) -> [f32; 3] {
+        let value = |index: usize| values.get(index).copied().unwrap_or(0.0);
+        match self {
+            ColorSpace::Gray => [value(0); 3],
+            ColorSpace::Rgb => [value(0), value(1), value(2)],
+            ColorSpace::Cmyk => {
+                let black = 1.0 - value(3);
+                [(1.0 - value(0)) * black, (1.0 - value(1)) * black, (1.0 - value(2)) * black]
+            }
+            ColorSpace::Lab { .. } => lab_to_rgb(value(0), value(1), value(2)),
+            ColorSpace::Indexed { base, hival, palette } => {
+                let index = value(0).round().clamp(0.0, *hival as f32) as usize;
+                let components = base.components();
+                let decode = base.default_decode(8);
+                let entry: Vec<f32> = (0..components)
+                    .map(|component| {
+                        let byte = palette.get(index * components + component).copied().unwrap_or(0) as f32 / 255.0;
+                        decode[component][0] + byte * (decode[component][1] - decode[component][0])
+                    })
+                    .collect();
+                base.to_rgb(&entry)
+            }
+            ColorSpace::DeviceN { alternate, tint_transform: Some(function), .. } => alternate.to_rgb(&function.eval(values)),
+            ColorSpace::DeviceN { tint_transform: None, .. } => {
+                let ink = values.iter().copied().fold(0.0, f32::max).clamp(0.0, 1.0);
+                [1.0 - ink; 3]
+            }
+        }
+    }
+}
+
+fn number(object: &Object) -> Option<f32> {
+    object.as_float().ok().or_else(|| object.as_i64().ok().map(|value| value as f32))
+}
+
+// through CIE XYZ to sRGB, taking the white point to be that of sRGB
+fn lab_to_rgb(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let f = |t: f32| if t > 6.0 / 29.0 { t.powi(3) } else { 3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0) };
+    let fy = (l + 16.0) / 116.0;
+    let (x, y, z) = (0.9505 * f(fy + a / 500.0), f(fy), 1.089 * f(fy - b / 200.0));
+    let linear = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ];
+    linear.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::StringFormat;
+
+    fn indexed(base: &str, hival: i64, palette: &[u8]) -> ColorSpace {
+        let array = vec![Object::Name(b"Indexed".to_vec()), Object::Name(base.as_bytes().to_vec()), hival.into(), Object::String(palette.to_vec(), StringFormat::Hexadecimal)];
+        ColorSpace::parse(&Document::new(), &Object::Array(array)).unwrap()
+    }
+
+    #[test]
+    fn looks_up_indexed_colours_in_the_palette() {
+        let rgb = indexed("DeviceRGB", 2, &[255, 0, 0, 0, 255, 0, 0, 0, 255]);
+        assert_eq!(rgb.components(), 1);
+        assert!(!rgb.is_gray());
+        assert_eq!(rgb.default_decode(4), vec![[0.0, 15.0]]);
+        assert_eq!(rgb.to_rgb(&[0.0]), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb.to_rgb(&[1.0]), [0.0, 1.0, 0.0]);
+        assert_eq!(rgb.to_rgb(&[2.2]), [0.0, 0.0, 1.0]);
+        // indices above hival take the last entry
+        assert_eq!(rgb.to_rgb(&[7.0]), [0.0, 0.0, 1.0]);
+
+        let cmyk = indexed("DeviceCMYK", 1, &[0, 0, 0, 255, 255, 0, 0, 0]);
+        assert_eq!(cmyk.to_rgb(&[0.0]), [0.0, 0.0, 0.0]);
+        assert_eq!(cmyk.to_rgb(&[1.0]), [0.0, 1.0, 1.0]);
+
+        // a palette shorter than hival says is black past its end
+        let gray = indexed("DeviceGray", 3, &[255, 128]);
+        assert!(gray.is_gray());
+        assert_eq!(gray.to_rgb(&[0.0]), [1.0; 3]);
+        assert_eq!(gray.to_rgb(&[3.0]), [0.0; 3]);
+
+        let malformed = vec![Object::Name(b"Indexed".to_vec()), Object::Name(b"DeviceRGB".to_vec()), 1.into()];
+        assert!(ColorSpace::parse(&Document::new(), &Object::Array(malformed)).is_err());
+    }
+}
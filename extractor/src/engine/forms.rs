@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{decode_text_string, Document, Object, ObjectId};
+
+use crate::engine::outline;
+
+// Interactive form fields from the AcroForm tree. Field values live in the field dictionaries,
+// not in the page content, so without this filled-in forms come back blank.
+
+// field flags from table 226 and 230 of the PDF 1.7 specification
+const FLAG_RADIO: i64 = 1 << 15;
+const FLAG_PUSH_BUTTON: i64 = 1 << 16;
+const MAX_FIELD_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Checkbox,
+    Radio,
+    PushButton,
+    Choice,
+    Signature,
+    Unknown,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FormField {
+    // fully qualified name, the partial names of the field and its parents joined with dots
+    pub name: String,
+    pub field_type: FieldType,
+    pub value: Option<String>,
+    // only set for checkboxes and radio buttons
+    pub checked: Option<bool>,
+    pub page_num: Option<u32>,
+}
+
+// where widgets sit, through the pages' /Annots arrays or the widget's own /P entry
+struct PageLookup {
+    widgets: HashMap<ObjectId, u32>,
+    pages: HashMap<ObjectId, u32>,
+}
+
+// attributes a field inherits from its parents when it does not set them itself
+#[derive(Clone, Default)]
+struct Inherited<'a> {
+    name: Vec<String>,
+    field_type: Option<&'a [u8]>,
+    flags: i64,
+    value: Option<&'a Object>,
+}
+
+pub fn read_form_fields(doc: &Document) -> Vec<FormField> {
+    let Some(fields) = doc.catalog().ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| doc.dereference(form).ok())
+        .and_then(|(_, form)| form.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| doc.dereference(fields).ok())
+        .and_then(|(_, fields)| fields.as_array().ok())
+    else {
+        return vec![];
+    };
+
+    let page_lookup = PageLookup {
+        widgets: widget_pages(doc),
+        pages: outline::page_numbers(doc),
+    };
+    let mut visited = HashSet::new();
+    let mut form_fields = Vec::new();
+    for field in fields {
+        if let Ok(field_id) = field.as_reference() {
+            read_field(doc, field_id, Inherited::default(), &page_lookup, &mut visited, &mut form_fields, 0);
+        }
+    }
+    form_fields
+}
+
+fn read_field<'a>(
+    doc: &'a Document,
+    field_id: ObjectId,
+    mut inherited: Inherited<'a>,
+    page_lookup: &PageLookup,
+    visited: &mut HashSet<ObjectId>,
+    form_fields: &mut Vec<FormField>,
+    depth: usize,
+) {
+    if depth > MAX_FIELD_DEPTH || !visited.insert(field_id) {
+        return;
+    }
+    let Ok(field) = doc.get_dictionary(field_id) else {
+        return;
+    };
+
+    if let Some(name) = field.get(b"T").ok().and_then(|name| decode_text_string(name).ok()) {
+        inherited.name.push(name);
+    }
+    if let Ok(field_type) = field.get(b"FT").and_then(Object::as_name) {
+        inherited.field_type = Some(field_type);
+    }
+    if let Ok(flags) = field.get(b"Ff").and_then(Object::as_i64) {
+        inherited.flags = flags;
+    }
+    if let Ok(value) = field.get(b"V") {
+        inherited.value = doc.dereference(value).ok().map(|(_, value)| value);
+    }
+
+    // kids with a partial name are child fields, kids without one are the widgets of this field
+    let kids: Vec<ObjectId> = field.get(b"Kids").and_then(Object::as_array)
+        .map(|kids| kids.iter().filter_map(|kid| kid.as_reference().ok()).collect())
+        .unwrap_or_default();
+    let (child_fields, widgets): (Vec<ObjectId>, Vec<ObjectId>) = kids.into_iter()
+        .partition(|kid| doc.get_dictionary(*kid).is_ok_and(|kid| kid.has(b"T")));
+
+    if !child_fields.is_empty() {
+        for child in child_fields {
+            read_field(doc, child, inherited.clone(), page_lookup, visited, form_fields, depth + 1);
+        }
+        return;
+    }
+
+    // a field without separate widgets is merged with its only widget
+    let widgets = if widgets.is_empty() { vec![field_id] } else { widgets };
+    let field_type = field_type(inherited.field_type, inherited.flags);
+    let value = inherited.value.and_then(value_text);
+    let checked = match field_type {
+        FieldType::Checkbox | FieldType::Radio => {
+            let state = value.clone().or_else(|| widgets.iter()
+                .filter_map(|widget| doc.get_dictionary(*widget).ok())
+                .find_map(|widget| widget.get(b"AS").and_then(Object::as_name_str).ok().filter(|state| *state != "Off"))
+                .map(str::to_string));
+            Some(state.is_some_and(|state| state != "Off"))
+        }
+        _ => None,
+    };
+    let page_num = widgets.iter().find_map(|widget| page_lookup.widgets.get(widget).copied().or_else(|| {
+        doc.get_dictionary(*widget).ok()
+            .and_then(|widget| widget.get(b"P").and_then(Object::as_reference).ok())
+            .and_then(|page_id| page_lookup.pages.get(&page_id).copied())
+    }));
+
+    form_fields.push(FormField {
+        name: inherited.name.join("."),
+        field_type,
+        value,
+        checked,
+        page_num,
+    });
+}
+
+fn field_type(field_type: Option<&[u8]>, flags: i64) -> FieldType {
+    match field_type {
+        Some(b"Tx") => FieldType::Text,
+        Some(b"Ch") => FieldType::Choice,
+        Some(b"Sig") => FieldType::Signature,
+        Some(b"Btn") if flags & FLAG_PUSH_BUTTON != 0 => FieldType::PushButton,
+        Some(b"Btn") if flags & FLAG_RADIO != 0 => FieldType::Radio,
+        Some(b"Btn") => FieldType::Checkbox,
+        _ => FieldType::Unknown,
+    }
+}
+
+// text fields hold strings, buttons hold the name of their on state and multi-select choice
+// fields an array of the selected options
+fn value_text(value: &Object) -> Option<String> {
+    let text = match value {
+        Object::String(..) => decode_text_string(value).ok()?,
+        Object::Name(name) => String::from_utf8_lossy(name).into_owned(),
+        Object::Array(values) => values.iter()
+            .filter_map(value_text)
+            .collect::<Vec<_>>()
+            .join(", "),
+        Object::Integer(value) => value.to_string(),
+        Object::Real(value) => value.to_string(),
+        _ => return None,
+    };
+    Some(text).filter(|text| !text.is_empty())
+}
+
+fn widget_pages(doc: &Document) -> HashMap<ObjectId, u32> {
+    let mut widget_pages = HashMap::new();
+    for (page_num, page_id) in doc.get_pages() {
+        let annotations = doc.get_dictionary(page_id).ok()
+            .and_then(|page| page.get(b"Annots").ok())
+            .and_then(|annotations| doc.dereference(annotations).ok())
+            .and_then(|(_, annotations)| annotations.as_array().ok());
+        for annotation in annotations.into_iter().flatten() {
+            if let Ok(annotation_id) = annotation.as_reference() {
+                widget_pages.entry(annotation_id).or_insert(page_num);
+            }
+        }
+    }
+    widget_pages
+}
+
+// fields without a widget on any page, such as hidden calculation fields, are left out
+pub fn fields_by_page(fields: Vec<FormField>) -> HashMap<u32, Vec<FormField>> {
+    let mut by_page: HashMap<u32, Vec<FormField>> = HashMap::new();
+    for field in fields {
+        if let Some(page_num) = field.page_num {
+            by_page.entry(page_num).or_default().push(field);
+        }
+    }
+    by_page
+}
@@ -0,0 +1,624 @@
+use std::{collections::HashMap, io::{Cursor, Read}};
+
+use flate2::read::ZlibDecoder;
+use image::{imageops::{self, FilterType}, DynamicImage, GrayAlphaImage, GrayImage, ImageFormat, ImageReader, LumaA, RgbImage, Rgba, RgbaImage};
+use lopdf::{xobject::PdfImage, Dictionary, Document, Object};
+
+use crate::engine::{ccitt::{self, CcittParams}, color_space::ColorSpace, jbig2, jpx};
+
+// Decodes image XObjects into pixels for OCR. The stream filters are undone in order, after which
+// the data is either a whole encoded image (DCTDecode, JPXDecode) or rows of raw samples, which is
+// also what CCITT fax and JBIG2 data decode to. Samples are unpacked, mapped through the Decode
+// array and turned into gray or RGB through the colour space. Transparency from an SMask, a Mask or
+// the image's own opacity channel is kept apart, so OCR can read the image over a white page.
+
+// about 12000 × 12000, a 1200 dpi scan of a large page
+const MAX_PIXELS: u64 = 150_000_000;
+
+// an image's colours and, when parts of it are transparent, how opaque each pixel is
+pub struct DecodedImage {
+    pub color: DynamicImage,
+    // the size of `color`, 0 for transparent to 255 for opaque
+    pub alpha: Option<GrayImage>,
+}
+
+impl DecodedImage {
+    // what OCR reads, the image over a white page
+    pub fn on_white(&self) -> DynamicImage {
+        let Some(alpha) = &self.alpha else {
+            return self.color.clone();
+        };
+        let mut image = luma_or_rgb(self.color.clone());
+        let channels = image.color().channel_count() as usize;
+        for (pixel, opacity) in samples_mut(&mut image).chunks_exact_mut(channels).zip(alpha.iter()) {
+            let opacity = *opacity as u32;
+            for value in pixel {
+                *value = ((*value as u32 * opacity + 255 * (255 - opacity)) / 255) as u8;
+            }
+        }
+        image
+    }
+
+    // the image with its transparency as an alpha channel, for export
+    pub fn with_alpha(&self) -> DynamicImage {
+        let Some(alpha) = &self.alpha else {
+            return self.color.clone();
+        };
+        let (width, height) = alpha.dimensions();
+        match &self.color {
+            DynamicImage::ImageLuma8(gray) => DynamicImage::ImageLumaA8(GrayAlphaImage::from_fn(width, height, |x, y| {
+                LumaA([gray.get_pixel(x, y)[0], alpha.get_pixel(x, y)[0]])
+            })),
+            color => {
+                let rgb = color.to_rgb8();
+                DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+                    let [r, g, b] = rgb.get_pixel(x, y).0;
+                    Rgba([r, g, b, alpha.get_pixel(x, y)[0]])
+                }))
+            }
+        }
+    }
+}
+
+pub fn decode_image(doc: &Document, image: &PdfImage) -> Result<DecodedImage, Box<dyn std::error::Error + Send + Sync>> {
+    let dict = image.origin_dict;
+    let (mut color, own_alpha) = decode_stream(doc, dict, image.content)?;
+    let (width, height) = (color.width(), color.height());
+
+    // a soft mask wins over the opacity in the image data, which wins over a stencil Mask
+    let alpha = match soft_mask(doc, dict) {
+        Some((alpha, matte)) => {
+            if let Some(matte) = matte {
+                color = unmatte(color, &fit(alpha.clone(), width, height), &matte);
+            }
+            Some(alpha)
+        }
+        None => own_alpha.or_else(|| stencil_mask(doc, dict)),
+    };
+    Ok(DecodedImage { color, alpha: alpha.map(|alpha| fit(alpha, width, height)) })
+}
+
+// the pixels of an image stream, with the opacity its data carries through SMaskInData or a colour key
+fn decode_stream(doc: &Document, dict: &Dictionary, content: &[u8]) -> Result<(DynamicImage, Option<GrayImage>), Box<dyn std::error::Error + Send + Sync>> {
+    let dimension = |key: &[u8]| dict.get(key).and_then(Object::as_i64).unwrap_or(0);
+    let (width, height) = (dimension(b"Width"), dimension(b"Height"));
+    if width <= 0 || height <= 0 || width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!("Unsupported image size {}x{}", width, height).into());
+    }
+
+    let filters = filters(doc, dict);
+    let max_length = max_decoded_length(doc, dict, width as u64, height as u64);
+    let mut data = content.to_vec();
+    for (index, (filter, params)) in filters.iter().enumerate() {
+        let params = params.as_ref();
+        data = match filter.as_str() {
+            "FlateDecode" | "Fl" => predict(inflate(&data, max_length)?, params),
+            "LZWDecode" | "LZW" => predict(lzw(&data, params), params),
+            "ASCIIHexDecode" | "AHx" => ascii_hex(&data),
+            "ASCII85Decode" | "A85" => ascii85(&data),
+            "RunLengthDecode" | "RL" => run_length(&data),
+            "CCITTFaxDecode" | "CCF" => ccitt::decode(&data, &CcittParams::from_dict(params), height as usize)?,
+            "JBIG2Decode" => jbig2::decode(&data, jbig2_globals(doc, params).as_deref())?,
+            // image codecs produce the finished image, so they can only come last
+            "DCTDecode" | "DCT" if index == filters.len() - 1 => return Ok((decode_dct(doc, dict, &data)?, None)),
+            "JPXDecode" if index == filters.len() - 1 => return decode_jpx(doc, dict, &data),
+            other => return Err(format!("Unsupported filter: {}", other).into()),
+        };
+    }
+    decode_samples(doc, dict, width as usize, height as usize, &data)
+}
+
+// the SMask of an image as opacity, with the Matte colour the image was premultiplied with
+fn soft_mask(doc: &Document, dict: &Dictionary) -> Option<(GrayImage, Option<Vec<u8>>)> {
+    let stream = dict.get(b"SMask").and_then(|smask| doc.dereference(smask)).ok()?.1.as_stream().ok()?;
+    let alpha = match decode_stream(doc, &stream.dict, &stream.content) {
+        Ok((mask, _)) => mask.to_luma8(),
+        Err(e) => {
+            println!("Ignoring soft mask that failed to decode: {}", e);
+            return None;
+        }
+    };
+    let matte = stream.dict.get(b"Matte").and_then(Object::as_array).ok().map(|matte| {
+        matte.iter()
+            .filter_map(|value| value.as_float().ok().or_else(|| value.as_i64().ok().map(|value| value as f32)))
+            .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    });
+    Some((alpha, matte))
+}
+
+// a Mask stream is a stencil where 1 leaves the page unpainted, which decodes to white
+fn stencil_mask(doc: &Document, dict: &Dictionary) -> Option<GrayImage> {
+    let stream = dict.get(b"Mask").and_then(|mask| doc.dereference(mask)).ok()?.1.as_stream().ok()?;
+    match decode_stream(doc, &stream.dict, &stream.content) {
+        Ok((mask, _)) => {
+            let mut alpha = mask.to_luma8();
+            imageops::invert(&mut alpha);
+            Some(alpha)
+        }
+        Err(e) => {
+            println!("Ignoring stencil mask that failed to decode: {}", e);
+            None
+        }
+    }
+}
+
+// masks can have a resolution of their own
+fn fit(alpha: GrayImage, width: u32, height: u32) -> GrayImage {
+    if alpha.dimensions() == (width, height) {
+        return alpha;
+    }
+    imageops::resize(&alpha, width, height, FilterType::Triangle)
+}
+
+// undoes the premultiplication of colours with their opacity over a matte colour
+fn unmatte(color: DynamicImage, alpha: &GrayImage, matte: &[u8]) -> DynamicImage {
+    let mut color = luma_or_rgb(color);
+    let channels = color.color().channel_count() as usize;
+    if matte.len() != channels {
+        return color;
+    }
+    for (pixel, opacity) in samples_mut(&mut color).chunks_exact_mut(channels).zip(alpha.iter()) {
+        // fully transparent pixels have no colour left to recover
+        if *opacity == 0 {
+            continue;
+        }
+        for (value, matte) in pixel.iter_mut().zip(matte) {
+            let straight = *matte as f32 + (*value as f32 - *matte as f32) * 255.0 / *opacity as f32;
+            *value = straight.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    color
+}
+
+fn luma_or_rgb(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => image,
+        other => DynamicImage::ImageRgb8(other.to_rgb8()),
+    }
+}
+
+fn samples_mut(image: &mut DynamicImage) -> &mut [u8] {
+    match image {
+        DynamicImage::ImageLuma8(buffer) => buffer,
+        DynamicImage::ImageRgb8(buffer) => buffer,
+        _ => &mut [],
+    }
+}
+
+// the filters of the stream in decoding order, each with its DecodeParms
+fn filters(doc: &Document, dict: &Dictionary) -> Vec<(String, Option<Dictionary>)> {
+    let resolve = |key: &[u8]| dict.get(key).and_then(|value| doc.dereference(value)).map(|(_, value)| value).ok();
+    let names: Vec<String> = match resolve(b"Filter") {
+        Some(Object::Name(name)) => vec![String::from_utf8_lossy(name).into_owned()],
+        Some(Object::Array(names)) => names.iter().filter_map(|name| name.as_name().ok()).map(|name| String::from_utf8_lossy(name).into_owned()).collect(),
+        _ => vec![],
+    };
+    let params: Vec<Option<Dictionary>> = match resolve(b"DecodeParms").or_else(|| resolve(b"DP")) {
+        Some(Object::Dictionary(params)) => vec![Some(params.clone())],
+        Some(Object::Array(params)) => params.iter()
+            .map(|params| doc.dereference(params).ok().and_then(|(_, params)| params.as_dict().ok()).cloned())
+            .collect(),
+        _ => vec![],
+    };
+    names.into_iter().enumerate().map(|(index, name)| (name, params.get(index).cloned().flatten())).collect()
+}
+
+// the symbol dictionaries shared by the JBIG2 images of a document
+fn jbig2_globals(doc: &Document, params: Option<&Dictionary>) -> Option<Vec<u8>> {
+    let globals = params?.get(b"JBIG2Globals").and_then(|globals| doc.dereference(globals)).ok()?.1.as_stream().ok()?;
+    Some(globals.decompressed_content().unwrap_or_else(|_| globals.content.clone()))
+}
+
+fn decode_array(doc: &Document, dict: &Dictionary) -> Vec<[f32; 2]> {
+    dict.get(b"Decode")
+        .and_then(|decode| doc.dereference(decode))
+        .and_then(|(_, decode)| decode.as_array())
+        .map(|decode| {
+            decode.iter()
+                .filter_map(|value| value.as_float().ok().or_else(|| value.as_i64().ok().map(|value| value as f32)))
+                .collect::<Vec<f32>>()
+                .chunks_exact(2)
+                .map(|pair| [pair[0], pair[1]])
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn decode_dct(doc: &Document, dict: &Dictionary, data: &[u8]) -> Result<DynamicImage, Box<dyn std::error::Error + Send + Sync>> {
+    let mut image = ImageReader::with_format(Cursor::new(data), ImageFormat::Jpeg)
+        .decode()
+        .map_err(|e| format!("Failed to decode JPEG image: {}", e))?;
+    // an inverted Decode array, as written for CMYK JPEGs stored negative
+    if decode_array(doc, dict).first().is_some_and(|[min, max]| min > max) {
+        image.invert();
+    }
+    Ok(image)
+}
+
+// JPEG 2000 brings its own colour space and precision, a ColorSpace entry only overrides the colours
+fn decode_jpx(doc: &Document, dict: &Dictionary, data: &[u8]) -> Result<(DynamicImage, Option<GrayImage>), Box<dyn std::error::Error + Send + Sync>> {
+    let jpx = jpx::decode(data)?;
+    let color_space = match dict.get(b"ColorSpace") {
+        // a space with more components than came out was already converted by opj_decompress
+        Ok(color_space) => Some(ColorSpace::parse(doc, color_space)?).filter(|color_space| color_space.components() <= jpx.channels),
+        Err(_) => None,
+    };
+    let color_space = color_space.unwrap_or(if jpx.channels <= 2 { ColorSpace::Gray } else { ColorSpace::Rgb });
+    let components = color_space.components();
+    let precision = u32::BITS - jpx.max.leading_zeros();
+
+    // the colour components go through the same path as raw samples, the opacity is kept apart
+    let bits = if jpx.max > 255 { 16 } else { 8 };
+    let mut samples = Vec::with_capacity(jpx.width * jpx.height * components * bits / 8);
+    let mut alpha = Vec::new();
+    for pixel in jpx.samples.chunks_exact(jpx.channels) {
+        for sample in &pixel[..components] {
+            if bits == 16 {
+                samples.extend(sample.to_be_bytes());
+            } else {
+                samples.push(*sample as u8);
+            }
+        }
+        if let Some(opacity) = pixel.get(components) {
+            alpha.push((*opacity as u32 * 255 / jpx.max) as u8);
+        }
+    }
+    let image = samples_to_image(&color_space, &color_space.default_decode(precision), jpx.max, bits, jpx.width, jpx.height, &samples)?;
+
+    // SMaskInData 1 stores the opacity next to the colours, 2 has the colours premultiplied by it
+    let smask_in_data = dict.get(b"SMaskInData").and_then(Object::as_i64).unwrap_or(0);
+    let Some(alpha) = GrayImage::from_raw(jpx.width as u32, jpx.height as u32, alpha).filter(|_| smask_in_data != 0) else {
+        return Ok((image, None));
+    };
+    let image = if smask_in_data == 2 {
+        let black = vec![0; image.color().channel_count() as usize];
+        unmatte(image, &alpha, &black)
+    } else {
+        image
+    };
+    Ok((image, Some(alpha)))
+}
+
+fn decode_samples(doc: &Document, dict: &Dictionary, width: usize, height: usize, data: &[u8]) -> Result<(DynamicImage, Option<GrayImage>), Box<dyn std::error::Error + Send + Sync>> {
+    // a stencil mask is 1 bit per pixel, where 0 paints the page and 1 leaves it as it is
+    let image_mask = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+    let bits = if image_mask {
+        1
+    } else {
+        dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8) as usize
+    };
+    if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+        return Err(format!("Unsupported BitsPerComponent {}", bits).into());
+    }
+
+    let color_space = match dict.get(b"ColorSpace") {
+        Ok(color_space) if !image_mask => ColorSpace::parse(doc, color_space)?,
+        // without a colour space the number of components follows from the data length
+        _ if !image_mask => ColorSpace::from_components(data.len() * 8 / (width * height * bits).max(1))
+            .ok_or("Image without a colour space")?,
+        _ => ColorSpace::Gray,
+    };
+    let decode = Some(decode_array(doc, dict))
+        .filter(|decode| decode.len() == color_space.components())
+        .unwrap_or_else(|| color_space.default_decode(bits as u32));
+    let image = samples_to_image(&color_space, &decode, (1u32 << bits) - 1, bits, width, height, data)?;
+
+    // a Mask array holds a range of sample values per component, pixels inside all of them are not painted
+    let components = color_space.components();
+    let color_key = dict.get(b"Mask")
+        .and_then(|mask| doc.dereference(mask))
+        .and_then(|(_, mask)| mask.as_array())
+        .map(|ranges| ranges.iter().filter_map(|value| value.as_i64().ok()).collect::<Vec<i64>>())
+        .ok()
+        .filter(|ranges| !image_mask && ranges.len() == components * 2);
+    let alpha = color_key.map(|ranges| {
+        let row_length = (width * components * bits).div_ceil(8);
+        GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            let Some(row) = data.get(y as usize * row_length..(y as usize + 1) * row_length) else {
+                return image::Luma([255]);
+            };
+            let masked = ranges.chunks_exact(2).enumerate().all(|(component, range)| {
+                let sample = read_sample(row, x as usize * components + component, bits) as i64;
+                range[0] <= sample && sample <= range[1]
+            });
+            image::Luma([if masked { 0 } else { 255 }])
+        })
+    });
+    Ok((image, alpha))
+}
+
+// rows of `bits` sized samples to pixels, `max_sample` being the sample value that maps onto the
+// top of the Decode range
+fn samples_to_image(color_space: &ColorSpace, decode: &[[f32; 2]], max_sample: u32, bits: usize, width: usize, height: usize, data: &[u8]) -> Result<DynamicImage, Box<dyn std::error::Error + Send + Sync>> {
+    let components = color_space.components();
+    let max = max_sample as f32;
+    let scale: Vec<(f32, f32)> = decode.iter().map(|[min, max_value]| (*min, (max_value - min) / max)).collect();
+
+    // rows start on a byte boundary, missing data at the end of a truncated stream stays black
+    let row_length = (width * components * bits).div_ceil(8);
+    let mut data = data.to_vec();
+    data.resize(row_length * height, 0);
+
+    // converting through a function or Lab is slow, and most images only use a few colours
+    let cached = matches!(color_space, ColorSpace::Lab { .. } | ColorSpace::DeviceN { .. }) && components * bits <= 64;
+    let mut cache: HashMap<u64, [u8; 3]> = HashMap::new();
+    let gray = color_space.is_gray();
+    let mut pixels = Vec::with_capacity(width * height * if gray { 1 } else { 3 });
+    let mut values = vec![0.0; components];
+
+    for row in data.chunks_exact(row_length) {
+        for x in 0..width {
+            let mut key = 0u64;
+            for (component, value) in values.iter_mut().enumerate() {
+                let sample = read_sample(row, x * components + component, bits);
+                key = key << bits | sample as u64;
+                *value = scale[component].0 + sample as f32 * scale[component].1;
+            }
+            let rgb = if cached {
+                *cache.entry(key).or_insert_with(|| to_bytes(color_space.to_rgb(&values)))
+            } else {
+                to_bytes(color_space.to_rgb(&values))
+            };
+            if gray {
+                pixels.push(rgb[0]);
+            } else {
+                pixels.extend_from_slice(&rgb);
+            }
+        }
+    }
+
+    let (width, height) = (width as u32, height as u32);
+    let image = if gray {
+        GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+    } else {
+        RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+    };
+    image.ok_or_else(|| "Failed to build image from samples".into())
+}
+
+fn to_bytes(rgb: [f32; 3]) -> [u8; 3] {
+    rgb.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+// the `index`th sample of a row, 16 bit samples are big-endian
+fn read_sample(row: &[u8], index: usize, bits: usize) -> u32 {
+    match bits {
+        8 => row[index] as u32,
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+        _ => {
+            let bit = index * bits;
+            (row[bit / 8] >> (8 - bits - bit % 8)) as u32 & ((1 << bits) - 1)
+        }
+    }
+}
+
+fn write_sample(row: &mut [u8], index: usize, bits: usize, value: u32) {
+    match bits {
+        8 => row[index] = value as u8,
+        16 => row[index * 2..index * 2 + 2].copy_from_slice(&(value as u16).to_be_bytes()),
+        _ => {
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            let mask = (((1u32 << bits) - 1) << shift) as u8;
+            row[bit / 8] = (row[bit / 8] & !mask) | ((value << shift) as u8 & mask);
+        }
+    }
+}
+
+// the most any filter of an image needs to decode to: the raw samples, with a byte per row for the
+// PNG predictor's filter tags and a row to spare. Encoded DCT, JPX, fax or JBIG2 data is smaller
+fn max_decoded_length(doc: &Document, dict: &Dictionary, width: u64, height: u64) -> u64 {
+    let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8).clamp(1, 16) as u64;
+    // without a colour space that can be read the data can still carry four components
+    let components = dict.get(b"ColorSpace").ok()
+        .and_then(|color_space| ColorSpace::parse(doc, color_space).ok())
+        .map_or(4, |color_space| color_space.components()) as u64;
+    (height + 1) * ((width * components * bits).div_ceil(8) + 1)
+}
+
+// data inflating to more than `limit` bytes is cut off there, so it cannot take all memory
+fn inflate(data: &[u8], limit: u64) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    // whatever was inflated before the stream broke off is still worth reading
+    if let Err(e) = ZlibDecoder::new(data).take(limit).read_to_end(&mut output) {
+        if output.is_empty() {
+            return Err(format!("Failed to inflate image data: {}", e));
+        }
+        println!("Image data is truncated: {}", e);
+    }
+    Ok(output)
+}
+
+fn lzw(data: &[u8], params: Option<&Dictionary>) -> Vec<u8> {
+    use weezl::{decode::Decoder, BitOrder};
+
+    let early_change = params.and_then(|params| params.get(b"EarlyChange").and_then(Object::as_i64).ok()).unwrap_or(1) != 0;
+    let mut decoder = if early_change {
+        Decoder::with_tiff_size_switch(BitOrder::Msb, 8)
+    } else {
+        Decoder::new(BitOrder::Msb, 8)
+    };
+    let mut output = Vec::new();
+    if let Err(e) = decoder.into_stream(&mut output).decode_all(data).status {
+        println!("Image data is truncated: {}", e);
+    }
+    output
+}
+
+// undoes the TIFF or PNG predictor that Flate and LZW data may have been encoded with
+fn predict(data: Vec<u8>, params: Option<&Dictionary>) -> Vec<u8> {
+    let Some(params) = params else {
+        return data;
+    };
+    let param = |key: &[u8], default: i64| params.get(key).and_then(Object::as_i64).unwrap_or(default).max(1) as usize;
+    let (colors, bits, columns) = (param(b"Colors", 1), param(b"BitsPerComponent", 8), param(b"Columns", 1));
+    let row_length = (colors * bits * columns).div_ceil(8);
+
+    match param(b"Predictor", 1) {
+        2 if matches!(bits, 1 | 2 | 4 | 8 | 16) => tiff_predictor(data, row_length, colors, columns, bits),
+        10..=15 => png_predictor(&data, row_length, (colors * bits).div_ceil(8)),
+        _ => data,
+    }
+}
+
+// every sample is stored as the difference to the same component of the pixel before it
+fn tiff_predictor(mut data: Vec<u8>, row_length: usize, colors: usize, columns: usize, bits: usize) -> Vec<u8> {
+    let mask = (1u32 << bits) - 1;
+    for row in data.chunks_exact_mut(row_length) {
+        for index in colors..colors * columns {
+            let value = read_sample(row, index, bits) + read_sample(row, index - colors, bits);
+            write_sample(row, index, bits, value & mask);
+        }
+    }
+    data
+}
+
+// every row starts with a byte naming the PNG filter it was encoded with
+fn png_predictor(data: &[u8], row_length: usize, pixel_length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_length];
+    for row in data.chunks(row_length + 1) {
+        let mut current = row[1..].to_vec();
+        current.resize(row_length, 0);
+        for index in 0..row_length {
+            let left = if index >= pixel_length { current[index - pixel_length] } else { 0 };
+            let up = previous[index];
+            let up_left = if index >= pixel_length { previous[index - pixel_length] } else { 0 };
+            let prediction = match row[0] {
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => 0,
+            };
+            current[index] = current[index].wrapping_add(prediction);
+        }
+        output.extend_from_slice(&current);
+        previous = current;
+    }
+    output
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn ascii_hex(data: &[u8]) -> Vec<u8> {
+    let digits: Vec<u8> = data.iter()
+        .take_while(|byte| **byte != b'>')
+        .filter_map(|byte| (*byte as char).to_digit(16))
+        .map(|digit| digit as u8)
+        .collect();
+    // an odd last digit is followed by an implied 0
+    digits.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect()
+}
+
+fn ascii85(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut group = [0u8; 5];
+    let mut count = 0;
+    for &byte in data {
+        match byte {
+            b'~' => break,
+            b'z' if count == 0 => output.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group[count] = byte - b'!';
+                count += 1;
+                if count == 5 {
+                    output.extend_from_slice(&ascii85_group(&group).to_be_bytes());
+                    count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+    // a final partial group is padded with the highest digit and cut back
+    if count > 1 {
+        group[count..].fill(84);
+        output.extend_from_slice(&ascii85_group(&group).to_be_bytes()[..count - 1]);
+    }
+    output
+}
+
+fn ascii85_group(group: &[u8; 5]) -> u32 {
+    group.iter().fold(0u32, |value, digit| value.wrapping_mul(85).wrapping_add(*digit as u32))
+}
+
+fn run_length(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut index = 0;
+    while let Some(&length) = data.get(index) {
+        match length {
+            128 => break,
+            0..=127 => {
+                let end = (index + 2 + length as usize).min(data.len());
+                output.extend_from_slice(&data[index + 1..end]);
+                index = end;
+            }
+            _ => {
+                if let Some(&byte) = data.get(index + 1) {
+                    output.extend(std::iter::repeat(byte).take(257 - length as usize));
+                }
+                index += 2;
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use lopdf::dictionary;
+    use std::io::Write;
+
+    #[test]
+    fn stops_inflating_at_the_size_of_the_image() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0x80; 16 << 20]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let dict = dictionary! { "Width" => 10, "Height" => 10, "BitsPerComponent" => 8, "ColorSpace" => "DeviceGray", "Filter" => "FlateDecode" };
+        let doc = Document::new();
+        assert_eq!(max_decoded_length(&doc, &dict, 10, 10), 11 * 11);
+        assert_eq!(inflate(&bomb, 121).unwrap().len(), 121);
+
+        let (image, alpha) = decode_stream(&doc, &dict, &bomb).unwrap();
+        assert_eq!((image.width(), image.height()), (10, 10));
+        assert!(image.to_luma8().iter().all(|value| *value == 0x80) && alpha.is_none());
+    }
+
+    #[test]
+    fn undoes_png_and_tiff_predictors() {
+        let params = |predictor: i64| dictionary! { "Predictor" => predictor, "Colors" => 1, "BitsPerComponent" => 8, "Columns" => 3 };
+        let rows = [
+            0, 1, 2, 3,
+            // sub, left + 1
+            1, 1, 1, 1,
+            // up, the row above + 1
+            2, 1, 1, 1,
+            // average of left and up
+            3, 4, 0, 0,
+            // paeth, nearest of left, up and up left to left + up - up left
+            4, 1, 1, 1,
+        ];
+        assert_eq!(predict(rows.to_vec(), Some(&params(15))), vec![1, 2, 3, 1, 2, 3, 2, 3, 4, 5, 4, 4, 6, 6, 7]);
+        // sums wrap around and a short last row is padded
+        assert_eq!(predict(vec![1, 200, 100, 0, 1, 5], Some(&params(10))), vec![200, 44, 44, 5, 5, 5]);
+
+        assert_eq!(predict(vec![1, 1, 1, 10, 255, 2], Some(&params(2))), vec![1, 2, 3, 10, 9, 11]);
+        let two_colors = dictionary! { "Predictor" => 2, "Colors" => 2, "BitsPerComponent" => 8, "Columns" => 2 };
+        assert_eq!(predict(vec![1, 2, 3, 4], Some(&two_colors)), vec![1, 2, 4, 6]);
+        assert_eq!(predict(vec![1, 2, 3], None), vec![1, 2, 3]);
+    }
+}
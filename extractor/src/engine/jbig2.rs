@@ -0,0 +1,74 @@
+use std::{path::PathBuf, process::Command};
+
+use crate::helper::file_helper;
+
+// JBIG2Decode through the jbig2dec command line tool. PDF embeds JBIG2 without its file header,
+// with the symbol dictionaries shared between images in a separate JBIG2Globals stream, both of
+// which jbig2dec reads with --embedded. The bitmap comes back as a PBM, whose rows are padded to a
+// byte like PDF samples but with 1 for black, so it is inverted to read as DeviceGray.
+
+pub fn decode(data: &[u8], globals: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let base = file_helper::get_temp_path("jbig2");
+    let page_path = base.with_extension("jb2");
+    let globals_path = base.with_extension("glb");
+    let output_path = base.with_extension("pbm");
+
+    let result = run_jbig2dec(data, globals, &page_path, &globals_path, &output_path);
+    for path in [&page_path, &globals_path, &output_path] {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+fn run_jbig2dec(data: &[u8], globals: Option<&[u8]>, page_path: &PathBuf, globals_path: &PathBuf, output_path: &PathBuf) -> Result<Vec<u8>, String> {
+    std::fs::write(page_path, data).map_err(|e| format!("Failed to write JBIG2 data: {}", e))?;
+    let mut command = Command::new("jbig2dec");
+    command.args(["--embedded", "--format", "pbm", "--output"]).arg(output_path);
+    // the globals go before the page data they belong to
+    if let Some(globals) = globals {
+        std::fs::write(globals_path, globals).map_err(|e| format!("Failed to write JBIG2 globals: {}", e))?;
+        command.arg(globals_path);
+    }
+    command.arg(page_path);
+
+    let output = command.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "jbig2dec is not installed".to_string(),
+        _ => format!("Failed to run jbig2dec: {}", e),
+    })?;
+    if !output.status.success() {
+        return Err(format!("jbig2dec failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    let pbm = std::fs::read(output_path).map_err(|e| format!("jbig2dec wrote no image: {}", e))?;
+    let samples = pbm_samples(&pbm).ok_or("jbig2dec wrote an unreadable PBM")?;
+    Ok(samples.iter().map(|byte| !byte).collect())
+}
+
+// the packed rows of a binary PBM, after its `P4 <width> <height>` header
+fn pbm_samples(pbm: &[u8]) -> Option<&[u8]> {
+    if !pbm.starts_with(b"P4") {
+        return None;
+    }
+    let mut position = 2;
+    let mut numbers = 0;
+    while numbers < 2 {
+        match pbm.get(position)? {
+            b'#' => {
+                while *pbm.get(position)? != b'\n' {
+                    position += 1;
+                }
+            }
+            byte if byte.is_ascii_digit() => {
+                while pbm.get(position)?.is_ascii_digit() {
+                    position += 1;
+                }
+                numbers += 1;
+                continue;
+            }
+            _ => {}
+        }
+        position += 1;
+    }
+    // a single whitespace character ends the header
+    pbm.get(position + 1..)
+}
@@ -0,0 +1,112 @@
+use std::{path::PathBuf, process::Command};
+
+use crate::helper::file_helper;
+
+// JPXDecode through opj_decompress from OpenJPEG. The tool applies what the JPEG 2000 file says
+// about its colours, so sYCC, CMYK, CIELab and ICC profiles come back as gray or RGB, and writes
+// them with any opacity channel as a PNM or PAM with the samples at their own precision.
+
+// the JP2 signature box, anything else is taken to be a bare codestream
+const JP2_SIGNATURE: &[u8] = b"\x00\x00\x00\x0cjP  \r\n\x87\n";
+
+pub struct JpxImage {
+    pub width: usize,
+    pub height: usize,
+    // colour components first, then the opacity channel if there is one
+    pub channels: usize,
+    // the largest sample value, 2^precision - 1
+    pub max: u32,
+    // interleaved per pixel
+    pub samples: Vec<u16>,
+}
+
+pub fn decode(data: &[u8]) -> Result<JpxImage, String> {
+    let base = file_helper::get_temp_path("jpx");
+    // opj_decompress goes by the extension to tell the two kinds of data apart
+    let input_path = base.with_extension(if data.starts_with(JP2_SIGNATURE) { "jp2" } else { "j2k" });
+    let output_path = base.with_extension("pnm");
+
+    let result = run_opj_decompress(data, &input_path, &output_path);
+    for path in [&input_path, &output_path] {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+fn run_opj_decompress(data: &[u8], input_path: &PathBuf, output_path: &PathBuf) -> Result<JpxImage, String> {
+    std::fs::write(input_path, data).map_err(|e| format!("Failed to write JPX data: {}", e))?;
+    // chroma subsampled components are scaled up to the full image size
+    let output = Command::new("opj_decompress")
+        .arg("-i").arg(input_path)
+        .arg("-o").arg(output_path)
+        .arg("-upsample")
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "opj_decompress is not installed".to_string(),
+            _ => format!("Failed to run opj_decompress: {}", e),
+        })?;
+    if !output.status.success() {
+        return Err(format!("opj_decompress failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    let pnm = std::fs::read(output_path).map_err(|e| format!("opj_decompress wrote no image: {}", e))?;
+    parse_pnm(&pnm).ok_or_else(|| "opj_decompress wrote an unreadable image".to_string())
+}
+
+// P5 gray, P6 RGB, or P7 with WIDTH, HEIGHT, DEPTH and MAXVAL lines for images with opacity
+fn parse_pnm(pnm: &[u8]) -> Option<JpxImage> {
+    let (channels, width, height, max, data) = if pnm.starts_with(b"P7") {
+        let end = pnm.windows(7).position(|window| window == b"ENDHDR\n")?;
+        let header = std::str::from_utf8(&pnm[..end]).ok()?;
+        let field = |key: &str| {
+            header.lines()
+                .find_map(|line| line.strip_prefix(key).map(str::trim))
+                .and_then(|value| value.parse::<usize>().ok())
+        };
+        (field("DEPTH ")?, field("WIDTH ")?, field("HEIGHT ")?, field("MAXVAL ")?, &pnm[end + 7..])
+    } else {
+        let channels = match pnm.get(..2)? {
+            b"P5" => 1,
+            b"P6" => 3,
+            _ => return None,
+        };
+        let mut numbers = Vec::new();
+        let mut position = 2;
+        while numbers.len() < 3 {
+            match pnm.get(position)? {
+                b'#' => {
+                    while *pnm.get(position)? != b'\n' {
+                        position += 1;
+                    }
+                }
+                byte if byte.is_ascii_digit() => {
+                    let start = position;
+                    while pnm.get(position)?.is_ascii_digit() {
+                        position += 1;
+                    }
+                    numbers.push(std::str::from_utf8(&pnm[start..position]).ok()?.parse::<usize>().ok()?);
+                    continue;
+                }
+                _ => {}
+            }
+            position += 1;
+        }
+        // a single whitespace character ends the header
+        (channels, numbers[0], numbers[1], numbers[2], pnm.get(position + 1..)?)
+    };
+    if channels == 0 || max == 0 || max > 65535 {
+        return None;
+    }
+
+    // samples above 255 take two bytes, most significant first
+    let count = width * height * channels;
+    let samples: Vec<u16> = if max > 255 {
+        data.chunks_exact(2).take(count).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect()
+    } else {
+        data.iter().take(count).map(|byte| *byte as u16).collect()
+    };
+    if samples.len() < count {
+        return None;
+    }
+    Some(JpxImage { width, height, channels, max: max as u32, samples })
+}
@@ -0,0 +1,82 @@
+use whatlang::Script;
+
+// Language of a page, detected from its text so OCR can use the matching Tesseract traineddata
+// instead of English for everything.
+
+// shorter text gives whatlang too little to go on
+const MIN_DETECTION_CHARS: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PageLanguage {
+    // ISO 639-3, such as "fra" or "ara"
+    pub language: String,
+    // such as "Latin" or "Arabic"
+    pub script: String,
+    // 0 to 1
+    pub confidence: f32,
+    pub reliable: bool,
+}
+
+pub fn detect(text: &str) -> Option<PageLanguage> {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if letters < MIN_DETECTION_CHARS {
+        return None;
+    }
+    let info = whatlang::detect(text)?;
+    Some(PageLanguage {
+        language: info.lang().code().to_string(),
+        script: info.script().name().to_string(),
+        confidence: (info.confidence() * 100.0).round() as f32 / 100.0,
+        reliable: info.is_reliable(),
+    })
+}
+
+// the languages to OCR a page in, detected ones first. Latin script pages keep English as a
+// fallback for the quotes, names and references most of them contain
+pub fn ocr_languages(language: &PageLanguage) -> String {
+    let latin = Script::Latin.name();
+    if language.language == "eng" || language.script != latin {
+        language.language.clone()
+    } else {
+        format!("{}+eng", language.language)
+    }
+}
+
+// Tesseract names most traineddata after the ISO 639-3 code, these are the exceptions
+pub fn tesseract_code(code: &str) -> &str {
+    match code {
+        "cmn" | "zho" => "chi_sim",
+        "pes" => "fas",
+        "nob" => "nor",
+        code => code,
+    }
+}
+
+// the BCP 47 tag for a traineddata name, such as "fr" for "fra", for formats that declare the
+// language of their text. BCP 47 takes the two letter ISO 639-1 code where there is one
+pub fn bcp47(traineddata: &str) -> Option<String> {
+    // script variants such as "deu_latf" or "jpn_vert" are tagged as their language
+    let tag = match traineddata {
+        "chi_sim" | "chi_sim_vert" => "zh-Hans",
+        "chi_tra" | "chi_tra_vert" => "zh-Hant",
+        "osd" | "equ" => return None,
+        name => match name.split('_').next().unwrap_or(name) {
+            "afr" => "af", "aka" => "ak", "amh" => "am", "ara" => "ar", "aze" => "az", "bel" => "be",
+            "ben" => "bn", "bul" => "bg", "cat" => "ca", "ces" => "cs", "cmn" | "zho" => "zh", "dan" => "da",
+            "deu" => "de", "ell" => "el", "eng" => "en", "epo" => "eo", "est" => "et", "fas" | "pes" => "fa",
+            "fin" => "fi", "fra" => "fr", "guj" => "gu", "heb" => "he", "hin" => "hi", "hrv" => "hr",
+            "hun" => "hu", "hye" => "hy", "ind" => "id", "ita" => "it", "jav" => "jv", "jpn" => "ja",
+            "kan" => "kn", "kat" => "ka", "khm" => "km", "kor" => "ko", "lat" => "la", "lav" => "lv",
+            "lit" => "lt", "mal" => "ml", "mar" => "mr", "mkd" => "mk", "mya" => "my", "nep" => "ne",
+            "nld" => "nl", "nob" => "nb", "nor" => "no", "ori" => "or", "pan" => "pa", "pol" => "pl",
+            "por" => "pt", "ron" => "ro", "rus" => "ru", "sin" => "si", "slk" => "sk", "slv" => "sl",
+            "sna" => "sn", "spa" => "es", "srp" => "sr", "swe" => "sv", "tam" => "ta", "tel" => "te",
+            "tgl" => "tl", "tha" => "th", "tuk" => "tk", "tur" => "tr", "ukr" => "uk", "urd" => "ur",
+            "uzb" => "uz", "vie" => "vi", "yid" => "yi", "zul" => "zu",
+            // languages without a two letter code keep their ISO 639-3 one
+            code if code.len() == 3 && code.chars().all(|c| c.is_ascii_lowercase()) => code,
+            _ => return None,
+        },
+    };
+    Some(tag.to_string())
+}
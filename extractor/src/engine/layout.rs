@@ -0,0 +1,423 @@
+use crate::{engine::text_layer::{self, Glyph, Matrix}, types::engine_handler::OcrWord};
+
+// Positioned text of a page as blocks of lines of words. Every level carries its bounding box as
+// [x0, y0, x1, y1] in PDF user space, so boxes from the text layer and from OCR can be compared.
+
+// grouping thresholds, relative to the font size
+const MAX_GLYPH_GAP: f32 = 0.2;
+const MAX_GLYPH_OVERLAP: f32 = 0.5;
+const MAX_BASELINE_SHIFT: f32 = 0.5;
+const MAX_WORD_GAP: f32 = 1.5;
+const MAX_LINE_DROP: f32 = 2.0;
+const MAX_SIZE_RATIO: f32 = 1.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutSource {
+    TextLayer,
+    Ocr,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Word {
+    pub text: String,
+    pub bbox: [f32; 4],
+    // without the subset tag, None for OCR words
+    pub font_name: Option<String>,
+    pub font_size: Option<f32>,
+    // OCR confidence from 0 to 100, None for the text layer
+    pub confidence: Option<f32>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Line {
+    pub bbox: [f32; 4],
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Block {
+    pub bbox: [f32; 4],
+    pub source: LayoutSource,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct PageLayout {
+    pub blocks: Vec<Block>,
+}
+
+impl PageLayout {
+    pub fn text(&self) -> String {
+        self.blocks.iter()
+            .map(Block::text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl Block {
+    pub fn text(&self) -> String {
+        self.lines.iter()
+            .map(Line::text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.words.iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// the visible page a rendered image was taken from
+#[derive(Debug, Clone, Copy)]
+pub struct PageFrame {
+    // [x0, y0, x1, y1] of the crop box, or the media box without one
+    pub page_box: [f32; 4],
+    pub rotation: i64,
+}
+
+impl PageFrame {
+    // maps pixels of a render of this page, `width` × `height` and with the page rotation
+    // applied, back onto user space
+    pub fn render_matrix(&self, width: u32, height: u32) -> Matrix {
+        let [x0, y0, x1, y1] = self.page_box;
+        let (page_width, page_height) = (x1 - x0, y1 - y0);
+        let rotated = matches!(self.rotation, 90 | 270);
+        let (shown_width, shown_height) = if rotated { (page_height, page_width) } else { (page_width, page_height) };
+        let sx = shown_width / width.max(1) as f32;
+        let sy = shown_height / height.max(1) as f32;
+
+        match self.rotation {
+            90 => [0.0, sx, sy, 0.0, x0, y0],
+            180 => [-sx, 0.0, 0.0, sy, x1, y0],
+            270 => [0.0, -sx, -sy, 0.0, x1, y1],
+            _ => [sx, 0.0, 0.0, -sy, x0, y1],
+        }
+    }
+}
+
+// maps pixels of an image XObject, `width` × `height`, onto user space through the matrix it
+// was drawn with, which places the image in the unit square
+pub fn image_matrix(width: u32, height: u32, placement: &Matrix) -> Matrix {
+    let unit = [1.0 / width.max(1) as f32, 0.0, 0.0, -1.0 / height.max(1) as f32, 0.0, 1.0];
+    text_layer::multiply(&unit, placement)
+}
+
+pub fn from_glyphs(glyphs: &[Glyph]) -> PageLayout {
+    let words = group_words(glyphs);
+    let lines = group_lines(words);
+    PageLayout { blocks: group_blocks(lines) }
+}
+
+// OCR engines already group words into blocks and lines, only the coordinates need mapping
+pub fn from_ocr_words(words: &[OcrWord], pixels_to_page: &Matrix) -> PageLayout {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut previous: Option<(u32, u32, u32)> = None;
+
+    for ocr_word in words {
+        let (left, top) = (ocr_word.left as f32, ocr_word.top as f32);
+        let (right, bottom) = (left + ocr_word.width as f32, top + ocr_word.height as f32);
+        let word = Word {
+            text: ocr_word.text.clone(),
+            bbox: transform_box(pixels_to_page, [left, top, right, bottom]),
+            font_name: None,
+            font_size: None,
+            confidence: Some(round(ocr_word.confidence)),
+        };
+
+        let position = (ocr_word.block, ocr_word.paragraph, ocr_word.line);
+        match (previous, blocks.last_mut()) {
+            (Some(line), Some(block)) if line == position => {
+                if let Some(line) = block.lines.last_mut() {
+                    line.words.push(word);
+                }
+            }
+            (Some((block_num, paragraph, _)), Some(block)) if (block_num, paragraph) == (position.0, position.1) => {
+                block.lines.push(Line { bbox: word.bbox, words: vec![word] });
+            }
+            _ => blocks.push(Block {
+                bbox: word.bbox,
+                source: LayoutSource::Ocr,
+                lines: vec![Line { bbox: word.bbox, words: vec![word] }],
+            }),
+        }
+        previous = Some(position);
+    }
+
+    for block in blocks.iter_mut() {
+        for line in block.lines.iter_mut() {
+            line.bbox = union(line.words.iter().map(|word| word.bbox));
+        }
+        block.bbox = union(block.lines.iter().map(|line| line.bbox));
+    }
+    PageLayout { blocks }
+}
+
+// a run of glyphs, or of words, along one baseline
+struct Run {
+    words: Vec<Word>,
+    bbox: [f32; 4],
+    start: (f32, f32),
+    end: (f32, f32),
+    direction: (f32, f32),
+    font_size: f32,
+}
+
+impl Run {
+    // how far `point` is from the end of the run, along the baseline and across it
+    fn offset(&self, point: (f32, f32)) -> (f32, f32) {
+        let (dx, dy) = (point.0 - self.end.0, point.1 - self.end.1);
+        let along = dx * self.direction.0 + dy * self.direction.1;
+        let across = dy * self.direction.0 - dx * self.direction.1;
+        (along, across)
+    }
+
+    fn same_direction(&self, direction: (f32, f32)) -> bool {
+        self.direction.0 * direction.0 + self.direction.1 * direction.1 > 0.99
+    }
+
+    // extent along the baseline, for checking whether two lines sit above each other
+    fn span(&self) -> (f32, f32) {
+        let project = |point: (f32, f32)| point.0 * self.direction.0 + point.1 * self.direction.1;
+        (project(self.start), project(self.end))
+    }
+}
+
+fn group_words(glyphs: &[Glyph]) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut text = String::new();
+    // a space ends the word even when the next glyph is close enough to continue it
+    let mut split = true;
+
+    for glyph in glyphs {
+        if glyph.text.trim().is_empty() {
+            split = true;
+            continue;
+        }
+        let size = glyph.font_size.max(1.0);
+        let direction = glyph.direction();
+
+        let continues = !split && runs.last().is_some_and(|run| {
+            let (along, across) = run.offset(glyph.origin);
+            run.same_direction(direction)
+                && across.abs() < MAX_BASELINE_SHIFT * size
+                && along > -MAX_GLYPH_OVERLAP * size
+                && along < MAX_GLYPH_GAP * size
+        });
+        match runs.last_mut() {
+            Some(run) if continues => {
+                text.push_str(&glyph.text);
+                run.bbox = union([run.bbox, glyph.bbox]);
+                run.end = glyph.end;
+                if let Some(word) = run.words.last_mut() {
+                    word.text.clone_from(&text);
+                    word.bbox = round_box(run.bbox);
+                }
+            }
+            _ => {
+                text = glyph.text.clone();
+                runs.push(Run {
+                    words: vec![Word {
+                        text: text.clone(),
+                        bbox: round_box(glyph.bbox),
+                        font_name: glyph.font_name.clone(),
+                        font_size: Some(round(glyph.font_size)),
+                        confidence: None,
+                    }],
+                    bbox: glyph.bbox,
+                    start: glyph.origin,
+                    end: glyph.end,
+                    direction,
+                    font_size: size,
+                });
+            }
+        }
+        split = false;
+    }
+    runs
+}
+
+fn group_lines(words: Vec<Run>) -> Vec<Run> {
+    let mut lines: Vec<Run> = Vec::new();
+    for word in words {
+        let size = word.font_size;
+        let continues = lines.last().is_some_and(|line| {
+            let (along, across) = line.offset(word.start);
+            line.same_direction(word.direction)
+                && across.abs() < MAX_BASELINE_SHIFT * size
+                && along > -MAX_GLYPH_OVERLAP * size
+                && along < MAX_WORD_GAP * size
+        });
+        match lines.last_mut() {
+            Some(line) if continues => {
+                line.bbox = union([line.bbox, word.bbox]);
+                line.end = word.end;
+                line.font_size = line.font_size.max(size);
+                line.words.extend(word.words);
+            }
+            _ => lines.push(word),
+        }
+    }
+    lines
+}
+
+fn group_blocks(lines: Vec<Run>) -> Vec<Block> {
+    let mut blocks: Vec<(Block, Run)> = Vec::new();
+    for line in lines {
+        let continues = blocks.last().is_some_and(|(_, previous)| {
+            // the drop is measured perpendicular to the baseline, towards the bottom of the text
+            let (dx, dy) = (line.start.0 - previous.start.0, line.start.1 - previous.start.1);
+            let drop = dx * previous.direction.1 - dy * previous.direction.0;
+            let (start, end) = previous.span();
+            let (line_start, line_end) = line.span();
+            let ratio = line.font_size.max(previous.font_size) / line.font_size.min(previous.font_size);
+
+            previous.same_direction(line.direction)
+                && drop > 0.0
+                && drop < MAX_LINE_DROP * previous.font_size
+                && line_start < end
+                && line_end > start
+                && ratio <= MAX_SIZE_RATIO
+        });
+
+        let bbox = round_box(line.bbox);
+        let text_line = Line { bbox, words: line.words.clone() };
+        match blocks.last_mut() {
+            Some((block, previous)) if continues => {
+                block.bbox = union([block.bbox, bbox]);
+                block.lines.push(text_line);
+                *previous = line;
+            }
+            _ => blocks.push((Block { bbox, source: LayoutSource::TextLayer, lines: vec![text_line] }, line)),
+        }
+    }
+    blocks.into_iter().map(|(block, _)| block).collect()
+}
+
+fn transform_box(matrix: &Matrix, [x0, y0, x1, y1]: [f32; 4]) -> [f32; 4] {
+    let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| text_layer::apply(matrix, x, y));
+    round_box(union(corners.map(|(x, y)| [x, y, x, y])))
+}
+
+pub fn union(boxes: impl IntoIterator<Item = [f32; 4]>) -> [f32; 4] {
+    let joined = boxes.into_iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |a, b| {
+        [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+    });
+    if joined[0] > joined[2] { [0.0; 4] } else { joined }
+}
+
+// hundredths of a point are well below anything visible and keep the JSON readable
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+fn round_box(bbox: [f32; 4]) -> [f32; 4] {
+    bbox.map(round)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // glyphs half an em wide laid out from `x` along the baseline at `y`, spaces included
+    fn run(text: &str, x: f32, y: f32, size: f32) -> Vec<Glyph> {
+        let width = size / 2.0;
+        text.chars().enumerate().map(|(index, c)| {
+            let x = x + index as f32 * width;
+            Glyph {
+                text: c.to_string(),
+                origin: (x, y),
+                end: (x + width, y),
+                bbox: [x, y - size * 0.2, x + width, y + size * 0.8],
+                font_name: Some("Helvetica".to_string()),
+                font_size: size,
+            }
+        }).collect()
+    }
+
+    fn texts(layout: &PageLayout) -> Vec<String> {
+        layout.blocks.iter().map(Block::text).collect()
+    }
+
+    #[test]
+    fn groups_glyphs_into_words_lines_and_blocks() {
+        let glyphs = [
+            run("Hello world", 100.0, 700.0, 10.0),
+            run("second line", 100.0, 688.0, 10.0),
+            // too far below to belong to the paragraph above
+            run("Far away", 100.0, 600.0, 10.0),
+        ].concat();
+        let layout = from_glyphs(&glyphs);
+        assert_eq!(texts(&layout), vec!["Hello world\nsecond line", "Far away"]);
+
+        let first = &layout.blocks[0];
+        assert_eq!(first.source, LayoutSource::TextLayer);
+        assert_eq!(first.bbox, [100.0, 686.0, 155.0, 708.0]);
+        assert_eq!(first.lines[0].words[0].bbox, [100.0, 698.0, 125.0, 708.0]);
+        assert_eq!(first.lines[0].words[0].font_size, Some(10.0));
+    }
+
+    #[test]
+    fn splits_words_on_gaps_without_spaces() {
+        // 3pt is past the glyph gap of a 10pt font but well within the word gap
+        let glyphs = [run("ab", 100.0, 700.0, 10.0), run("cd", 113.0, 700.0, 10.0), run("far", 200.0, 700.0, 10.0)].concat();
+        let layout = from_glyphs(&glyphs);
+        assert_eq!(texts(&layout), vec!["ab cd", "far"]);
+        assert_eq!(layout.blocks[0].lines[0].words.len(), 2);
+    }
+
+    #[test]
+    fn starts_a_block_at_a_change_of_size_or_direction() {
+        let heading = run("Title", 100.0, 720.0, 20.0);
+        let body = run("Body text", 100.0, 700.0, 10.0);
+        let vertical: Vec<Glyph> = run("Up", 0.0, 0.0, 10.0).into_iter().map(|glyph| Glyph {
+            origin: (50.0, glyph.origin.0),
+            end: (50.0, glyph.end.0),
+            bbox: [42.0, glyph.origin.0, 52.0, glyph.end.0],
+            ..glyph
+        }).collect();
+        let layout = from_glyphs(&[heading, body, vertical].concat());
+        assert_eq!(texts(&layout), vec!["Title", "Body text", "Up"]);
+    }
+
+    #[test]
+    fn maps_render_pixels_onto_the_page_for_every_rotation() {
+        // a crop box off the origin, rendered at 2 pixels per point
+        let page_box = [10.0, 20.0, 610.0, 820.0];
+        let corners = |rotation: i64| {
+            let (width, height) = if matches!(rotation, 90 | 270) { (1600, 1200) } else { (1200, 1600) };
+            let matrix = PageFrame { page_box, rotation }.render_matrix(width, height);
+            // top left, top right and bottom left of the render
+            [(0.0, 0.0), (width as f32, 0.0), (0.0, height as f32)].map(|(x, y)| text_layer::apply(&matrix, x, y))
+        };
+        assert_eq!(corners(0), [(10.0, 820.0), (610.0, 820.0), (10.0, 20.0)]);
+        assert_eq!(corners(90), [(10.0, 20.0), (10.0, 820.0), (610.0, 20.0)]);
+        assert_eq!(corners(180), [(610.0, 20.0), (10.0, 20.0), (610.0, 820.0)]);
+        assert_eq!(corners(270), [(610.0, 820.0), (610.0, 20.0), (10.0, 820.0)]);
+    }
+
+    #[test]
+    fn keeps_the_grouping_of_ocr_words() {
+        let word = |text: &str, (block, paragraph, line): (u32, u32, u32), left: u32, top: u32| OcrWord {
+            text: text.to_string(), block, paragraph, line, left, top, width: 100, height: 20, confidence: 91.456,
+        };
+        let words = [
+            word("one", (1, 1, 1), 0, 0),
+            word("two", (1, 1, 1), 120, 0),
+            word("three", (1, 1, 2), 0, 30),
+            word("four", (2, 1, 1), 0, 200),
+        ];
+        // 2 pixels per point on a 612 × 792 page
+        let layout = from_ocr_words(&words, &[0.5, 0.0, 0.0, -0.5, 0.0, 792.0]);
+        assert_eq!(texts(&layout), vec!["one two\nthree", "four"]);
+        assert_eq!(layout.blocks[0].bbox, [0.0, 767.0, 110.0, 792.0]);
+        assert_eq!(layout.blocks[0].lines[0].words[0].confidence, Some(91.46));
+        assert!(layout.blocks.iter().all(|block| block.source == LayoutSource::Ocr));
+    }
+}
@@ -0,0 +1,286 @@
+use std::{collections::BTreeMap, path::Path};
+
+use lopdf::{encryption::{self, DecryptionError}, Document, Object, ObjectId, ObjectStream, Reader};
+
+use crate::{engine::{metadata::EncryptionInfo, render, repair}, types::extract_error::ExtractError};
+
+// lopdf cannot parse an encrypted object stream before the document is decrypted, and drops it
+// while loading, so encrypted documents are reloaded with their object streams disguised
+const HIDDEN_OBJECT_STREAM: &[u8] = b"PdfzObjStm";
+
+type ObjectFilter = fn(ObjectId, &mut Object) -> Option<(ObjectId, Object)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoaderKind {
+    Lopdf,
+    // lopdf after the cross-reference table was rebuilt from a scan of the file
+    LopdfRepaired,
+    // only pdfium could open the file, text and OCR come from pdfium alone
+    Pdfium,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LoaderInfo {
+    pub loader: LoaderKind,
+    pub repairs: Vec<String>,
+    // why the loaders tried before this one gave up
+    pub errors: Vec<String>,
+}
+
+pub enum LoadedDocument {
+    Lopdf { doc: Box<Document>, encryption: Option<EncryptionInfo> },
+    Pdfium { page_count: u32 },
+}
+
+impl LoadedDocument {
+    pub fn as_lopdf(&self) -> Option<&Document> {
+        match self {
+            LoadedDocument::Lopdf { doc, .. } => Some(doc),
+            LoadedDocument::Pdfium { .. } => None,
+        }
+    }
+
+    // page number -> page object id, which only lopdf can provide
+    pub fn pages(&self) -> BTreeMap<u32, Option<ObjectId>> {
+        match self {
+            LoadedDocument::Lopdf { doc, .. } => doc.get_pages().into_iter()
+                .map(|(page_num, page_id)| (page_num, Some(page_id)))
+                .collect(),
+            LoadedDocument::Pdfium { page_count } => (1..=*page_count).map(|page_num| (page_num, None)).collect(),
+        }
+    }
+}
+
+pub fn load_document(path: &Path, password: Option<&str>) -> Result<(LoadedDocument, LoaderInfo), Box<dyn std::error::Error + Send + Sync>> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Error reading PDF file: {}", e))?;
+    let mut errors = Vec::new();
+
+    match load_lopdf(&bytes, password) {
+        Ok((doc, encryption)) => {
+            return Ok((LoadedDocument::Lopdf { doc: Box::new(doc), encryption }, loader_info(LoaderKind::Lopdf, vec![], errors)));
+        }
+        Err(e) if is_password_error(e.as_ref()) => return Err(e),
+        Err(e) => {
+            println!("lopdf failed to load {:?}: {}, trying to repair it", path, e);
+            errors.push(format!("lopdf: {}", e));
+        }
+    }
+
+    match repair::rebuild_xref(&bytes) {
+        Some(mut repaired) => match load_lopdf(&repaired.bytes, password) {
+            Ok((mut doc, encryption)) => {
+                repaired.repairs.extend(repair::restore_catalog(&mut doc));
+                if !doc.get_pages().is_empty() {
+                    return Ok((LoadedDocument::Lopdf { doc: Box::new(doc), encryption }, loader_info(LoaderKind::LopdfRepaired, repaired.repairs, errors)));
+                }
+                errors.push("lopdf (repaired): no pages found".to_string());
+            }
+            Err(e) if is_password_error(e.as_ref()) => return Err(e),
+            Err(e) => errors.push(format!("lopdf (repaired): {}", e)),
+        },
+        None => errors.push("repair: no PDF objects found in the file".to_string()),
+    }
+
+    println!("Falling back to pdfium for {:?}", path);
+    match render::page_count(path, password) {
+        Ok(page_count) => Ok((LoadedDocument::Pdfium { page_count }, loader_info(LoaderKind::Pdfium, vec![], errors))),
+        Err(e) => {
+            errors.push(format!("pdfium: {}", e));
+            Err(format!("Error loading PDF file: {}", errors.join("; ")).into())
+        }
+    }
+}
+
+// wrong or missing passwords will not get better with another loader, unsupported encryption might
+fn is_password_error(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(error.downcast_ref::<ExtractError>(), Some(ExtractError::PasswordRequired | ExtractError::WrongPassword))
+}
+
+fn loader_info(loader: LoaderKind, repairs: Vec<String>, errors: Vec<String>) -> LoaderInfo {
+    LoaderInfo { loader, repairs, errors }
+}
+
+fn load_lopdf(bytes: &[u8], password: Option<&str>) -> Result<(Document, Option<EncryptionInfo>), Box<dyn std::error::Error + Send + Sync>> {
+    // a failed plain load can also be an encrypted object stream holding part of the page tree
+    let load_error = match read(bytes, None) {
+        Ok(doc) if !doc.is_encrypted() => return Ok((doc, None)),
+        Ok(_) => None,
+        Err(e) => Some(e),
+    };
+
+    let mut doc = match read(bytes, Some(hide_object_streams)) {
+        Ok(doc) if doc.is_encrypted() => doc,
+        _ => {
+            let reason = load_error.map_or_else(|| "failed to reload the encrypted document".to_string(), |e| e.to_string());
+            return Err(reason.into());
+        }
+    };
+
+    println!("Document is encrypted, decrypting");
+    let encryption = EncryptionInfo::from_lopdf(&doc);
+    decrypt(&mut doc, password)?;
+    expand_object_streams(&mut doc);
+    Ok((doc, encryption))
+}
+
+fn read(bytes: &[u8], filter: Option<ObjectFilter>) -> lopdf::Result<Document> {
+    Reader { buffer: bytes, document: Document::new() }.read(filter)
+}
+
+fn decrypt(doc: &mut Document, password: Option<&str>) -> Result<(), ExtractError> {
+    // documents protected with only an owner password open with an empty user password
+    match decrypt_with(doc, "") {
+        Ok(()) => return Ok(()),
+        Err(lopdf::Error::Decryption(DecryptionError::IncorrectPassword)) => {}
+        Err(e) => return Err(ExtractError::UnsupportedEncryption(e.to_string())),
+    }
+
+    let Some(password) = password else {
+        return Err(ExtractError::PasswordRequired);
+    };
+    match decrypt_with(doc, password) {
+        Ok(()) => Ok(()),
+        Err(lopdf::Error::Decryption(DecryptionError::IncorrectPassword)) => Err(ExtractError::WrongPassword),
+        Err(e) => Err(ExtractError::UnsupportedEncryption(e.to_string())),
+    }
+}
+
+// lopdf only decrypts strings and streams that are objects of their own and the values of the
+// Info dictionary, strings inside any other dictionary or array are decrypted here
+fn decrypt_with(doc: &mut Document, password: &str) -> lopdf::Result<()> {
+    let encrypt_id = doc.trailer.get(b"Encrypt").and_then(Object::as_reference).ok();
+    let info_id = doc.trailer.get(b"Info").and_then(Object::as_reference).ok();
+    let key = encryption::get_encryption_key(doc, password, true)?;
+    doc.decrypt(password)?;
+
+    for (id, object) in doc.objects.iter_mut() {
+        // strings in cross-reference stream dictionaries are never encrypted
+        if Some(*id) == encrypt_id || object.type_name().is_ok_and(|name| name == "XRef") {
+            continue;
+        }
+        let values: Vec<&mut Object> = match object {
+            Object::Dictionary(dict) => dict.iter_mut().map(|(_, value)| value).collect(),
+            Object::Stream(stream) => stream.dict.iter_mut().map(|(_, value)| value).collect(),
+            Object::Array(items) => items.iter_mut().collect(),
+            _ => continue,
+        };
+        for value in values {
+            // lopdf has already done the strings directly in the Info dictionary
+            if Some(*id) == info_id && matches!(value, Object::String(..)) {
+                continue;
+            }
+            decrypt_strings(&key, *id, value);
+        }
+    }
+    Ok(())
+}
+
+fn decrypt_strings(key: &[u8], id: ObjectId, object: &mut Object) {
+    match object {
+        Object::String(..) => {
+            if let Ok(decrypted) = encryption::decrypt_object(key, id, object) {
+                if let Object::String(content, _) = object {
+                    *content = decrypted;
+                }
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| decrypt_strings(key, id, item)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| decrypt_strings(key, id, value)),
+        _ => {}
+    }
+}
+
+fn hide_object_streams(id: ObjectId, object: &mut Object) -> Option<(ObjectId, Object)> {
+    if let Ok(stream) = object.as_stream_mut() {
+        if stream.dict.type_is(b"ObjStm") {
+            stream.dict.set("Type", Object::Name(HIDDEN_OBJECT_STREAM.to_vec()));
+        }
+    }
+    Some((id, object.clone()))
+}
+
+fn expand_object_streams(doc: &mut Document) {
+    let hidden: Vec<ObjectId> = doc.objects.iter()
+        .filter(|(_, object)| object.as_stream().is_ok_and(|stream| stream.dict.type_is(HIDDEN_OBJECT_STREAM)))
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in hidden {
+        let Some(Object::Stream(stream)) = doc.objects.get_mut(&id) else {
+            continue;
+        };
+        stream.dict.set("Type", Object::Name(b"ObjStm".to_vec()));
+        let mut stream = stream.clone();
+        match ObjectStream::new(&mut stream) {
+            Ok(object_stream) => {
+                // like lopdf, objects stored directly in the file win over object stream copies
+                for (object_id, object) in object_stream.objects {
+                    doc.objects.entry(object_id).or_insert(object);
+                }
+            }
+            Err(e) => println!("Failed to expand object stream {:?}: {}", id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use lopdf::{dictionary, StringFormat};
+
+    fn literal(text: &str) -> Object {
+        Object::String(text.as_bytes().to_vec(), StringFormat::Literal)
+    }
+
+    // a 40 bit RC4 document whose Encrypt dictionary has no /U, so any password opens it; RC4
+    // is its own inverse, which lets decrypt_object encrypt the strings
+    pub fn encrypted_pdf() -> Vec<u8> {
+        let mut doc = Document::with_version("1.4");
+        doc.objects.insert((1, 0), Object::Dictionary(dictionary! { "Type" => "Catalog", "Pages" => (2, 0), "Outlines" => (4, 0) }));
+        doc.objects.insert((2, 0), Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![(3, 0).into()], "Count" => 1 }));
+        doc.objects.insert((4, 0), Object::Dictionary(dictionary! { "Type" => "Outlines", "First" => (5, 0), "Last" => (5, 0), "Count" => 1 }));
+        doc.objects.insert((7, 0), Object::Dictionary(dictionary! {
+            "Filter" => "Standard", "V" => 1, "R" => 2, "P" => -4,
+            "O" => Object::String(vec![0x5A; 32], StringFormat::Hexadecimal),
+        }));
+        doc.trailer.set("Root", (1, 0));
+        doc.trailer.set("Info", (6, 0));
+        doc.trailer.set("Encrypt", (7, 0));
+        doc.trailer.set("ID", vec![Object::String(vec![1; 16], StringFormat::Hexadecimal), Object::String(vec![2; 16], StringFormat::Hexadecimal)]);
+        doc.max_id = 7;
+
+        let key = encryption::get_encryption_key(&doc, "", false).unwrap();
+        let encrypt = |id: ObjectId, text: &str| {
+            Object::String(encryption::decrypt_object(&key, id, &literal(text)).unwrap(), StringFormat::Hexadecimal)
+        };
+        let annotation = dictionary! { "Type" => "Annot", "Subtype" => "Text", "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()], "Contents" => encrypt((3, 0), "A note") };
+        doc.objects.insert((3, 0), Object::Dictionary(dictionary! {
+            "Type" => "Page", "Parent" => (2, 0), "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Annots" => vec![Object::Dictionary(annotation)],
+        }));
+        doc.objects.insert((5, 0), Object::Dictionary(dictionary! { "Title" => encrypt((5, 0), "Chapter 1"), "Parent" => (4, 0), "Dest" => vec![(3, 0).into(), "Fit".into()] }));
+        doc.objects.insert((6, 0), Object::Dictionary(dictionary! { "Title" => encrypt((6, 0), "Annual report"), "Keywords" => vec![encrypt((6, 0), "finance")] }));
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn decrypts_strings_inside_dictionaries_and_arrays() {
+        let (doc, encryption) = load_lopdf(&encrypted_pdf(), None).unwrap();
+        assert!(encryption.is_some());
+
+        let title = |id: ObjectId| doc.get_dictionary(id).unwrap().get(b"Title").unwrap().as_str().unwrap().to_vec();
+        assert_eq!(title((5, 0)), b"Chapter 1");
+        assert_eq!(title((6, 0)), b"Annual report");
+        let keywords = doc.get_dictionary((6, 0)).unwrap().get(b"Keywords").unwrap().as_array().unwrap();
+        assert_eq!(keywords[0].as_str().unwrap(), b"finance");
+
+        let annots = doc.get_dictionary((3, 0)).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annots[0].as_dict().unwrap().get(b"Contents").unwrap().as_str().unwrap(), b"A note");
+        // the Encrypt dictionary is left as it was
+        assert_eq!(doc.get_dictionary((7, 0)).unwrap().get(b"O").unwrap().as_str().unwrap(), &[0x5A; 32]);
+    }
+}
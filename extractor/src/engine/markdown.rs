@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+
+use crate::{engine::layout::{Block, LayoutSource, Line, Word}, types::engine_handler::{DocumentExtractInfo, PageExtractInfo, TextSource}};
+
+// Markdown for consumers that want structure rather than one string per page. Headings come from
+// the font size and weight of the text layer and from the line height of OCR output, bullet and
+// numbered lines become lists and rows of short, aligned cells become pipe tables. Headers and
+// footers are left out, pages are separated by an HTML comment naming the page.
+
+// a block this much larger than the body text is a heading
+const MIN_HEADING_RATIO: f32 = 1.15;
+const MAX_HEADING_LINES: usize = 2;
+const MAX_HEADING_WORDS: usize = 20;
+// heading sizes closer than this share a level
+const HEADING_SIZE_STEP: f32 = 0.5;
+const MAX_HEADING_LEVEL: usize = 6;
+// relative to the font size, how far the last line of a paragraph ends before the right edge and
+// how far the first line of the next one is indented
+const PARAGRAPH_END_GAP: f32 = 2.0;
+const MIN_INDENT: f32 = 0.5;
+// a gap between words this many line heights wide separates two table cells
+const MIN_CELL_GAP: f32 = 2.0;
+const MIN_TABLE_ROWS: usize = 3;
+// table cells hold a few words, lines of text columns side by side hold many more
+const MAX_CELL_WORDS: f32 = 6.0;
+const BULLETS: [char; 9] = ['•', '◦', '▪', '‣', '○', '■', '·', '-', '*'];
+// "cmbx" is the bold Computer Modern of TeX documents
+const BOLD_FONTS: [&str; 6] = ["bold", "black", "heavy", "semibold", "demi", "cmbx"];
+const MONOSPACE_FONTS: [&str; 4] = ["mono", "courier", "consol", "cmtt"];
+
+// body and heading sizes of one layout source, points of font size for the text layer and
+// points of line height for OCR
+struct Styles {
+    body: f32,
+    // largest first, the index is the heading level
+    headings: Vec<f32>,
+}
+
+enum ListMarker {
+    Bullet,
+    Number(u32),
+}
+
+struct Cell {
+    text: String,
+    words: usize,
+    bbox: [f32; 4],
+}
+
+struct Row {
+    top: f32,
+    bottom: f32,
+    cells: Vec<Cell>,
+    lines: Vec<(usize, usize)>,
+}
+
+struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+pub fn render(document: &DocumentExtractInfo) -> String {
+    let text_styles = styles(&document.pages, LayoutSource::TextLayer);
+    let ocr_styles = styles(&document.pages, LayoutSource::Ocr);
+
+    let mut parts = Vec::new();
+    for page in &document.pages {
+        parts.push(format!("<!-- page {} -->", page.page_num));
+        parts.extend(render_page(page, &text_styles, &ocr_styles));
+    }
+    let mut markdown = parts.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+fn render_page(page: &PageExtractInfo, text_styles: &Styles, ocr_styles: &Styles) -> Vec<String> {
+    let blocks = page.layout.as_ref().map(|layout| layout.blocks.as_slice()).unwrap_or_default();
+    if blocks.is_empty() {
+        return page.full_text.split("\n\n").filter_map(|paragraph| render_lines(&paragraph.lines().collect::<Vec<_>>())).collect();
+    }
+
+    let (tables, table_lines) = find_tables(blocks);
+    let mut rendered_tables = vec![false; tables.len()];
+    let mut parts = Vec::new();
+    for (block_index, block) in blocks.iter().enumerate() {
+        let page_styles = match block.source {
+            LayoutSource::TextLayer => text_styles,
+            LayoutSource::Ocr => ocr_styles,
+        };
+        // tables cut through blocks, the lines around one are rendered on their own
+        let mut run: Vec<&Line> = Vec::new();
+        for (line_index, line) in block.lines.iter().enumerate() {
+            let Some(table) = table_lines.get(&(block_index, line_index)) else {
+                run.push(line);
+                continue;
+            };
+            parts.extend(render_run(&run, block.source, page_styles));
+            run.clear();
+            // a table goes where the first of its lines is read
+            if !rendered_tables[*table] {
+                rendered_tables[*table] = true;
+                parts.push(render_table(&tables[*table]));
+            }
+        }
+        parts.extend(render_run(&run, block.source, page_styles));
+    }
+
+    // engines without word positions leave their text out of the layout
+    let has_ocr_layout = blocks.iter().any(|block| block.source == LayoutSource::Ocr);
+    if matches!(page.source, Some(TextSource::Ocr) | Some(TextSource::Merged)) && !has_ocr_layout {
+        for image in &page.images {
+            parts.extend(image.text.split("\n\n").filter_map(|paragraph| render_lines(&paragraph.lines().collect::<Vec<_>>())));
+        }
+    }
+    parts
+}
+
+fn render_run(lines: &[&Line], source: LayoutSource, page_styles: &Styles) -> Vec<String> {
+    // a heading set in its own size or weight is often grouped with the paragraph under it
+    let mut groups: Vec<Vec<&Line>> = Vec::new();
+    let mut previous_style = None;
+    let right = lines.iter().map(|line| line.bbox[2]).fold(f32::MIN, f32::max);
+    for line in lines {
+        let style = (round_size(line_size(line, source)), line.words.iter().all(is_bold));
+        match groups.last_mut() {
+            Some(group) if previous_style == Some(style) && !starts_paragraph(group[group.len() - 1], line, right, style.0) => group.push(line),
+            _ => groups.push(vec![line]),
+        }
+        previous_style = Some(style);
+    }
+
+    let mut parts = Vec::new();
+    for group in groups {
+        if let Some(level) = heading_level(&group, source, page_styles) {
+            let text = group.iter().map(|line| line.text()).collect::<Vec<_>>().join(" ");
+            parts.push(format!("{} {}", "#".repeat(level), text.trim()));
+            continue;
+        }
+        parts.extend(render_lines(&group.iter().map(|line| line.text()).collect::<Vec<_>>()));
+    }
+    parts
+}
+
+// the line before ends short of the right edge, after a full stop or with this line indented
+fn starts_paragraph(previous: &Line, line: &Line, right: f32, size: f32) -> bool {
+    let short = previous.bbox[2] < right - PARAGRAPH_END_GAP * size;
+    let indented = line.bbox[0] > previous.bbox[0] + MIN_INDENT * size;
+    let sentence_end = previous.text().trim_end().ends_with(['.', ':', '!', '?']);
+    short && (indented || sentence_end)
+}
+
+// a paragraph, followed by list items when some of the lines start with a bullet or a number
+fn render_lines<S: AsRef<str>>(lines: &[S]) -> Option<String> {
+    let lines: Vec<&str> = lines.iter().map(|line| line.as_ref().trim()).filter(|line| !line.is_empty()).collect();
+    let markers: Vec<Option<(ListMarker, &str)>> = lines.iter().map(|line| list_marker(line)).collect();
+    let numbers: Vec<Option<u32>> = markers.iter()
+        .map(|marker| match marker {
+            Some((ListMarker::Number(number), _)) => Some(*number),
+            _ => None,
+        })
+        .collect();
+
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut items: Vec<(ListMarker, Vec<&str>)> = Vec::new();
+    for (index, (line, marker)) in lines.into_iter().zip(markers).enumerate() {
+        // a number on its own is more often a year, an amount or a footnote than a list item, a
+        // list needs the number before or after it as well
+        let marker = marker.filter(|(marker, _)| match marker {
+            ListMarker::Bullet => true,
+            ListMarker::Number(number) => {
+                let previous = numbers[..index].iter().rev().flatten().next();
+                let next = numbers[index + 1..].iter().flatten().next();
+                previous.is_some_and(|previous| previous + 1 == *number) || next.is_some_and(|next| *next == number + 1)
+            }
+        });
+        match marker {
+            Some((marker, rest)) => items.push((marker, vec![rest])),
+            None => match items.last_mut() {
+                Some((_, item)) => item.push(line),
+                None => paragraph.push(line),
+            },
+        }
+    }
+
+    let mut parts = Vec::new();
+    if !paragraph.is_empty() {
+        parts.push(escape_line_start(&paragraph.join(" ")));
+    }
+    if !items.is_empty() {
+        let list = items.into_iter()
+            .map(|(marker, item)| match marker {
+                ListMarker::Bullet => format!("- {}", item.join(" ")),
+                ListMarker::Number(number) => format!("{}. {}", number, item.join(" ")),
+            })
+            .collect::<Vec<_>>();
+        parts.push(list.join("\n"));
+    }
+    Some(parts.join("\n\n")).filter(|text| !text.is_empty())
+}
+
+fn list_marker(line: &str) -> Option<(ListMarker, &str)> {
+    let mut chars = line.chars();
+    let first = chars.next()?;
+    if BULLETS.contains(&first) {
+        let rest = chars.as_str();
+        // "-" and "*" also start negative numbers and emphasis, so they need a space behind them
+        let spaced = rest.starts_with(char::is_whitespace);
+        if (spaced || !matches!(first, '-' | '*')) && !rest.trim().is_empty() {
+            return Some((ListMarker::Bullet, rest.trim_start()));
+        }
+        return None;
+    }
+
+    // "1.", "2)" and "(3)"
+    let (token, rest) = line.split_once(char::is_whitespace)?;
+    let number = token.trim_start_matches('(').trim_end_matches(['.', ')']);
+    let marked = token.len() > number.len() && (token.ends_with('.') || token.ends_with(')'));
+    if marked && !number.is_empty() && number.len() <= 3 && number.chars().all(|c| c.is_ascii_digit()) && !rest.trim().is_empty() {
+        return Some((ListMarker::Number(number.parse().ok()?), rest.trim_start()));
+    }
+    None
+}
+
+// text starting like a heading, quote or list would otherwise be read as one
+fn escape_line_start(text: &str) -> String {
+    if text.starts_with(['#', '>', '+', '=', '|']) {
+        return format!("\\{}", text);
+    }
+    // "- ", "* " and rules like "---"
+    let mut chars = text.chars();
+    if let Some(first @ ('-' | '*')) = chars.next() {
+        if chars.next().map_or(true, |next| next.is_whitespace() || next == first) {
+            return format!("\\{}", text);
+        }
+    }
+    // "2024. " and "3) "
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = &text[digits..];
+    if digits > 0 && rest.starts_with(['.', ')']) && rest[1..].chars().next().map_or(true, char::is_whitespace) {
+        return format!("{}\\{}", &text[..digits], rest);
+    }
+    text.to_string()
+}
+
+fn heading_level(lines: &[&Line], source: LayoutSource, page_styles: &Styles) -> Option<usize> {
+    let words: Vec<&Word> = lines.iter().flat_map(|line| line.words.iter()).collect();
+    let text = lines.iter().map(|line| line.text()).collect::<Vec<_>>().join(" ");
+    if lines.len() > MAX_HEADING_LINES || words.len() > MAX_HEADING_WORDS || page_styles.body <= 0.0
+        || !text.chars().any(char::is_alphabetic) || list_marker(&text).is_some()
+    {
+        return None;
+    }
+
+    let size = lines.iter().map(|line| line_size(line, source)).sum::<f32>() / lines.len() as f32;
+    if size >= page_styles.body * MIN_HEADING_RATIO {
+        let rounded = round_size(size);
+        let level = page_styles.headings.iter().position(|heading| *heading <= rounded).unwrap_or(page_styles.headings.len().saturating_sub(1));
+        return Some((level + 1).min(MAX_HEADING_LEVEL));
+    }
+    // a bold line in body size, as used for the lowest level of headings. Bold code is not one
+    let bold = source == LayoutSource::TextLayer && words.iter().all(|word| is_bold(word) && !is_monospace(word));
+    if bold && lines.len() == 1 && round_size(size) >= round_size(page_styles.body) && !text.trim_end().ends_with(['.', ':', ',', ';']) {
+        return Some((page_styles.headings.len() + 1).min(MAX_HEADING_LEVEL));
+    }
+    None
+}
+
+fn styles(pages: &[PageExtractInfo], source: LayoutSource) -> Styles {
+    let blocks: Vec<&Block> = pages.iter()
+        .filter_map(|page| page.layout.as_ref())
+        .flat_map(|layout| layout.blocks.iter())
+        .filter(|block| block.source == source)
+        .collect();
+
+    // the body size is the one most of the text is set in
+    let mut sizes: Vec<(f32, usize)> = blocks.iter()
+        .flat_map(|block| block.lines.iter())
+        .map(|line| (line_size(line, source), line.text().chars().count()))
+        .collect();
+    sizes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: usize = sizes.iter().map(|(_, chars)| chars).sum();
+    let mut counted = 0;
+    let body = sizes.iter()
+        .find(|(_, chars)| {
+            counted += chars;
+            counted * 2 >= total
+        })
+        .map_or(0.0, |(size, _)| *size);
+
+    let mut headings: Vec<f32> = blocks.iter()
+        .filter(|block| block.lines.len() <= MAX_HEADING_LINES)
+        .map(|block| block.lines.iter().map(|line| line_size(line, source)).sum::<f32>() / block.lines.len().max(1) as f32)
+        .filter(|size| *size >= body * MIN_HEADING_RATIO)
+        .map(round_size)
+        .collect();
+    headings.sort_by(|a, b| b.total_cmp(a));
+    headings.dedup();
+    Styles { body, headings }
+}
+
+fn line_size(line: &Line, source: LayoutSource) -> f32 {
+    let height = line.bbox[3] - line.bbox[1];
+    match source {
+        LayoutSource::TextLayer => line.words.iter().filter_map(|word| word.font_size).reduce(f32::max).unwrap_or(height),
+        LayoutSource::Ocr => height,
+    }
+}
+
+fn round_size(size: f32) -> f32 {
+    (size / HEADING_SIZE_STEP).round() * HEADING_SIZE_STEP
+}
+
+fn is_bold(word: &Word) -> bool {
+    font_name_contains(word, &BOLD_FONTS)
+}
+
+fn is_monospace(word: &Word) -> bool {
+    font_name_contains(word, &MONOSPACE_FONTS)
+}
+
+fn font_name_contains(word: &Word, parts: &[&str]) -> bool {
+    word.font_name.as_deref()
+        .map(str::to_lowercase)
+        .is_some_and(|name| parts.iter().any(|part| name.contains(part)))
+}
+
+// rows are lines of the page side by side, a table is a run of rows with the same number of
+// cells in the same columns
+fn find_tables(blocks: &[Block]) -> (Vec<Table>, HashMap<(usize, usize), usize>) {
+    let mut lines: Vec<((usize, usize), &Line)> = blocks.iter().enumerate()
+        .flat_map(|(block_index, block)| block.lines.iter().enumerate().map(move |(line_index, line)| ((block_index, line_index), line)))
+        .collect();
+    lines.sort_by(|a, b| b.1.bbox[3].total_cmp(&a.1.bbox[3]));
+
+    let mut rows: Vec<Row> = Vec::new();
+    for (index, line) in lines {
+        let cells = split_cells(line);
+        let (top, bottom) = (line.bbox[3], line.bbox[1]);
+        let shares_row = rows.last().is_some_and(|row| {
+            let overlap = row.top.min(top) - row.bottom.max(bottom);
+            overlap > 0.5 * (row.top - row.bottom).min(top - bottom)
+        });
+        match rows.last_mut() {
+            Some(row) if shares_row => {
+                row.top = row.top.max(top);
+                row.bottom = row.bottom.min(bottom);
+                row.cells.extend(cells);
+                row.lines.push(index);
+            }
+            _ => rows.push(Row { top, bottom, cells, lines: vec![index] }),
+        }
+    }
+    for row in rows.iter_mut() {
+        row.cells.sort_by(|a, b| a.bbox[0].total_cmp(&b.bbox[0]));
+    }
+
+    let mut tables = Vec::new();
+    let mut table_lines = HashMap::new();
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = start + 1;
+        while end < rows.len() && continues_table(&rows[end - 1], &rows[end]) {
+            end += 1;
+        }
+        let run = &rows[start..end];
+        let cells: Vec<&Cell> = run.iter().flat_map(|row| row.cells.iter()).collect();
+        let words_per_cell = cells.iter().map(|cell| cell.words).sum::<usize>() as f32 / cells.len().max(1) as f32;
+        if run.len() >= MIN_TABLE_ROWS && run[0].cells.len() >= 2 && words_per_cell <= MAX_CELL_WORDS {
+            for row in run {
+                for line in &row.lines {
+                    table_lines.insert(*line, tables.len());
+                }
+            }
+            tables.push(Table {
+                rows: run.iter().map(|row| row.cells.iter().map(|cell| cell.text.clone()).collect()).collect(),
+            });
+        }
+        start = end;
+    }
+    (tables, table_lines)
+}
+
+fn continues_table(previous: &Row, row: &Row) -> bool {
+    previous.cells.len() >= 2
+        && previous.cells.len() == row.cells.len()
+        && previous.cells.iter().zip(&row.cells).all(|(a, b)| a.bbox[2].min(b.bbox[2]) > a.bbox[0].max(b.bbox[0]))
+}
+
+fn split_cells(line: &Line) -> Vec<Cell> {
+    let height = (line.bbox[3] - line.bbox[1]).max(1.0);
+    let mut cells: Vec<(Vec<&str>, [f32; 4])> = Vec::new();
+    for word in &line.words {
+        match cells.last_mut() {
+            Some((words, bbox)) if word.bbox[0] - bbox[2] < MIN_CELL_GAP * height => {
+                words.push(&word.text);
+                bbox[2] = bbox[2].max(word.bbox[2]);
+            }
+            _ => cells.push((vec![&word.text], word.bbox)),
+        }
+    }
+    cells.into_iter()
+        .map(|(words, bbox)| Cell { text: words.join(" "), words: words.len(), bbox })
+        .collect()
+}
+
+fn render_table(table: &Table) -> String {
+    let columns = table.rows.first().map_or(0, Vec::len);
+    let row = |cells: &Vec<String>| format!("| {} |", cells.iter().map(|cell| cell.replace('|', "\\|")).collect::<Vec<_>>().join(" | "));
+    let mut rows = Vec::with_capacity(table.rows.len() + 1);
+    rows.extend(table.rows.first().map(row));
+    rows.push(format!("|{}", " --- |".repeat(columns)));
+    rows.extend(table.rows.iter().skip(1).map(row));
+    rows.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // words set from `x` along the line at `y`, each character half the font size wide
+    fn line(words: &[(&str, f32)], y: f32, size: f32, font: &str) -> Line {
+        let words: Vec<Word> = words.iter()
+            .map(|(text, x)| Word {
+                text: text.to_string(),
+                bbox: [*x, y, x + text.chars().count() as f32 * size / 2.0, y + size],
+                font_name: Some(font.to_string()),
+                font_size: Some(size),
+                confidence: None,
+            })
+            .collect();
+        let right = words.iter().map(|word| word.bbox[2]).fold(f32::MIN, f32::max);
+        Line { bbox: [words[0].bbox[0], y, right, y + size], words }
+    }
+
+    fn block(lines: Vec<Line>) -> Block {
+        let bbox = [
+            lines.iter().map(|line| line.bbox[0]).fold(f32::MAX, f32::min),
+            lines.iter().map(|line| line.bbox[1]).fold(f32::MAX, f32::min),
+            lines.iter().map(|line| line.bbox[2]).fold(f32::MIN, f32::max),
+            lines.iter().map(|line| line.bbox[3]).fold(f32::MIN, f32::max),
+        ];
+        Block { bbox, source: LayoutSource::TextLayer, lines }
+    }
+
+    fn marker(line: &str) -> Option<(String, &str)> {
+        list_marker(line).map(|(marker, rest)| match marker {
+            ListMarker::Bullet => ("-".to_string(), rest),
+            ListMarker::Number(number) => (number.to_string(), rest),
+        })
+    }
+
+    #[test]
+    fn finds_bullets_and_numbers_at_the_start_of_a_line() {
+        assert_eq!(marker("• first"), Some(("-".to_string(), "first")));
+        assert_eq!(marker("- second"), Some(("-".to_string(), "second")));
+        assert_eq!(marker("1. one"), Some(("1".to_string(), "one")));
+        assert_eq!(marker("2) two"), Some(("2".to_string(), "two")));
+        assert_eq!(marker("(3) three"), Some(("3".to_string(), "three")));
+
+        assert_eq!(marker("-5 degrees"), None);
+        assert_eq!(marker("*emphasis*"), None);
+        assert_eq!(marker("2024. The year"), None);
+        assert_eq!(marker("3."), None);
+        assert_eq!(marker("v2. next"), None);
+    }
+
+    #[test]
+    fn keeps_lone_numbers_and_dashes_out_of_lists() {
+        assert_eq!(render_lines(&["1. one", "2. two", "wrapped"]).unwrap(), "1. one\n2. two wrapped");
+        assert_eq!(render_lines(&["Intro", "• a", "- b"]).unwrap(), "Intro\n\n- a\n- b");
+        // a number without a neighbour stays in the paragraph and is escaped there
+        assert_eq!(render_lines(&["3) Results were good."]).unwrap(), "3\\) Results were good.");
+        assert_eq!(render_lines(&["2024. A year", "of change"]).unwrap(), "2024\\. A year of change");
+        assert_eq!(render_lines(&["1. one", "5. five"]).unwrap(), "1\\. one 5. five");
+
+        assert_eq!(escape_line_start("-"), "\\-");
+        assert_eq!(escape_line_start("---"), "\\---");
+        assert_eq!(escape_line_start("* * *"), "\\* * *");
+        assert_eq!(escape_line_start("# tag"), "\\# tag");
+        assert_eq!(escape_line_start("-5 degrees"), "-5 degrees");
+        assert_eq!(escape_line_start("3.5 metres"), "3.5 metres");
+        assert_eq!(escape_line_start("42"), "42");
+    }
+
+    #[test]
+    fn levels_headings_by_size_and_weight() {
+        let styles = Styles { body: 10.0, headings: vec![18.0, 14.0] };
+        let level = |lines: &[Line]| heading_level(&lines.iter().collect::<Vec<_>>(), LayoutSource::TextLayer, &styles);
+
+        assert_eq!(level(&[line(&[("Title", 0.0)], 700.0, 18.0, "Helvetica")]), Some(1));
+        assert_eq!(level(&[line(&[("Section", 0.0)], 700.0, 14.0, "Helvetica")]), Some(2));
+        // between two heading sizes takes the level of the smaller one
+        assert_eq!(level(&[line(&[("Between", 0.0)], 700.0, 16.0, "Helvetica")]), Some(2));
+        // bold body text is the level below the smallest heading size
+        assert_eq!(level(&[line(&[("Summary", 0.0)], 700.0, 10.0, "Helvetica-Bold")]), Some(3));
+
+        assert_eq!(level(&[line(&[("Body", 0.0)], 700.0, 10.0, "Helvetica")]), None);
+        assert_eq!(level(&[line(&[("Ends", 0.0), ("here.", 30.0)], 700.0, 10.0, "Helvetica-Bold")]), None);
+        assert_eq!(level(&[line(&[("let", 0.0), ("x", 20.0)], 700.0, 10.0, "Courier-Bold")]), None);
+        assert_eq!(level(&[line(&[("1.", 0.0), ("Methods", 20.0)], 700.0, 18.0, "Helvetica")]), None);
+        assert_eq!(level(&[line(&[("2024", 0.0)], 700.0, 18.0, "Helvetica")]), None);
+        let tall = [
+            line(&[("One", 0.0)], 700.0, 18.0, "Helvetica"),
+            line(&[("Two", 0.0)], 680.0, 18.0, "Helvetica"),
+            line(&[("Three", 0.0)], 660.0, 18.0, "Helvetica"),
+        ];
+        assert_eq!(level(&tall), None);
+        // OCR output has no font names, weight alone makes no heading
+        let ocr = [line(&[("Summary", 0.0)], 700.0, 10.0, "Helvetica-Bold")];
+        assert_eq!(heading_level(&ocr.iter().collect::<Vec<_>>(), LayoutSource::Ocr, &styles), None);
+    }
+
+    #[test]
+    fn finds_tables_of_aligned_short_cells() {
+        let row = |cells: &[(&str, f32)], y: f32| line(cells, y, 10.0, "Helvetica");
+        let blocks = vec![
+            block(vec![row(&[("A paragraph above the table", 0.0)], 760.0)]),
+            // the first column and the second are read as blocks of their own
+            block(vec![row(&[("Name", 0.0)], 700.0), row(&[("Alice", 0.0)], 685.0), row(&[("Bob", 0.0)], 670.0)]),
+            block(vec![row(&[("Age", 200.0)], 700.0), row(&[("31", 200.0)], 685.0), row(&[("42", 200.0)], 670.0)]),
+        ];
+        let (tables, table_lines) = find_tables(&blocks);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].rows, vec![vec!["Name", "Age"], vec!["Alice", "31"], vec!["Bob", "42"]]);
+        assert_eq!(table_lines.len(), 6);
+        assert!(!table_lines.contains_key(&(0, 0)));
+        assert_eq!(render_table(&tables[0]), "| Name | Age |\n| --- | --- |\n| Alice | 31 |\n| Bob | 42 |");
+
+        // two rows are not enough
+        let (tables, _) = find_tables(&blocks[1..].iter().map(|block| Block { lines: block.lines[..2].to_vec(), ..block.clone() }).collect::<Vec<_>>());
+        assert!(tables.is_empty());
+
+        // columns of running text are side by side as well, but hold whole lines
+        let text = |x: f32, y: f32| line(&[("words", x), ("of", x + 30.0), ("a", x + 45.0), ("column", x + 55.0), ("of", x + 90.0), ("text", x + 105.0), ("here", x + 130.0)], y, 10.0, "Helvetica");
+        let columns = vec![
+            block(vec![text(0.0, 700.0), text(0.0, 685.0), text(0.0, 670.0)]),
+            block(vec![text(300.0, 700.0), text(300.0, 685.0), text(300.0, 670.0)]),
+        ];
+        let (tables, table_lines) = find_tables(&columns);
+        assert!(tables.is_empty());
+        assert!(table_lines.is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use std::path::Path;
+
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use pdfium_render::prelude::{PdfDocumentMetadataTagType, PdfDocumentVersion, PdfPageRenderRotation, PdfSecurityHandlerRevision};
+
+use crate::engine::{layout::PageFrame, loader::LoadedDocument, render};
+
+// Document level information that clients used to open every PDF a second time for. Pages are
+// described even when only a selection of them is extracted.
+
+const TEXT_SHOWING_OPERATORS: [&str; 4] = ["Tj", "TJ", "'", "\""];
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DocumentMetadata {
+    pub pdf_version: Option<String>,
+    pub page_count: u32,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    // ISO 8601, with the offset when the PDF date has one and as local time when it has none;
+    // the raw value when the date could not be parsed
+    pub creation_date: Option<String>,
+    pub modification_date: Option<String>,
+    // raw XMP packet from the catalog's /Metadata stream
+    pub xmp: Option<String>,
+    pub encryption: Option<EncryptionInfo>,
+    pub pages: Vec<PageMetadata>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EncryptionInfo {
+    pub filter: Option<String>,
+    pub version: Option<i64>,
+    pub revision: Option<i64>,
+    pub key_length: Option<i64>,
+    pub encrypt_metadata: Option<bool>,
+    pub permissions: Permissions,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Permissions {
+    pub print: bool,
+    pub print_high_quality: bool,
+    pub modify: bool,
+    pub copy: bool,
+    pub annotate: bool,
+    pub fill_forms: bool,
+    pub extract_for_accessibility: bool,
+    pub assemble: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PageMetadata {
+    pub page_num: u32,
+    // visible area in points, the crop box when there is one
+    pub width: f32,
+    pub height: f32,
+    pub rotation: i64,
+    pub has_text_layer: bool,
+}
+
+impl EncryptionInfo {
+    // the encryption dictionary has to be read before lopdf decrypts the document, which
+    // removes it from the trailer
+    pub fn from_lopdf(doc: &Document) -> Option<Self> {
+        let dict = doc.get_encrypted().ok()?;
+        let integer = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok();
+        // bit positions from table 22 of the PDF 1.7 specification
+        let flags = integer(b"P").unwrap_or(-1);
+        let allowed = |bit: u32| flags & (1 << (bit - 1)) != 0;
+
+        Some(Self {
+            filter: dict.get(b"Filter").and_then(Object::as_name_str).ok().map(str::to_string),
+            version: integer(b"V"),
+            revision: integer(b"R"),
+            key_length: integer(b"Length"),
+            encrypt_metadata: dict.get(b"EncryptMetadata").and_then(Object::as_bool).ok(),
+            permissions: Permissions {
+                print: allowed(3),
+                print_high_quality: allowed(3) && (integer(b"R").unwrap_or(2) < 3 || allowed(12)),
+                modify: allowed(4),
+                copy: allowed(5),
+                annotate: allowed(6),
+                fill_forms: allowed(6) || allowed(9),
+                extract_for_accessibility: allowed(5) || allowed(10),
+                assemble: allowed(4) || allowed(11),
+            },
+        })
+    }
+}
+
+pub fn read_metadata(doc: &LoadedDocument, pdf_path: &Path, password: Option<&str>) -> DocumentMetadata {
+    match doc {
+        LoadedDocument::Lopdf { doc, encryption } => from_lopdf(doc, encryption.clone()),
+        LoadedDocument::Pdfium { page_count } => from_pdfium(pdf_path, password).unwrap_or_else(|e| {
+            println!("Failed to read metadata with pdfium: {}", e);
+            DocumentMetadata { page_count: *page_count, ..Default::default() }
+        }),
+    }
+}
+
+fn from_lopdf(doc: &Document, encryption: Option<EncryptionInfo>) -> DocumentMetadata {
+    let info = doc.trailer.get(b"Info")
+        .and_then(|info| doc.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .ok();
+    let info_text = |key: &[u8]| info.and_then(|info| info.get(key).ok())
+        .and_then(|value| doc.dereference(value).ok())
+        .and_then(|(_, value)| decode_text_string(value).ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let pages: Vec<PageMetadata> = doc.get_pages().into_iter()
+        .map(|(page_num, page_id)| lopdf_page_metadata(doc, page_num, page_id))
+        .collect();
+
+    DocumentMetadata {
+        pdf_version: Some(catalog_version(doc).unwrap_or_else(|| doc.version.clone())),
+        page_count: pages.len() as u32,
+        title: info_text(b"Title"),
+        author: info_text(b"Author"),
+        subject: info_text(b"Subject"),
+        keywords: info_text(b"Keywords"),
+        creator: info_text(b"Creator"),
+        producer: info_text(b"Producer"),
+        creation_date: info_text(b"CreationDate").map(|date| normalize_pdf_date(&date)),
+        modification_date: info_text(b"ModDate").map(|date| normalize_pdf_date(&date)),
+        xmp: xmp_packet(doc),
+        encryption,
+        pages,
+    }
+}
+
+// a /Version in the catalog overrides the header when a file was upgraded by an incremental update
+fn catalog_version(doc: &Document) -> Option<String> {
+    let version = doc.catalog().ok()?.get(b"Version").and_then(Object::as_name_str).ok()?;
+    (version > doc.version.as_str()).then(|| version.to_string())
+}
+
+fn xmp_packet(doc: &Document) -> Option<String> {
+    let metadata = doc.catalog().ok()?.get(b"Metadata").ok()?;
+    let (_, metadata) = doc.dereference(metadata).ok()?;
+    let stream = metadata.as_stream().ok()?;
+    let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+    Some(String::from_utf8_lossy(&content).trim().to_string()).filter(|xmp| !xmp.is_empty())
+}
+
+fn lopdf_page_metadata(doc: &Document, page_num: u32, page_id: ObjectId) -> PageMetadata {
+    let PageFrame { page_box, rotation } = lopdf_page_frame(doc, page_id);
+    let has_text_layer = doc.get_and_decode_page_content(page_id)
+        .map(|content| content.operations.iter().any(|op| TEXT_SHOWING_OPERATORS.contains(&op.operator.as_str())))
+        .unwrap_or(false);
+
+    PageMetadata {
+        page_num,
+        width: (page_box[2] - page_box[0]).abs(),
+        height: (page_box[3] - page_box[1]).abs(),
+        rotation,
+        has_text_layer,
+    }
+}
+
+fn lopdf_page_frame(doc: &Document, page_id: ObjectId) -> PageFrame {
+    let page_box = inherited(doc, page_id, b"CropBox")
+        .or_else(|| inherited(doc, page_id, b"MediaBox"))
+        .and_then(|page_box| page_box.as_array().ok().cloned())
+        .and_then(|page_box| page_box.iter().map(|n| n.as_float().ok()).collect::<Option<Vec<f32>>>())
+        .and_then(|page_box| <[f32; 4]>::try_from(page_box).ok())
+        // US letter is the default the specification gives for a missing MediaBox
+        .unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let rotation = inherited(doc, page_id, b"Rotate")
+        .and_then(|rotation| rotation.as_i64().ok())
+        .unwrap_or(0)
+        .rem_euclid(360);
+
+    PageFrame {
+        page_box: [page_box[0].min(page_box[2]), page_box[1].min(page_box[3]), page_box[0].max(page_box[2]), page_box[1].max(page_box[3])],
+        rotation,
+    }
+}
+
+// the frame a page render maps back onto. pdfium only reports the size of the page as shown,
+// with the rotation already applied, and puts its origin at 0, 0
+pub fn page_frame(doc: &LoadedDocument, metadata: &DocumentMetadata, page_num: u32, page_id: Option<ObjectId>) -> Option<PageFrame> {
+    if let Some((doc, page_id)) = doc.as_lopdf().zip(page_id) {
+        return Some(lopdf_page_frame(doc, page_id));
+    }
+    let page = metadata.pages.iter().find(|page| page.page_num == page_num)?;
+    let (width, height) = if matches!(page.rotation, 90 | 270) { (page.height, page.width) } else { (page.width, page.height) };
+    Some(PageFrame { page_box: [0.0, 0.0, width, height], rotation: page.rotation })
+}
+
+// page attributes such as MediaBox and Rotate can be set on any ancestor in the page tree
+pub fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node: &Dictionary = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return doc.dereference(value).ok().map(|(_, value)| value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+fn from_pdfium(pdf_path: &Path, password: Option<&str>) -> Result<DocumentMetadata, Box<dyn std::error::Error + Send + Sync>> {
+    let document = render::open_document(pdf_path, password)?;
+    let tag = |tag_type: PdfDocumentMetadataTagType| document.metadata().get(tag_type)
+        .map(|tag| tag.value().trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let pages: Vec<PageMetadata> = document.pages().iter().enumerate()
+        .map(|(index, page)| PageMetadata {
+            page_num: index as u32 + 1,
+            width: page.width().value,
+            height: page.height().value,
+            rotation: match page.rotation() {
+                Ok(PdfPageRenderRotation::Degrees90) => 90,
+                Ok(PdfPageRenderRotation::Degrees180) => 180,
+                Ok(PdfPageRenderRotation::Degrees270) => 270,
+                _ => 0,
+            },
+            has_text_layer: page.text().is_ok_and(|text| !text.all().trim().is_empty()),
+        })
+        .collect();
+
+    let permissions = document.permissions();
+    // revisions 5 and 6 (AES-256) are not known to pdfium-render and come back as an error
+    let revision = match permissions.security_handler_revision() {
+        Ok(PdfSecurityHandlerRevision::Unprotected) => None,
+        Ok(PdfSecurityHandlerRevision::Revision2) => Some(Some(2)),
+        Ok(PdfSecurityHandlerRevision::Revision3) => Some(Some(3)),
+        Ok(PdfSecurityHandlerRevision::Revision4) => Some(Some(4)),
+        Err(_) => Some(None),
+    };
+    let encryption = revision.map(|revision| EncryptionInfo {
+        filter: None,
+        version: None,
+        revision,
+        key_length: None,
+        encrypt_metadata: None,
+        permissions: Permissions {
+            print: permissions.can_print_high_quality().unwrap_or(false) || permissions.can_print_only_low_quality().unwrap_or(false),
+            print_high_quality: permissions.can_print_high_quality().unwrap_or(false),
+            modify: permissions.can_modify_document_content().unwrap_or(false),
+            copy: permissions.can_extract_text_and_graphics().unwrap_or(false),
+            annotate: permissions.can_add_or_modify_text_annotations().unwrap_or(false),
+            fill_forms: permissions.can_fill_existing_interactive_form_fields().unwrap_or(false),
+            extract_for_accessibility: permissions.can_extract_text_and_graphics().unwrap_or(false),
+            assemble: permissions.can_assemble_document().unwrap_or(false),
+        },
+    });
+
+    Ok(DocumentMetadata {
+        pdf_version: pdfium_version(document.version()),
+        page_count: pages.len() as u32,
+        title: tag(PdfDocumentMetadataTagType::Title),
+        author: tag(PdfDocumentMetadataTagType::Author),
+        subject: tag(PdfDocumentMetadataTagType::Subject),
+        keywords: tag(PdfDocumentMetadataTagType::Keywords),
+        creator: tag(PdfDocumentMetadataTagType::Creator),
+        producer: tag(PdfDocumentMetadataTagType::Producer),
+        creation_date: tag(PdfDocumentMetadataTagType::CreationDate).map(|date| normalize_pdf_date(&date)),
+        modification_date: tag(PdfDocumentMetadataTagType::ModificationDate).map(|date| normalize_pdf_date(&date)),
+        xmp: None,
+        encryption,
+        pages,
+    })
+}
+
+fn pdfium_version(version: PdfDocumentVersion) -> Option<String> {
+    let version = match version {
+        PdfDocumentVersion::Unset => return None,
+        PdfDocumentVersion::Pdf1_0 => 10,
+        PdfDocumentVersion::Pdf1_1 => 11,
+        PdfDocumentVersion::Pdf1_2 => 12,
+        PdfDocumentVersion::Pdf1_3 => 13,
+        PdfDocumentVersion::Pdf1_4 => 14,
+        PdfDocumentVersion::Pdf1_5 => 15,
+        PdfDocumentVersion::Pdf1_6 => 16,
+        PdfDocumentVersion::Pdf1_7 => 17,
+        PdfDocumentVersion::Pdf2_0 => 20,
+        PdfDocumentVersion::Other(version) => version,
+    };
+    Some(format!("{}.{}", version / 10, version % 10))
+}
+
+// PDF dates look like `D:YYYYMMDDHHmmSSOHH'mm'` where everything after the year is optional
+fn normalize_pdf_date(raw: &str) -> String {
+    let date = raw.strip_prefix("D:").unwrap_or(raw);
+    let digits: String = date.chars().take_while(char::is_ascii_digit).collect();
+    if !(4..=14).contains(&digits.len()) || digits.len() % 2 != 0 {
+        return raw.to_string();
+    }
+
+    let field = |start: usize, default: &str| digits.get(start..start + 2).unwrap_or(default).to_string();
+    let (month, day) = (field(4, "01"), field(6, "01"));
+    let (hour, minute, second) = (field(8, "00"), field(10, "00"), field(12, "00"));
+
+    let offset = &date[digits.len()..];
+    let offset = match offset.chars().next() {
+        // without an offset the relation to UTC is unknown, so none is made up
+        None => String::new(),
+        Some('Z') => "Z".to_string(),
+        Some(sign @ ('+' | '-')) => {
+            let parts: Vec<&str> = offset[1..].split('\'').filter(|part| !part.is_empty()).collect();
+            let hours = parts.first().copied().unwrap_or("00");
+            let minutes = parts.get(1).copied().unwrap_or("00");
+            if !(hours.len() == 2 && minutes.len() == 2 && format!("{}{}", hours, minutes).chars().all(|c| c.is_ascii_digit())) {
+                return raw.to_string();
+            }
+            format!("{}{}:{}", sign, hours, minutes)
+        }
+        Some(_) => return raw.to_string(),
+    };
+
+    format!("{}-{}-{}T{}:{}:{}{}", &digits[..4], month, day, hour, minute, second, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_full_dates_with_offsets() {
+        assert_eq!(normalize_pdf_date("D:20240315093000+01'00'"), "2024-03-15T09:30:00+01:00");
+        assert_eq!(normalize_pdf_date("D:20240315093000-05'30"), "2024-03-15T09:30:00-05:30");
+        assert_eq!(normalize_pdf_date("D:20240315093000+02"), "2024-03-15T09:30:00+02:00");
+        assert_eq!(normalize_pdf_date("D:20240315093000Z"), "2024-03-15T09:30:00Z");
+        assert_eq!(normalize_pdf_date("D:20240315093000Z00'00'"), "2024-03-15T09:30:00Z");
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        assert_eq!(normalize_pdf_date("D:2024"), "2024-01-01T00:00:00");
+        assert_eq!(normalize_pdf_date("D:202403"), "2024-03-01T00:00:00");
+        assert_eq!(normalize_pdf_date("20240315"), "2024-03-15T00:00:00");
+        assert_eq!(normalize_pdf_date("D:2024031509"), "2024-03-15T09:00:00");
+    }
+
+    #[test]
+    fn keeps_dates_it_cannot_read() {
+        for raw in ["", "D:", "D:24", "D:20240", "D:2024031509300012", "March 2024", "D:20240315+1", "D:20240315+ab'cd'", "D:20240315 junk"] {
+            assert_eq!(normalize_pdf_date(raw), raw);
+        }
+    }
+}
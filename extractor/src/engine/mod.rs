@@ -1,14 +1,53 @@
-use std::{io::{Cursor, Read}, sync::Arc};
+use std::{collections::HashMap, future::Future, path::{Path, PathBuf}, pin::Pin, sync::{atomic::{AtomicU32, Ordering}, Arc}};
 
-use flate2::read::ZlibDecoder;
-use image::{ImageBuffer, ImageFormat, ImageReader, Rgba, RgbaImage};
-use lopdf::{xobject::PdfImage, Document};
+use futures::{stream, StreamExt, TryStreamExt};
+use lopdf::{xobject::PdfImage, ObjectId};
 use redis::Client;
 
-use crate::{helper::file_helper::{self, save_processed_json}, libs::redis::{mark_as_done, mark_as_failed, mark_progress}, types::engine_handler::{EngineHandler, PageExtractInfo}, worker::NewFileProcessQueue};
+use crate::{helper::{file_helper::{self, save_processed_json}, page_selection::{legacy_page_selection, parse_page_selection}}, libs::redis::{mark_as_done, mark_as_failed_with, mark_progress, Status}, types::{engine_handler::{AttachmentExtractInfo, DocumentExtractInfo, EngineHandler, ImageExtractInfo, ImageKind, PageExtractInfo, TextSource}, extract_error::ExtractError}, worker::{NewFileProcessQueue, OcrMode, OutputFormat}};
 
 pub mod tesseract;
+pub mod annotations;
+pub mod attachments;
+pub mod ccitt;
+pub mod color_space;
+pub mod forms;
+pub mod image_decode;
+pub mod jbig2;
+pub mod jpx;
+pub mod language;
+pub mod layout;
 pub mod ollama;
+pub mod loader;
+pub mod markdown;
+pub mod metadata;
+pub mod normalize;
+pub mod ocr_xml;
+pub mod outline;
+pub mod pdf_function;
+pub mod reading_order;
+pub mod render;
+pub mod repair;
+pub mod repeated_lines;
+pub mod searchable_pdf;
+pub mod text_layer;
+pub mod text_quality;
+
+use attachments::{AttachmentKind, EmbeddedFile};
+use layout::{PageFrame, PageLayout};
+use loader::LoadedDocument;
+use text_quality::TextQuality;
+
+type DocumentResult = Result<DocumentExtractInfo, Box<dyn std::error::Error + Send + Sync>>;
+
+// a file going through the extraction, either the upload itself or an attachment found in it
+pub struct DocumentSource {
+    pub path: PathBuf,
+    pub file_id: String,
+    pub password: Option<String>,
+    // 0 for the uploaded file, attachments are one level deeper than the document holding them
+    pub depth: u32,
+}
 
 pub struct MainEngine {
     pub message : NewFileProcessQueue, 
@@ -27,26 +66,67 @@ impl MainEngine {
     pub async fn run(&self){
         println!("Processing file: {}", self.message.file);
         let id = self.message.file.split('.').next().unwrap_or("");
+
+        // page results from an earlier run are only reusable if they were produced with the same options
+        if let Some(previous) = file_helper::load_job_checkpoint(id) {
+            if !previous.produces_same_pages(&self.message) {
+                println!("Discarding checkpoints of {} made with different options", id);
+                if let Err(e) = file_helper::clear_checkpoints(id) {
+                    eprintln!("Error clearing checkpoints: {}", e);
+                }
+            }
+        }
+        if let Err(e) = file_helper::save_job_checkpoint(&self.message, id) {
+            eprintln!("Error saving job checkpoint: {}", e);
+        }
+
         let result = self.extract_file(&self.message).await;
-        let result = match result {
-            Ok(res) => {
-                save_processed_json(res, id);
+        match result {
+            Ok(mut res) => {
+                if self.message.writes(OutputFormat::Markdown) {
+                    if let Err(e) = file_helper::save_processed_markdown(&markdown::render(&res), id) {
+                        eprintln!("Error saving Markdown: {}", e);
+                    }
+                }
+                if self.message.writes(OutputFormat::SearchablePdf) {
+                    let path = file_helper::get_upload_path(&self.message.file);
+                    let output = file_helper::get_processed_path(&format!("{}.pdf", id));
+                    match searchable_pdf::write_searchable_pdf(&path, self.message.password.as_deref(), self.message.decrypt_searchable_pdf, &res.pages, &output) {
+                        Ok(()) => println!("Searchable PDF saved to {:?}", output),
+                        Err(e) => eprintln!("Error saving searchable PDF: {}", e),
+                    }
+                }
+                // kept until now for the Markdown headings and tables and the searchable PDF text
+                if !self.message.layout {
+                    for page_info in res.pages.iter_mut() {
+                        page_info.layout = None;
+                    }
+                }
+                if self.message.writes(OutputFormat::Json) {
+                    save_processed_json(res, id);
+                }
+                if let Err(e) = file_helper::clear_checkpoints(id) {
+                    eprintln!("Error clearing checkpoints: {}", e);
+                }
                 if let Err(e) = mark_as_done(id).await {
                     eprintln!("Error marking as success: {}", e);
                 }
             },
             Err(e) => {
                 eprintln!("Error processing file: {}", e);
-                if let Err(e) = mark_as_failed(id).await {
+                if let Err(e) = file_helper::mark_job_checkpoint_failed(id) {
+                    eprintln!("Error updating job checkpoint: {}", e);
+                }
+                let status = e.downcast_ref::<ExtractError>().map_or(Status::Failed, ExtractError::status);
+                if let Err(e) = mark_as_failed_with(id, status, &e.to_string()).await {
                     eprintln!("Error marking as failed: {}", e);
                 }
-                return;
             }
         };
     }
 
 
-    async fn extract_file(&self, process_queue: &NewFileProcessQueue) -> Result<Vec<PageExtractInfo>, Box<dyn std::error::Error + Send + Sync>> {
+    async fn extract_file(&self, process_queue: &NewFileProcessQueue) -> Result<DocumentExtractInfo, Box<dyn std::error::Error + Send + Sync>> {
         println!("Extracting file {}", process_queue.file);
         let path = file_helper::get_upload_path(format!("{}", process_queue.file).as_str());
         println!("Processing {:?}", path);
@@ -55,159 +135,469 @@ impl MainEngine {
             return Err(format!("File does not exist: {:?}", path).into());
         }
 
-        let doc = Document::load(path).map_err(|e| format!("Error loading PDF file: {}", e))?;
-        let file_id = process_queue.file.split('.').next().unwrap_or("");
-        let mut all_page_info: Vec<PageExtractInfo> = Vec::new();
+        let source = DocumentSource {
+            path,
+            file_id: process_queue.file.split('.').next().unwrap_or("").to_string(),
+            password: process_queue.password.clone(),
+            depth: 0,
+        };
+        self.extract_document(&source).await
+    }
+
+    // boxed because attachments recurse into it
+    fn extract_document<'a>(&'a self, source: &'a DocumentSource) -> Pin<Box<dyn Future<Output = DocumentResult> + Send + 'a>> {
+        Box::pin(async move {
+            let process_queue = &self.message;
+            let path = &source.path;
+            let file_id = source.file_id.as_str();
+            let password = source.password.as_deref();
+            let top_level = source.depth == 0;
+
+            let (doc, loader_info) = loader::load_document(path, password)?;
+            println!("Loaded {:?} with {:?}", path, loader_info.loader);
+            let metadata = metadata::read_metadata(&doc, path, password);
+            let outline = outline::read_outline(&doc, path, password);
+            let document_pages = doc.pages();
+            let page_count = document_pages.len() as u32;
+            // the page selection refers to the uploaded file, attachments are extracted whole
+            let selection = match &process_queue.pages {
+                _ if !top_level => (1..=page_count).collect(),
+                Some(expression) => parse_page_selection(expression, page_count)?,
+                None => legacy_page_selection(process_queue.start_page, process_queue.page_count, page_count),
+            };
+            let selected_pages: Vec<(u32, Option<ObjectId>)> = selection.into_iter()
+                .filter_map(|page_num| document_pages.get(&page_num).map(|page_id| (page_num, *page_id)))
+                .collect();
+
+            let total = selected_pages.len() as u32;
+            let concurrency = process_queue.page_concurrency();
+            let completed = AtomicU32::new(0);
+            let mut checkpoints = if top_level { file_helper::load_page_checkpoints(file_id) } else { HashMap::new() };
+            println!("Extracting {} pages with concurrency {} ({} already checkpointed)", total, concurrency, checkpoints.len());
+
+            // `buffered` yields results in submission order, so the output stays sorted by page
+            // even though pages finish out of order
+            let all_page_info = stream::iter(selected_pages)
+                .map(|(page_num, page_id)| {
+                    let (doc, completed, metadata) = (&doc, &completed, &metadata);
+                    let checkpoint = checkpoints.remove(&page_num);
+                    async move {
+                        let page_info = match checkpoint {
+                            Some(page_info) => {
+                                println!("Reusing checkpoint for page {}", page_num);
+                                page_info
+                            }
+                            None => {
+                                println!("Extracting page {}", page_num);
+                                let frame = metadata::page_frame(doc, metadata, page_num, page_id);
+                                let page_info = self.process_page(source, doc, page_num, page_id, frame).await;
+                                println!("Extracted page {} with {:?} images", page_num, page_info);
+                                if top_level {
+                                    if let Err(e) = file_helper::save_page_checkpoint(file_id, &page_info) {
+                                        eprintln!("Error checkpointing page {}: {}", page_num, e);
+                                    }
+                                }
+                                page_info
+                            }
+                        };
+
+                        // progress is reported for the uploaded file only
+                        let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                        if top_level {
+                            mark_progress(file_id, done, total).await?;
+                        }
+                        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(page_info)
+                    }
+                })
+                .buffered(concurrency)
+                .try_collect::<Vec<PageExtractInfo>>()
+                .await?;
+
+            // document level structure is attached here rather than in process_page so checkpointed
+            // pages get it too. Forms, annotations and attachments are only read through lopdf
+            let (mut form_fields, mut page_annotations, embedded_files) = match doc.as_lopdf() {
+                Some(lopdf_doc) => {
+                    let page_nums: Vec<u32> = all_page_info.iter().map(|page_info| page_info.page_num).collect();
+                    (
+                        forms::fields_by_page(forms::read_form_fields(lopdf_doc)),
+                        annotations::read_annotations(lopdf_doc, path, password, &page_nums),
+                        attachments::list_attachments(lopdf_doc),
+                    )
+                }
+                None => Default::default(),
+            };
+            let mut all_page_info = all_page_info;
+            for page_info in all_page_info.iter_mut() {
+                page_info.sections = outline::sections_for_page(&outline, page_info.page_num);
+                page_info.form_fields = form_fields.remove(&page_info.page_num).unwrap_or_default();
+                page_info.annotations = page_annotations.remove(&page_info.page_num).unwrap_or_default();
+            }
+            // the layout is always collected since header and footer detection and the Markdown
+            // output work on it, but only kept in the JSON when asked for
+            repeated_lines::move_headers_and_footers(&mut all_page_info);
+            // after header and footer detection, which matches lines as they were extracted
+            for page_info in all_page_info.iter_mut() {
+                normalize::normalize_page(page_info, &process_queue.normalize);
+            }
+            if !process_queue.layout && !top_level {
+                for page_info in all_page_info.iter_mut() {
+                    page_info.layout = None;
+                }
+            }
+            drop(doc);
+
+            let mut attachment_info = Vec::new();
+            for (index, embedded_file) in embedded_files.into_iter().enumerate() {
+                attachment_info.push(self.extract_attachment(source, index, embedded_file).await);
+            }
         
-        // Get actual page count and determine the limit
-        let actual_page_count = doc.get_pages().len() as u32;
-        let mut page_limit = process_queue.page_count.min(actual_page_count);
-        if page_limit <= 0 {
-            page_limit = actual_page_count;
-        }
-        let page_limit = page_limit;
-        // Convert start_page to 0-based index if pages are 0-based
-        let start_page = process_queue.start_page.saturating_sub(1); 
-
-        for (page_num, page_id) in doc.get_pages() {
-            
-            // Skip pages before start_page
-            if page_num < start_page {
-                continue;
-            }
-            
-            // Break if we've processed enough pages
-            if all_page_info.len() >= page_limit as usize {
-                println!("Page limit reached: {}", page_limit);
-                break;
-            }
-            
-            println!("Extracting page {}", page_num);
-            let page_info = self.process_page(&doc, page_num, page_id).await;
-            println!("Extracted page {} with {:?} images", page_num, page_info);
-            
-            mark_progress(file_id, page_num, page_limit).await?;
-            all_page_info.push(page_info);
+            Ok(DocumentExtractInfo {
+                loader: loader_info,
+                metadata,
+                outline,
+                pages: all_page_info,
+                attachments: attachment_info,
+            })
+        })
+    }
+
+    async fn extract_attachment(&self, parent: &DocumentSource, index: usize, embedded_file: EmbeddedFile) -> AttachmentExtractInfo {
+        let kind = embedded_file.kind();
+        let mut info = AttachmentExtractInfo {
+            name: embedded_file.name.clone(),
+            description: embedded_file.description.clone(),
+            mime_type: embedded_file.mime_type.clone(),
+            kind,
+            source: embedded_file.source,
+            page_num: embedded_file.page_num,
+            size: embedded_file.data.as_ref().map_or(0, Vec::len),
+            saved_as: None,
+            document: None,
+            image: None,
+            error: None,
+        };
+
+        let data = match &embedded_file.data {
+            Ok(data) => data,
+            Err(e) => {
+                info.error = Some(e.clone());
+                return info;
+            }
+        };
+        let file_name = format!("{}_{}", index, embedded_file.file_name());
+        let path = match file_helper::save_attachment(&parent.file_id, &file_name, data) {
+            Ok((path, relative)) => {
+                info.saved_as = Some(relative);
+                path
+            }
+            Err(e) => {
+                info.error = Some(format!("Failed to save attachment: {}", e));
+                return info;
+            }
+        };
+
+        if !self.message.process_attachments || parent.depth >= self.message.attachment_depth() {
+            return info;
         }
-        
-        Ok(all_page_info)
+        println!("Processing attachment {} of {} as {:?}", embedded_file.name, parent.file_id, kind);
+        match kind {
+            AttachmentKind::Pdf => {
+                let source = DocumentSource {
+                    path,
+                    file_id: format!("{}_attachment_{}", parent.file_id, index),
+                    // the parent's password rarely applies, owner-only protection still opens
+                    password: None,
+                    depth: parent.depth + 1,
+                };
+                match self.extract_document(&source).await {
+                    Ok(document) => info.document = Some(Box::new(document)),
+                    Err(e) => info.error = Some(e.to_string()),
+                }
+            }
+            AttachmentKind::Image => {
+                let mut image_info = self.ocr_image(file_name, ImageKind::Attachment, path, &mut self.message.languages.clone()).await;
+                normalize::normalize_image(&mut image_info, &self.message.normalize);
+                info.image = Some(image_info);
+            }
+            AttachmentKind::Other => {}
+        }
+        info
     }
-     async fn process_page(&self, doc: &Document, page_num: u32, page_id: (u32, u16)) -> PageExtractInfo {
-        let mut image_paths: Vec<String> = vec![];
-        let mut image_text: Vec<String> = vec![];
+
+    async fn process_page(&self, source: &DocumentSource, doc: &LoadedDocument, page_num: u32, page_id: Option<ObjectId>, frame: Option<PageFrame>) -> PageExtractInfo {
+        let mut images: Vec<ImageExtractInfo> = vec![];
         let mut text_map: Vec<String> = Vec::new();
+        let file_id = source.file_id.as_str();
+        let mut text_layout = PageLayout::default();
+        let mut ocr_layout = PageLayout::default();
+        let mut image_placements = Vec::new();
 
-        if let Ok(text_content) = doc.extract_text(&[page_num]) {
-            // println!("String found from page {} : {}", page_num, text_content);
-            text_map.push(text_content);
-        }
-
-        if let Ok(page_images) = doc.get_page_images(page_id) {
-            let file_id = self.message.file.split('.').next().unwrap_or("");
-            for (i, image) in page_images.iter().enumerate() {
-                let image_name = format!("{}_{}_{}.png",file_id ,page_num, i);
-                let image_path = file_helper::get_pdf_image_process_path(image_name.as_str());
-                
-                if Self::save_pdf_image(&image, image_path.to_str().unwrap()).is_ok() {
-                    let img_path = image_path.to_str().unwrap();
-                
-                    // let extracted_text = futures::executor::block_on(
-                    //     self.engine.extract_text_from_image(img_path.to_string())
-                    // );
-                    println!("Extracting page content with {:?}", self.engine);
-                    match self.engine.extract_text_from_image(img_path.to_string()).await {
-                        Ok(extracted_text) => {
-                            println!("Gotten content of leng {:?} from {:?}", extracted_text.len(), self.engine);
-                            image_text.push(extracted_text);
-                            image_paths.push(image_name);
+        match doc {
+            LoadedDocument::Lopdf { doc, .. } => {
+                // the walk positions every glyph, extract_text is only the fallback for content
+                // streams it cannot parse
+                match page_id.map(|page_id| text_layer::walk_page(doc, page_id)) {
+                    Some(Ok(walked)) => {
+                        text_layout = layout::from_glyphs(&walked.glyphs);
+                        if self.message.reading_order() {
+                            text_layout = reading_order::order(text_layout);
                         }
-                        Err(e) => {
-                            println!("Error processing image with engine {:?} giving error  {:?}", self.engine, e);
+                        image_placements = walked.images;
+                        text_map.push(text_layout.text());
+                    }
+                    walked => {
+                        if let Some(Err(e)) = walked {
+                            println!("Failed to walk the content of page {}: {}", page_num, e);
+                        }
+                        if let Ok(text_content) = doc.extract_text(&[page_num]) {
+                            text_map.push(text_content);
                         }
                     }
                 }
             }
+            LoadedDocument::Pdfium { .. } => {
+                match render::page_text_async(source.path.clone(), source.password.clone(), page_num).await {
+                    Ok(text_content) => text_map.push(text_content),
+                    Err(e) => println!("pdfium failed to extract text of page {}: {}", page_num, e),
+                }
+            }
+        }
+        let text = text_map.join(" ");
+        let text_quality = TextQuality::score(&text);
+
+        let needs_ocr = match self.message.ocr_mode {
+            OcrMode::Always => true,
+            OcrMode::Auto => !text_quality.is_usable(),
+        };
+        if !needs_ocr {
+            println!("Page {} has a usable text layer, skipping OCR", page_num);
         }
+        // OCR reads the page in the languages of the job, or else in the language of its text layer
+        let mut ocr_languages = self.message.languages.clone().or_else(|| {
+            Some(&text)
+                .filter(|_| text_quality.is_usable())
+                .and_then(|text| language::detect(text))
+                .filter(|detected| detected.reliable)
+                .map(|detected| language::ocr_languages(&detected))
+        });
 
+        let lopdf_page = doc.as_lopdf().zip(page_id);
+        if needs_ocr && self.message.render_mode.uses_images() {
+            if let Some((lopdf_doc, Ok(page_images))) = lopdf_page.map(|(doc, page_id)| (doc, doc.get_page_images(page_id))) {
+                for (i, image) in page_images.iter().enumerate() {
+                    let image_name = format!("{}_{}_{}.png",file_id ,page_num, i);
+                    let image_path = file_helper::get_pdf_image_process_path(image_name.as_str());
+
+                    let transparent = match self.save_pdf_image(lopdf_doc, image, image_path.to_str().unwrap(), file_id) {
+                        Ok(transparent) => transparent,
+                        Err(e) => {
+                            println!("Skipping image {} on page {}: {}", image_name, page_num, e);
+                            images.push(self.image_result(image_name, ImageKind::Embedded, Err(format!("Failed to decode image: {}", e))));
+                            continue;
+                        }
+                    };
+                    let mut image_info = self.ocr_image(image_name, ImageKind::Embedded, image_path.clone(), &mut ocr_languages).await;
+                    image_info.rgba = transparent;
+                    // boxes only map back onto the page when the image was saved the way it is drawn
+                    let placement = image_placements.iter().find(|placement| placement.image_id == image.id);
+                    let matrix = placement.zip(image::image_dimensions(&image_path).ok())
+                        .filter(|(_, dimensions)| *dimensions == (image.width as u32, image.height as u32))
+                        .map(|(placement, (width, height))| layout::image_matrix(width, height, &placement.matrix));
+                    self.add_ocr_layout(&mut ocr_layout, &mut image_info, matrix);
+                    images.push(image_info);
+                }
+            }
+        }
+
+        // in auto mode a page with no usable text and nothing OCR-able embedded is most likely
+        // vector outlines or an image we cannot decode, so fall back to rendering it. Without
+        // lopdf there is no way to get at embedded images, so the page is always rendered
+        let render_fallback = (self.message.ocr_mode == OcrMode::Auto || lopdf_page.is_none())
+            && images.iter().all(|image| image.text.trim().is_empty());
+        if needs_ocr && (self.message.render_mode.uses_page_render() || render_fallback) {
+            let image_name = format!("{}_{}_page.png", file_id, page_num);
+            let image_path = file_helper::get_pdf_image_process_path(image_name.as_str());
+            let dpi = render::clamp_dpi(self.message.render_dpi);
+
+            match render::render_page_async(source.path.clone(), source.password.clone(), page_num, dpi, image_path.clone()).await {
+                Ok(()) => {
+                    let mut image_info = self.ocr_image(image_name, ImageKind::PageRender, image_path.clone(), &mut ocr_languages).await;
+                    let matrix = frame.zip(image::image_dimensions(&image_path).ok())
+                        .map(|(frame, (width, height))| frame.render_matrix(width, height));
+                    self.add_ocr_layout(&mut ocr_layout, &mut image_info, matrix);
+                    images.push(image_info);
+                }
+                Err(e) => {
+                    println!("Failed to render page {}: {}", page_num, e);
+                    images.push(self.image_result(image_name, ImageKind::PageRender, Err(format!("Failed to render page: {}", e))));
+                }
+            }
+        }
+
+        let has_ocr_text = images.iter().any(|image| !image.text.trim().is_empty());
+        let source = match (text_quality.is_empty() || text_quality.is_garbage(), has_ocr_text) {
+            (false, false) => Some(TextSource::TextLayer),
+            (false, true) => Some(TextSource::Merged),
+            (true, true) => Some(TextSource::Ocr),
+            (true, false) => None,
+        };
+        let kept_text = match source {
+            Some(TextSource::TextLayer) | Some(TextSource::Merged) => text.as_str(),
+            _ => "",
+        };
+        let full_text = Self::merge_text(kept_text, &images);
+        let layout = match source {
+            Some(TextSource::TextLayer) => text_layout,
+            Some(TextSource::Ocr) => ocr_layout,
+            Some(TextSource::Merged) => PageLayout { blocks: [text_layout.blocks, ocr_layout.blocks].concat() },
+            None => PageLayout::default(),
+        };
+        let language = language::detect(&full_text);
         PageExtractInfo {
             page_num,
-            text: text_map.join(" ")
+            text,
+            text_quality,
+            source,
+            images,
+            full_text,
+            sections: vec![],
+            form_fields: vec![],
+            annotations: vec![],
+            header: None,
+            footer: None,
+            layout: Some(layout),
+            edge_layout: None,
+            language,
         }
     }
-   
-   fn save_pdf_image<'a>(pdf_image: &PdfImage<'a>, file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(filters) = &pdf_image.filters {
-            for filter in filters {
-                match filter.as_str() {
-                    "DCTDecode" => {
-                        let img = ImageReader::with_format(Cursor::new(pdf_image.content), ImageFormat::Jpeg)
-                            .decode()
-                            .map_err(|e| format!("Failed to decode JPEG image: {}", e))?;
-                        img.save(file_name)?;
-                        return Ok(());
-                    }
-                    "FlateDecode" => {
-                        let mut decoder = ZlibDecoder::new(Cursor::new(pdf_image.content));
-                        let mut decompressed_data = Vec::new();
-                        decoder.read_to_end(&mut decompressed_data)?;
-
-                        let img = ImageReader::new(Cursor::new(decompressed_data))
-                            .with_guessed_format()?
-                            .decode()
-                            .map_err(|e| format!("Failed to decode FlateDecode image: {}", e))?;
-
-                        let img = img.rotate90();
-                        img.save(file_name)?;
-                        return Ok(());
-                    }
-                    _ => return Err(format!("Unsupported filter: {}", filter).into()),
-                }
+
+    // OCR text follows the reading order too, as long as its words could be placed on the page
+    fn add_ocr_layout(&self, layout: &mut PageLayout, image_info: &mut ImageExtractInfo, pixels_to_page: Option<text_layer::Matrix>) {
+        if let (Some(words), Some(matrix)) = (&image_info.words, pixels_to_page) {
+            let mut image_layout = layout::from_ocr_words(words, &matrix);
+            if self.message.reading_order() {
+                image_layout = reading_order::order(image_layout);
+                image_info.text = image_layout.text();
             }
+            layout.blocks.extend(image_layout.blocks);
         }
+    }
 
-        match pdf_image.color_space.as_deref() {
-            Some("DeviceRGB") => {
-                let expected_size = (pdf_image.width * pdf_image.height * 3) as usize;
-                if pdf_image.content.len() != expected_size {
-                    return Err("Content length does not match expected size for RGB".into());
-                }
+    // without languages to go on the image is read with the engine default first, and read again
+    // when its text turns out to be in another language, which the rest of the page then uses
+    async fn ocr_image(&self, image_name: String, kind: ImageKind, image_path: PathBuf, languages: &mut Option<String>) -> ImageExtractInfo {
+        println!("Extracting page content with {:?}", self.engine);
+        let image_path = image_path.to_str().unwrap().to_string();
+        let mut used_languages = self.engine.ocr_languages(languages.as_deref());
+        let mut result = self.engine.extract_from_image(image_path.clone(), languages.clone()).await;
 
-                let img: RgbaImage = ImageBuffer::from_fn(
-                    pdf_image.width as u32,
-                    pdf_image.height as u32,
-                    |x, y| {
-                        let idx = (y * pdf_image.width as u32 + x) as usize * 3;
-                        Rgba([
-                            pdf_image.content[idx],
-                            pdf_image.content[idx + 1],
-                            pdf_image.content[idx + 2],
-                            255,
-                        ])
-                    },
-                );
-                img.save(file_name)?;
+        let detected = match (&result, &languages) {
+            (Ok(output), None) => language::detect(&output.text).filter(|detected| detected.reliable),
+            _ => None,
+        };
+        if let Some(detected) = detected {
+            let detected = language::ocr_languages(&detected);
+            let detected_languages = self.engine.ocr_languages(Some(&detected));
+            if detected_languages != used_languages {
+                println!("Reading {} again in {:?}", image_name, detected_languages);
+                result = self.engine.extract_from_image(image_path.clone(), Some(detected.clone())).await;
+                used_languages = detected_languages;
             }
-            Some("DeviceGray") => {
-                let expected_size = (pdf_image.width * pdf_image.height) as usize;
-                if pdf_image.content.len() != expected_size {
-                    return Err("Content length does not match expected size for Gray".into());
-                }
+            *languages = Some(detected);
+        }
+
+        match result {
+            Ok(output) => {
+                println!("Gotten content of leng {:?} from {:?}", output.text.len(), self.engine);
+                let mut image_info = self.image_result(image_name, kind, Ok(output.text));
+                image_info.words = output.words;
+                image_info.languages = used_languages;
+                self.save_ocr_xml(&mut image_info, &image_path);
+                image_info
+            }
+            Err(e) => {
+                println!("Error processing image with engine {:?} giving error  {:?}", self.engine, e);
+                self.image_result(image_name, kind, Err(e.to_string()))
+            }
+        }
+    }
+
+    // hOCR and ALTO are written while the word boxes are at hand, they are not kept in checkpoints
+    fn save_ocr_xml(&self, image_info: &mut ImageExtractInfo, image_path: &str) {
+        let (hocr, alto) = (self.message.writes(OutputFormat::Hocr), self.message.writes(OutputFormat::Alto));
+        let Some(words) = image_info.words.as_ref().filter(|_| hocr || alto) else {
+            return;
+        };
+        let (width, height) = match image::image_dimensions(image_path) {
+            Ok(dimensions) => dimensions,
+            Err(e) => {
+                println!("Skipping hOCR and ALTO of {}: {}", image_info.image_name, e);
+                return;
+            }
+        };
+        let id = self.message.file.split('.').next().unwrap_or("");
+        let stem = image_info.image_name.rsplit_once('.').map_or(image_info.image_name.as_str(), |(stem, _)| stem);
 
-                let img: RgbaImage = ImageBuffer::from_fn(
-                    pdf_image.width as u32,
-                    pdf_image.height as u32,
-                    |x, y| {
-                        let idx = (y * pdf_image.width as u32 + x) as usize;
-                        let gray = pdf_image.content[idx];
-                        Rgba([gray, gray, gray, 255])
-                    },
-                );
-                img.save(file_name)?;
+        if hocr {
+            let content = ocr_xml::hocr(words, &image_info.image_name, width, height, image_info.languages.as_deref());
+            match file_helper::save_ocr_output(id, &format!("{}.hocr", stem), &content) {
+                Ok(relative) => image_info.hocr = Some(relative),
+                Err(e) => println!("Failed to save hOCR of {}: {}", image_info.image_name, e),
+            }
+        }
+        if alto {
+            let content = ocr_xml::alto(words, &image_info.image_name, width, height);
+            match file_helper::save_ocr_output(id, &format!("{}.alto.xml", stem), &content) {
+                Ok(relative) => image_info.alto = Some(relative),
+                Err(e) => println!("Failed to save ALTO of {}: {}", image_info.image_name, e),
             }
-            _ => return Err("Unsupported color space or image type".into()),
         }
+    }
+
+    fn image_result(&self, image_name: String, kind: ImageKind, result: Result<String, String>) -> ImageExtractInfo {
+        let (text, error) = match result {
+            Ok(text) => (text, None),
+            Err(e) => (String::new(), Some(e)),
+        };
+        ImageExtractInfo {
+            image_name,
+            kind,
+            text,
+            engine: self.engine.name(),
+            error,
+            languages: None,
+            hocr: None,
+            alto: None,
+            rgba: None,
+            words: None,
+        }
+    }
+
+    fn merge_text(text: &str, images: &[ImageExtractInfo]) -> String {
+        std::iter::once(text)
+            .chain(images.iter().map(|image| image.text.as_str()))
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
 
-        Ok(())
+    // saves the image over white for OCR, and with keep_image_alpha a copy that keeps its
+    // transparency, returning where that copy went
+    fn save_pdf_image(&self, doc: &lopdf::Document, pdf_image: &PdfImage, file_name: &str, file_id: &str) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let img = image_decode::decode_image(doc, pdf_image)?;
+        img.on_white().save(file_name)?;
+        if !self.message.keep_image_alpha || img.alpha.is_none() {
+            return Ok(None);
+        }
+        let stem = Path::new(file_name).file_stem().and_then(|stem| stem.to_str()).unwrap_or("image");
+        match file_helper::save_processed_image(file_id, &format!("{}_rgba.png", stem), &img.with_alpha()) {
+            Ok(relative) => Ok(Some(relative)),
+            Err(e) => {
+                println!("Failed to keep the transparent version of {}: {}", file_name, e);
+                Ok(None)
+            }
+        }
     }
 
 }
\ No newline at end of file
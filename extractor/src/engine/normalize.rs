@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{types::engine_handler::{ImageExtractInfo, PageExtractInfo}, worker::{NormalizeOptions, UnicodeForm}};
+
+// Clean-up applied to text layer and OCR text alike, so output from different fonts and engines
+// compares equal. Every step is off unless the job turns it on.
+
+const LIGATURES: [(char, &str); 7] = [
+    ('\u{FB00}', "ff"),
+    ('\u{FB01}', "fi"),
+    ('\u{FB02}', "fl"),
+    ('\u{FB03}', "ffi"),
+    ('\u{FB04}', "ffl"),
+    ('\u{FB05}', "st"),
+    ('\u{FB06}', "st"),
+];
+// hyphen-minus, soft hyphen and the unicode hyphen
+const HYPHENS: [char; 3] = ['-', '\u{00AD}', '\u{2010}'];
+
+pub fn normalize_page(page: &mut PageExtractInfo, options: &NormalizeOptions) {
+    if !options.is_enabled() {
+        return;
+    }
+    page.text = normalize_text(&page.text, options);
+    page.full_text = normalize_text(&page.full_text, options);
+    for text in [&mut page.header, &mut page.footer].into_iter().flatten() {
+        *text = normalize_text(text, options);
+    }
+    for image in page.images.iter_mut() {
+        normalize_image(image, options);
+    }
+    // words keep their boxes, so only the steps that work within a word apply to them
+    for word in page.layout.iter_mut().chain(page.edge_layout.iter_mut()).flat_map(|layout| layout.blocks.iter_mut()).flat_map(|block| block.lines.iter_mut()).flat_map(|line| line.words.iter_mut()) {
+        word.text = normalize_characters(&word.text, options);
+    }
+}
+
+pub fn normalize_image(image: &mut ImageExtractInfo, options: &NormalizeOptions) {
+    if options.is_enabled() {
+        image.text = normalize_text(&image.text, options);
+    }
+}
+
+pub fn normalize_text(text: &str, options: &NormalizeOptions) -> String {
+    let mut text = normalize_characters(text, options);
+    if options.dehyphenate {
+        text = dehyphenate(&text);
+    }
+    if options.whitespace {
+        text = collapse_whitespace(&text);
+    }
+    text
+}
+
+fn normalize_characters(text: &str, options: &NormalizeOptions) -> String {
+    let mut text = Cow::Borrowed(text);
+    if options.mojibake {
+        text = Cow::Owned(fix_mojibake(&text));
+    }
+    match options.unicode {
+        Some(UnicodeForm::Nfc) => text = Cow::Owned(text.nfc().collect()),
+        Some(UnicodeForm::Nfkc) => text = Cow::Owned(text.nfkc().collect()),
+        None => {}
+    }
+    if options.ligatures {
+        text = Cow::Owned(expand_ligatures(&text));
+    }
+    text.into_owned()
+}
+
+fn expand_ligatures(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match LIGATURES.iter().find(|(ligature, _)| *ligature == c) {
+            Some((_, letters)) => expanded.push_str(letters),
+            None => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+// UTF-8 text that was decoded as Windows-1252 somewhere on the way, like "cafÃ©" or "donâ€™t".
+// A word is only replaced when its Windows-1252 bytes form valid UTF-8, which real accented
+// text almost never does
+fn fix_mojibake(text: &str) -> String {
+    let mut fixed = String::with_capacity(text.len());
+    let mut word_start = None;
+    for (index, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                fixed.push_str(&repair_word(&text[start..index]));
+            }
+            fixed.push(c);
+        } else if word_start.is_none() {
+            word_start = Some(index);
+        }
+    }
+    if let Some(start) = word_start {
+        fixed.push_str(&repair_word(&text[start..]));
+    }
+    fixed
+}
+
+fn repair_word(word: &str) -> Cow<'_, str> {
+    if word.is_ascii() {
+        return Cow::Borrowed(word);
+    }
+    let (bytes, _, unmappable) = encoding_rs::WINDOWS_1252.encode(word);
+    if unmappable {
+        return Cow::Borrowed(word);
+    }
+    match std::str::from_utf8(&bytes) {
+        Ok(repaired) if repaired != word => Cow::Owned(repaired.to_string()),
+        _ => Cow::Borrowed(word),
+    }
+}
+
+// a word split over two lines as "exam-" and "ple" is joined on the first line. The hyphen goes,
+// which also joins compounds broken at their own hyphen, "self-" and "contained" become
+// "selfcontained"; only after capitals or digits, as in "X-" and "ray", is it kept
+fn dehyphenate(text: &str) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    // a continuation that was a single word leaves its line empty, which is dropped rather than
+    // read as a paragraph break
+    let mut emptied = vec![false; lines.len()];
+    for index in 0..lines.len().saturating_sub(1) {
+        let line = lines[index].trim_end();
+        let Some(stripped) = line.strip_suffix(HYPHENS) else {
+            continue;
+        };
+        if !stripped.chars().last().is_some_and(char::is_alphabetic) {
+            continue;
+        }
+        let next = lines[index + 1].trim_start();
+        if !next.chars().next().is_some_and(char::is_lowercase) {
+            continue;
+        }
+
+        let (rest_of_word, rest_of_line) = next.split_at(next.find(char::is_whitespace).unwrap_or(next.len()));
+        // a soft hyphen only ever marks a break
+        let hyphen = &line[stripped.len()..];
+        let word_before = stripped.rsplit(char::is_whitespace).next().unwrap_or_default();
+        let kept = hyphen != "\u{AD}" && word_before.chars().any(|c| c.is_uppercase() || c.is_numeric());
+        let joined = format!("{}{}{}", stripped, if kept { hyphen } else { "" }, rest_of_word);
+        let remaining = rest_of_line.trim_start().to_string();
+        emptied[index + 1] = remaining.is_empty();
+        lines[index] = joined;
+        lines[index + 1] = remaining;
+    }
+    lines.into_iter()
+        .zip(emptied)
+        .filter(|(_, emptied)| !emptied)
+        .map(|(line, _)| line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// runs of spaces become one space, trailing spaces go and blank lines are kept to one
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && collapsed.last().map_or(true, |previous| previous.is_empty()) {
+            continue;
+        }
+        collapsed.push(line);
+    }
+    while collapsed.last().is_some_and(|line| line.is_empty()) {
+        collapsed.pop();
+    }
+    collapsed.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs_utf8_read_as_windows_1252() {
+        assert_eq!(fix_mojibake("cafÃ© donâ€™t  Ã¼ber"), "café don’t  über");
+        // real accented text, and words that do not come back as UTF-8, are left alone
+        assert_eq!(fix_mojibake("café Øresund naïve"), "café Øresund naïve");
+        assert_eq!(fix_mojibake("plain ascii\ttext\n"), "plain ascii\ttext\n");
+    }
+
+    #[test]
+    fn joins_words_hyphenated_across_lines() {
+        assert_eq!(dehyphenate("an exam-\nple of text"), "an example\nof text");
+        // a continuation that was the whole line leaves no blank line behind
+        assert_eq!(dehyphenate("exam-\nple\nnext line"), "example\nnext line");
+        assert_eq!(dehyphenate("soft\u{AD}\nly"), "softly");
+        assert_eq!(dehyphenate("hy\u{2010}\nphen"), "hyphen");
+    }
+
+    #[test]
+    fn keeps_hyphens_that_are_not_line_breaks() {
+        // what the option gives up, compounds broken at their own hyphen lose it
+        assert_eq!(dehyphenate("self-\ncontained"), "selfcontained");
+        assert_eq!(dehyphenate("an X-\nray and COVID-\nrelated"), "an X-ray\nand COVID-related");
+        assert_eq!(dehyphenate("Jean-\nPaul"), "Jean-\nPaul");
+        assert_eq!(dehyphenate("pages 3-\n5"), "pages 3-\n5");
+        assert_eq!(dehyphenate("a dash -\nthen text"), "a dash -\nthen text");
+        assert_eq!(dehyphenate("last line-"), "last line-");
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(collapse_whitespace("  two   spaces \t here  \n\n\n\nnext\n \n"), "two spaces here\n\nnext");
+        assert_eq!(collapse_whitespace("\n\n  \n"), "");
+    }
+
+    #[test]
+    fn expands_ligatures() {
+        assert_eq!(expand_ligatures("\u{FB01}nal e\u{FB00}ort \u{FB04}"), "final effort ffl");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{engine::language, types::engine_handler::OcrWord};
+
+// hOCR and ALTO versions of the words Tesseract found in one image, for archive tooling that
+// reads OCR results in these formats only. Coordinates are pixels of the image from its top left
+// corner, as Tesseract writes them itself.
+
+// a block, paragraph and line of Tesseract output with the boxes of its words joined
+struct Group<'a> {
+    id: (u32, u32, u32),
+    bbox: [u32; 4],
+    words: Vec<&'a OcrWord>,
+}
+
+pub fn hocr(words: &[OcrWord], image_name: &str, width: u32, height: u32, languages: Option<&str>) -> String {
+    // the first traineddata is the one the text was mostly read with
+    let traineddata = languages.and_then(|languages| languages.split('+').next());
+    // paragraphs name the traineddata, as Tesseract's own hOCR does
+    let lang = traineddata.map(|lang| format!(" lang=\"{}\"", escape(lang))).unwrap_or_default();
+    // the document declares BCP 47, "und" when the traineddata has no language of its own
+    let tag = traineddata.and_then(language::bcp47).unwrap_or_else(|| "und".to_string());
+    let mut html = String::new();
+    html.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    html.push_str("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n");
+    html.push_str(&format!("<html xmlns=\"http://www.w3.org/1999/xhtml\" xml:lang=\"{0}\" lang=\"{0}\">\n", escape(&tag)));
+    html.push_str(" <head>\n  <title></title>\n");
+    html.push_str("  <meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>\n");
+    html.push_str("  <meta name=\"ocr-system\" content=\"tesseract\"/>\n");
+    html.push_str("  <meta name=\"ocr-capabilities\" content=\"ocr_page ocr_carea ocr_par ocr_line ocrx_word\"/>\n");
+    html.push_str(" </head>\n <body>\n");
+    html.push_str(&format!("  <div class=\"ocr_page\" id=\"page_1\" title=\"image &quot;{}&quot;; bbox 0 0 {} {}; ppageno 0\">\n", escape(image_name), width, height));
+
+    let refs: Vec<&OcrWord> = words.iter().collect();
+    let blocks = group(&refs, |word| (word.block, 0, 0));
+    for block in &blocks {
+        html.push_str(&format!("   <div class=\"ocr_carea\" id=\"block_{}\" title=\"bbox {}\">\n", block.id.0, bbox(block.bbox)));
+        for paragraph in group(&block.words, |word| (word.block, word.paragraph, 0)) {
+            html.push_str(&format!("    <p class=\"ocr_par\" id=\"par_{}_{}\"{} title=\"bbox {}\">\n", paragraph.id.0, paragraph.id.1, lang, bbox(paragraph.bbox)));
+            for (line_index, line) in group(&paragraph.words, |word| (word.block, word.paragraph, word.line)).iter().enumerate() {
+                html.push_str(&format!("     <span class=\"ocr_line\" id=\"line_{}_{}_{}\" title=\"bbox {}\">\n", line.id.0, line.id.1, line_index + 1, bbox(line.bbox)));
+                for (word_index, word) in line.words.iter().enumerate() {
+                    html.push_str(&format!(
+                        "      <span class=\"ocrx_word\" id=\"word_{}_{}_{}_{}\" title=\"bbox {}; x_wconf {}\">{}</span>\n",
+                        line.id.0, line.id.1, line_index + 1, word_index + 1, bbox(word_box(word)), word.confidence.round().max(0.0) as u32, escape(&word.text)
+                    ));
+                }
+                html.push_str("     </span>\n");
+            }
+            html.push_str("    </p>\n");
+        }
+        html.push_str("   </div>\n");
+    }
+    html.push_str("  </div>\n </body>\n</html>\n");
+    html
+}
+
+pub fn alto(words: &[OcrWord], image_name: &str, width: u32, height: u32) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/alto/v4/alto-4-2.xsd\">\n");
+    xml.push_str("  <Description>\n    <MeasurementUnit>pixel</MeasurementUnit>\n");
+    xml.push_str(&format!("    <sourceImageInformation>\n      <fileName>{}</fileName>\n    </sourceImageInformation>\n", escape(image_name)));
+    xml.push_str("    <OCRProcessing ID=\"OCR_0\">\n      <ocrProcessingStep>\n        <processingSoftware>\n          <softwareName>tesseract</softwareName>\n        </processingSoftware>\n      </ocrProcessingStep>\n    </OCRProcessing>\n");
+    xml.push_str("  </Description>\n  <Layout>\n");
+    xml.push_str(&format!("    <Page WIDTH=\"{0}\" HEIGHT=\"{1}\" PHYSICAL_IMG_NR=\"1\" ID=\"page_1\">\n      <PrintSpace HPOS=\"0\" VPOS=\"0\" WIDTH=\"{0}\" HEIGHT=\"{1}\">\n", width, height));
+
+    let refs: Vec<&OcrWord> = words.iter().collect();
+    let (mut line_count, mut word_count) = (0, 0);
+    for (block_index, paragraph) in group(&refs, |word| (word.block, word.paragraph, 0)).iter().enumerate() {
+        xml.push_str(&format!("        <TextBlock ID=\"block_{}\" {}>\n", block_index + 1, position(paragraph.bbox)));
+        for line in group(&paragraph.words, |word| (word.block, word.paragraph, word.line)) {
+            line_count += 1;
+            xml.push_str(&format!("          <TextLine ID=\"line_{}\" {}>\n", line_count, position(line.bbox)));
+            for (index, word) in line.words.iter().enumerate() {
+                // the space between two words spans the gap between their boxes
+                if index > 0 {
+                    let previous = word_box(line.words[index - 1]);
+                    let current = word_box(word);
+                    xml.push_str(&format!("            <SP WIDTH=\"{}\" VPOS=\"{}\" HPOS=\"{}\"/>\n", current[0].saturating_sub(previous[2]), previous[1], previous[2]));
+                }
+                word_count += 1;
+                xml.push_str(&format!(
+                    "            <String ID=\"string_{}\" {} WC=\"{:.2}\" CONTENT=\"{}\"/>\n",
+                    word_count, position(word_box(word)), (word.confidence / 100.0).clamp(0.0, 1.0), escape(&word.text)
+                ));
+            }
+            xml.push_str("          </TextLine>\n");
+        }
+        xml.push_str("        </TextBlock>\n");
+    }
+    xml.push_str("      </PrintSpace>\n    </Page>\n  </Layout>\n</alto>\n");
+    xml
+}
+
+// consecutive words with the same key, in the order Tesseract listed them
+fn group<'a>(words: &[&'a OcrWord], key: impl Fn(&OcrWord) -> (u32, u32, u32)) -> Vec<Group<'a>> {
+    let mut groups: Vec<Group<'a>> = Vec::new();
+    for word in words {
+        let id = key(word);
+        match groups.last_mut() {
+            Some(group) if group.id == id => {
+                let [x0, y0, x1, y1] = word_box(word);
+                group.bbox = [group.bbox[0].min(x0), group.bbox[1].min(y0), group.bbox[2].max(x1), group.bbox[3].max(y1)];
+                group.words.push(word);
+            }
+            _ => groups.push(Group { id, bbox: word_box(word), words: vec![word] }),
+        }
+    }
+    groups
+}
+
+fn word_box(word: &OcrWord) -> [u32; 4] {
+    [word.left, word.top, word.left + word.width, word.top + word.height]
+}
+
+fn bbox([x0, y0, x1, y1]: [u32; 4]) -> String {
+    format!("{} {} {} {}", x0, y0, x1, y1)
+}
+
+fn position([x0, y0, x1, y1]: [u32; 4]) -> String {
+    format!("HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"", x0, y0, x1 - x0, y1 - y0)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -21,6 +21,10 @@ impl EngineHandler for OllamaEngine {
         }
     }
 
+    fn name(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
     fn extract_text_from_image(&self, image_path: String) 
         -> Pin<Box<dyn Future<Output = Result<String, Box<dyn std::error::Error + Send>>> + Send>> 
     {
@@ -0,0 +1,206 @@
+use std::{collections::{HashMap, HashSet}, path::Path};
+
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use pdfium_render::prelude::{PdfAction, PdfBookmark};
+
+use crate::engine::{loader::LoadedDocument, render};
+
+// The bookmark tree of a document, used as its chapter structure. Every page gets the titles of
+// the outline entries it falls under so downstream chunking can keep sections together.
+
+// guards against outline and name trees that link back into themselves
+const MAX_OUTLINE_DEPTH: usize = 32;
+const MAX_PDFIUM_SIBLINGS: usize = 10_000;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OutlineItem {
+    pub title: String,
+    // None for entries pointing to another document or to a destination that does not resolve
+    pub page_num: Option<u32>,
+    pub children: Vec<OutlineItem>,
+}
+
+pub fn read_outline(doc: &LoadedDocument, pdf_path: &Path, password: Option<&str>) -> Vec<OutlineItem> {
+    match doc {
+        LoadedDocument::Lopdf { doc, .. } => from_lopdf(doc),
+        LoadedDocument::Pdfium { .. } => from_pdfium(pdf_path, password).unwrap_or_else(|e| {
+            println!("Failed to read outline with pdfium: {}", e);
+            vec![]
+        }),
+    }
+}
+
+// titles from the outermost to the innermost entry whose target page is the closest one at or
+// before `page_num`, later entries win ties since children follow their parent
+pub fn sections_for_page(outline: &[OutlineItem], page_num: u32) -> Vec<String> {
+    let mut flattened = Vec::new();
+    flatten(outline, &mut Vec::new(), &mut flattened);
+
+    let mut best: Option<(u32, &Vec<String>)> = None;
+    for (path, target) in &flattened {
+        if *target <= page_num && best.map_or(true, |(best_target, _)| *target >= best_target) {
+            best = Some((*target, path));
+        }
+    }
+    best.map(|(_, path)| path.clone()).unwrap_or_default()
+}
+
+fn flatten(items: &[OutlineItem], path: &mut Vec<String>, flattened: &mut Vec<(Vec<String>, u32)>) {
+    for item in items {
+        path.push(item.title.clone());
+        if let Some(page_num) = item.page_num {
+            flattened.push((path.clone(), page_num));
+        }
+        flatten(&item.children, path, flattened);
+        path.pop();
+    }
+}
+
+fn from_lopdf(doc: &Document) -> Vec<OutlineItem> {
+    let Some(first) = doc.catalog().ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|outlines| doc.dereference(outlines).ok())
+        .and_then(|(_, outlines)| outlines.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").and_then(Object::as_reference).ok())
+    else {
+        return vec![];
+    };
+
+    let page_numbers = page_numbers(doc);
+    let mut visited = HashSet::new();
+    read_siblings(doc, first, &page_numbers, &mut visited, 0)
+}
+
+fn read_siblings(doc: &Document, first: ObjectId, page_numbers: &HashMap<ObjectId, u32>, visited: &mut HashSet<ObjectId>, depth: usize) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    let mut next = Some(first);
+
+    while let Some(id) = next {
+        if depth > MAX_OUTLINE_DEPTH || !visited.insert(id) {
+            break;
+        }
+        let Ok(item) = doc.get_dictionary(id) else {
+            break;
+        };
+
+        let title = item.get(b"Title").ok()
+            .and_then(|title| doc.dereference(title).ok())
+            .and_then(|(_, title)| decode_text_string(title).ok())
+            .map(|title| title.trim().to_string())
+            .unwrap_or_default();
+        let children = match item.get(b"First").and_then(Object::as_reference) {
+            Ok(child) => read_siblings(doc, child, page_numbers, visited, depth + 1),
+            Err(_) => vec![],
+        };
+        items.push(OutlineItem {
+            title,
+            page_num: item_target(doc, item, page_numbers),
+            children,
+        });
+
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    items
+}
+
+// outline items and link annotations point at a page through /Dest or a GoTo action
+pub fn item_target(doc: &Document, item: &Dictionary, page_numbers: &HashMap<ObjectId, u32>) -> Option<u32> {
+    if let Ok(destination) = item.get(b"Dest") {
+        return resolve_destination(doc, destination, page_numbers);
+    }
+    let (_, action) = doc.dereference(item.get(b"A").ok()?).ok()?;
+    let action = action.as_dict().ok()?;
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    resolve_destination(doc, action.get(b"D").ok()?, page_numbers)
+}
+
+pub fn page_numbers(doc: &Document) -> HashMap<ObjectId, u32> {
+    doc.get_pages().into_iter().map(|(page_num, page_id)| (page_id, page_num)).collect()
+}
+
+// a destination is an array starting with the page, a dictionary holding that array under /D,
+// or a name looked up in the catalog's /Dests dictionary or /Names /Dests name tree
+pub fn resolve_destination(doc: &Document, destination: &Object, page_numbers: &HashMap<ObjectId, u32>) -> Option<u32> {
+    let (_, destination) = doc.dereference(destination).ok()?;
+    match destination {
+        Object::Array(array) => match array.first()? {
+            Object::Reference(page_id) => page_numbers.get(page_id).copied(),
+            // only remote destinations use page indices, which are meaningless for this file
+            _ => None,
+        },
+        Object::Dictionary(dict) => resolve_destination(doc, dict.get(b"D").ok()?, page_numbers),
+        Object::Name(name) | Object::String(name, _) => {
+            let named = named_destination(doc, name)?;
+            match named {
+                Object::Name(_) | Object::String(..) => None,
+                named => resolve_destination(doc, named, page_numbers),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn named_destination<'a>(doc: &'a Document, name: &[u8]) -> Option<&'a Object> {
+    let catalog = doc.catalog().ok()?;
+    if let Some(found) = catalog.get(b"Dests").ok()
+        .and_then(|dests| doc.dereference(dests).ok())
+        .and_then(|(_, dests)| dests.as_dict().ok())
+        .and_then(|dests| dests.get(name).ok())
+    {
+        return Some(found);
+    }
+
+    let (_, names) = doc.dereference(catalog.get(b"Names").ok()?).ok()?;
+    let (_, tree) = doc.dereference(names.as_dict().ok()?.get(b"Dests").ok()?).ok()?;
+    find_in_name_tree(doc, tree.as_dict().ok()?, name, 0)
+}
+
+fn find_in_name_tree<'a>(doc: &'a Document, node: &'a Dictionary, name: &[u8], depth: usize) -> Option<&'a Object> {
+    if depth > MAX_OUTLINE_DEPTH {
+        return None;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        return names.chunks(2)
+            .find(|pair| pair[0].as_str().is_ok_and(|key| key == name))
+            .and_then(|pair| pair.get(1));
+    }
+    node.get(b"Kids").and_then(Object::as_array).ok()?
+        .iter()
+        .filter_map(|kid| doc.dereference(kid).ok().and_then(|(_, kid)| kid.as_dict().ok()))
+        .find_map(|kid| find_in_name_tree(doc, kid, name, depth + 1))
+}
+
+fn from_pdfium(pdf_path: &Path, password: Option<&str>) -> Result<Vec<OutlineItem>, Box<dyn std::error::Error + Send + Sync>> {
+    let document = render::open_document(pdf_path, password)?;
+    Ok(document.bookmarks().root().map_or_else(Vec::new, |first| pdfium_siblings(first, 0)))
+}
+
+fn pdfium_siblings(first: PdfBookmark, depth: usize) -> Vec<OutlineItem> {
+    if depth > MAX_OUTLINE_DEPTH {
+        return vec![];
+    }
+    let mut items = Vec::new();
+    let mut next = Some(first);
+
+    while let Some(bookmark) = next {
+        if items.len() >= MAX_PDFIUM_SIBLINGS {
+            break;
+        }
+        let page_index = match bookmark.destination() {
+            Some(destination) => destination.page_index().ok(),
+            None => match bookmark.action() {
+                Some(PdfAction::LocalDestination(action)) => action.destination().ok().and_then(|destination| destination.page_index().ok()),
+                _ => None,
+            },
+        };
+        items.push(OutlineItem {
+            title: bookmark.title().map(|title| title.trim().to_string()).unwrap_or_default(),
+            page_num: page_index.map(|index| index as u32 + 1),
+            children: bookmark.first_child().map_or_else(Vec::new, |child| pdfium_siblings(child, depth + 1)),
+        });
+        next = bookmark.next_sibling();
+    }
+    items
+}
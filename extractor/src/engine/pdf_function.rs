@@ -0,0 +1,532 @@
+use lopdf::{Dictionary, Document, Object};
+
+// PDF functions (section 7.10 of the spec), as used by the tint transforms of Separation and
+// DeviceN colour spaces to turn colourant tints into colours of their alternate space.
+
+const MAX_DEPTH: u32 = 8;
+const MAX_STACK: usize = 100;
+const MAX_INTERPOLATED_INPUTS: usize = 4;
+// a 16 × 16 × 16 × 16 table with 4 outputs is already large for a tint transform
+const MAX_SAMPLES: usize = 16_000_000;
+
+pub enum Function {
+    // type 0, a table of samples interpolated between
+    Sampled {
+        domain: Vec<[f32; 2]>,
+        range: Vec<[f32; 2]>,
+        size: Vec<usize>,
+        encode: Vec<[f32; 2]>,
+        decode: Vec<[f32; 2]>,
+        // 0 to 1, the first input varying fastest
+        samples: Vec<f32>,
+    },
+    // type 2, c0 + x^n * (c1 - c0)
+    Exponential {
+        domain: [f32; 2],
+        c0: Vec<f32>,
+        c1: Vec<f32>,
+        exponent: f32,
+    },
+    // type 3, single-input functions each covering a part of the domain
+    Stitching {
+        domain: [f32; 2],
+        functions: Vec<Function>,
+        bounds: Vec<f32>,
+        encode: Vec<[f32; 2]>,
+    },
+    // type 4, a PostScript calculator program
+    PostScript {
+        domain: Vec<[f32; 2]>,
+        range: Vec<[f32; 2]>,
+        program: Vec<Operation>,
+    },
+    // an array of single-output functions, one per output
+    Array(Vec<Function>),
+}
+
+pub enum Operation {
+    Number(f64),
+    Operator(String),
+    Procedure(Vec<Operation>),
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+impl Function {
+    pub fn parse(doc: &Document, object: &Object) -> Result<Function, String> {
+        Self::parse_nested(doc, object, 0)
+    }
+
+    fn parse_nested(doc: &Document, object: &Object, depth: u32) -> Result<Function, String> {
+        if depth > MAX_DEPTH {
+            return Err("Functions nested too deep".to_string());
+        }
+        let (_, object) = doc.dereference(object).map_err(|e| e.to_string())?;
+        let (dict, content) = match object {
+            Object::Array(functions) => {
+                return functions.iter().map(|function| Self::parse_nested(doc, function, depth + 1)).collect::<Result<_, _>>().map(Function::Array);
+            }
+            Object::Dictionary(dict) => (dict, None),
+            Object::Stream(stream) => (&stream.dict, Some(stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()))),
+            _ => return Err("Function is not a dictionary or stream".to_string()),
+        };
+
+        let domain = limits(doc, dict, b"Domain");
+        let range = limits(doc, dict, b"Range");
+        match dict.get(b"FunctionType").and_then(Object::as_i64).map_err(|_| "Function without a type".to_string())? {
+            0 => {
+                let content = content.ok_or("Sampled function without samples")?;
+                let size: Vec<usize> = numbers(doc, dict, b"Size").iter().map(|size| (*size as usize).max(1)).collect();
+                let bits = dict.get(b"BitsPerSample").and_then(Object::as_i64).unwrap_or(8) as u32;
+                if size.len() != domain.len() || range.is_empty() || !matches!(bits, 1 | 2 | 4 | 8 | 12 | 16 | 24 | 32) {
+                    return Err("Malformed sampled function".to_string());
+                }
+                let encode = some_or(pairs(doc, dict, b"Encode"), || size.iter().map(|size| [0.0, (*size - 1) as f32]).collect());
+                let decode = some_or(pairs(doc, dict, b"Decode"), || range.clone());
+                if encode.len() != size.len() || decode.len() != range.len() {
+                    return Err("Malformed sampled function".to_string());
+                }
+                // Size comes straight from the file, a table bigger than its data is not allocated
+                let count = size.iter()
+                    .try_fold(range.len(), |count, size| count.checked_mul(*size))
+                    .filter(|count| *count <= MAX_SAMPLES && count * bits as usize <= content.len() * 8)
+                    .ok_or("Sampled function with more samples than data")?;
+                let max = ((1u64 << bits) - 1) as f32;
+                let samples = (0..count).map(|index| read_bits(&content, index * bits as usize, bits) as f32 / max).collect();
+                Ok(Function::Sampled { domain, range, size, encode, decode, samples })
+            }
+            2 => Ok(Function::Exponential {
+                domain: domain.first().copied().unwrap_or([0.0, 1.0]),
+                c0: some_or(numbers(doc, dict, b"C0"), || vec![0.0]),
+                c1: some_or(numbers(doc, dict, b"C1"), || vec![1.0]),
+                exponent: dict.get(b"N").and_then(number).unwrap_or(1.0),
+            }),
+            3 => {
+                let functions = dict.get(b"Functions")
+                    .and_then(|functions| doc.dereference(functions))
+                    .and_then(|(_, functions)| functions.as_array())
+                    .map_err(|_| "Stitching function without functions".to_string())?
+                    .iter()
+                    .map(|function| Self::parse_nested(doc, function, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let encode = pairs(doc, dict, b"Encode");
+                if functions.is_empty() || encode.len() != functions.len() {
+                    return Err("Malformed stitching function".to_string());
+                }
+                Ok(Function::Stitching { domain: domain.first().copied().unwrap_or([0.0, 1.0]), functions, bounds: numbers(doc, dict, b"Bounds"), encode })
+            }
+            4 => {
+                let content = content.ok_or("PostScript function without a program")?;
+                let mut tokens = tokenize(&content).into_iter();
+                // the whole program is one procedure
+                if tokens.next().as_deref() != Some("{") {
+                    return Err("PostScript function does not start with {".to_string());
+                }
+                Ok(Function::PostScript { domain, range, program: parse_procedure(&mut tokens)? })
+            }
+            other => Err(format!("Unsupported function type {}", other)),
+        }
+    }
+
+    pub fn eval(&self, input: &[f32]) -> Vec<f32> {
+        match self {
+            Function::Array(functions) => functions.iter().flat_map(|function| function.eval(input).into_iter().take(1)).collect(),
+            Function::Exponential { domain, c0, c1, exponent } => {
+                let x = input.first().copied().unwrap_or(0.0).clamp(domain[0], domain[1]);
+                let factor = x.powf(*exponent);
+                c0.iter().zip(c1).map(|(c0, c1)| c0 + factor * (c1 - c0)).collect()
+            }
+            Function::Stitching { domain, functions, bounds, encode } => {
+                let x = input.first().copied().unwrap_or(0.0).clamp(domain[0], domain[1]);
+                let index = bounds.iter().take_while(|bound| x >= **bound).count().min(functions.len() - 1);
+                let low = if index == 0 { domain[0] } else { bounds[index - 1] };
+                let high = bounds.get(index).copied().unwrap_or(domain[1]);
+                functions[index].eval(&[interpolate(x, [low, high], encode[index])])
+            }
+            Function::Sampled { domain, range, size, encode, decode, samples } => {
+                let outputs = range.len();
+                // each input lands between two samples, every corner of the cell around the point
+                // is weighted by how close it is. With many inputs the nearest sample is taken
+                let nearest = domain.len() > MAX_INTERPOLATED_INPUTS;
+                let positions: Vec<(usize, f32)> = domain.iter().enumerate().map(|(index, domain)| {
+                    let x = input.get(index).copied().unwrap_or(0.0).clamp(domain[0], domain[1]);
+                    let e = interpolate(x, *domain, encode[index]).clamp(0.0, (size[index] - 1) as f32);
+                    if nearest {
+                        return (e.round() as usize, 0.0);
+                    }
+                    let low = (e.floor() as usize).min(size[index].saturating_sub(2));
+                    (low, e - low as f32)
+                }).collect();
+
+                let mut output = vec![0.0; outputs];
+                let corners = if nearest { 1 } else { 1usize << positions.len() };
+                for corner in 0..corners {
+                    let mut weight = 1.0;
+                    let mut offset = 0;
+                    let mut stride = 1;
+                    for (index, (low, fraction)) in positions.iter().enumerate() {
+                        let upper = corner >> index & 1 == 1;
+                        let sample = if upper && size[index] > 1 { low + 1 } else { *low };
+                        weight *= if upper { *fraction } else { 1.0 - fraction };
+                        offset += sample * stride;
+                        stride *= size[index];
+                    }
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    for (channel, value) in output.iter_mut().enumerate() {
+                        *value += weight * samples.get(offset * outputs + channel).copied().unwrap_or(0.0);
+                    }
+                }
+                output.iter().enumerate()
+                    .map(|(index, value)| interpolate(*value, [0.0, 1.0], decode[index]).clamp(range[index][0], range[index][1]))
+                    .collect()
+            }
+            Function::PostScript { domain, range, program } => {
+                let mut stack: Vec<Value> = domain.iter().enumerate()
+                    .map(|(index, domain)| Value::Number(input.get(index).copied().unwrap_or(0.0).clamp(domain[0], domain[1]) as f64))
+                    .collect();
+                if run(program, &mut stack).is_none() || stack.len() < range.len() {
+                    return range.iter().map(|range| range[0]).collect();
+                }
+                let results = stack.split_off(stack.len() - range.len());
+                results.iter().zip(range)
+                    .map(|(value, range)| (as_number(*value) as f32).clamp(range[0], range[1]))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn interpolate(x: f32, from: [f32; 2], to: [f32; 2]) -> f32 {
+    if from[1] == from[0] {
+        return to[0];
+    }
+    to[0] + (x - from[0]) * (to[1] - to[0]) / (from[1] - from[0])
+}
+
+fn number(object: &Object) -> lopdf::Result<f32> {
+    object.as_float().or_else(|_| object.as_i64().map(|value| value as f32))
+}
+
+fn numbers(doc: &Document, dict: &Dictionary, key: &[u8]) -> Vec<f32> {
+    dict.get(key)
+        .and_then(|value| doc.dereference(value))
+        .and_then(|(_, value)| value.as_array())
+        .map(|values| values.iter().filter_map(|value| number(value).ok()).collect())
+        .unwrap_or_default()
+}
+
+fn pairs(doc: &Document, dict: &Dictionary, key: &[u8]) -> Vec<[f32; 2]> {
+    numbers(doc, dict, key).chunks_exact(2).map(|pair| [pair[0], pair[1]]).collect()
+}
+
+// Domain and Range, which inputs and outputs are clamped to, put in order since clamp panics on
+// bounds the wrong way round or NaN
+fn limits(doc: &Document, dict: &Dictionary, key: &[u8]) -> Vec<[f32; 2]> {
+    pairs(doc, dict, key).into_iter()
+        .filter(|pair| pair.iter().all(|value| value.is_finite()))
+        .map(|[low, high]| [low.min(high), low.max(high)])
+        .collect()
+}
+
+fn some_or<T>(values: Vec<T>, default: impl FnOnce() -> Vec<T>) -> Vec<T> {
+    if values.is_empty() { default() } else { values }
+}
+
+// `bits` bits from the bit offset, most significant first
+fn read_bits(data: &[u8], offset: usize, bits: u32) -> u64 {
+    let mut value = 0u64;
+    for bit in offset..offset + bits as usize {
+        let set = data.get(bit / 8).is_some_and(|byte| byte >> (7 - bit % 8) & 1 == 1);
+        value = value << 1 | set as u64;
+    }
+    value
+}
+
+fn tokenize(content: &[u8]) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for &byte in content {
+        match byte {
+            b'{' | b'}' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push((byte as char).to_string());
+            }
+            _ if byte.is_ascii_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(byte as char),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_procedure(tokens: &mut impl Iterator<Item = String>) -> Result<Vec<Operation>, String> {
+    let mut operations = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "{" => operations.push(Operation::Procedure(parse_procedure(tokens)?)),
+            "}" => return Ok(operations),
+            _ => match token.parse::<f64>() {
+                Ok(value) => operations.push(Operation::Number(value)),
+                Err(_) => operations.push(Operation::Operator(token)),
+            },
+        }
+    }
+    Err("PostScript function is missing a closing }".to_string())
+}
+
+fn as_number(value: Value) -> f64 {
+    match value {
+        Value::Number(value) => value,
+        Value::Bool(value) => value as u8 as f64,
+    }
+}
+
+fn pop_number(stack: &mut Vec<Value>) -> Option<f64> {
+    stack.pop().map(as_number)
+}
+
+// None when the program fails, such as on a stack underflow or an unknown operator
+fn run(program: &[Operation], stack: &mut Vec<Value>) -> Option<()> {
+    let mut procedures: Vec<&[Operation]> = Vec::new();
+    for operation in program {
+        let operator = match operation {
+            Operation::Number(value) => {
+                stack.push(Value::Number(*value));
+                continue;
+            }
+            Operation::Procedure(procedure) => {
+                procedures.push(procedure);
+                continue;
+            }
+            Operation::Operator(operator) => operator.as_str(),
+        };
+        if stack.len() > MAX_STACK {
+            return None;
+        }
+
+        match operator {
+            "if" => {
+                let procedure = procedures.pop()?;
+                if let Value::Bool(true) = stack.pop()? {
+                    run(procedure, stack)?;
+                }
+            }
+            "ifelse" => {
+                let (otherwise, then) = (procedures.pop()?, procedures.pop()?);
+                let Value::Bool(condition) = stack.pop()? else { return None };
+                run(if condition { then } else { otherwise }, stack)?;
+            }
+            "true" | "false" => stack.push(Value::Bool(operator == "true")),
+            "pop" => {
+                stack.pop()?;
+            }
+            "dup" => stack.push(*stack.last()?),
+            "exch" => {
+                let length = stack.len();
+                if length < 2 {
+                    return None;
+                }
+                stack.swap(length - 1, length - 2);
+            }
+            "copy" => {
+                let count = pop_number(stack)? as usize;
+                let start = stack.len().checked_sub(count)?;
+                stack.extend_from_within(start..);
+            }
+            "index" => {
+                let index = pop_number(stack)? as usize;
+                let value = *stack.get(stack.len().checked_sub(index + 1)?)?;
+                stack.push(value);
+            }
+            "roll" => {
+                let (shift, count) = (pop_number(stack)? as i64, pop_number(stack)? as usize);
+                let start = stack.len().checked_sub(count)?;
+                if count > 0 {
+                    stack[start..].rotate_right(shift.rem_euclid(count as i64) as usize);
+                }
+            }
+            "not" => match stack.pop()? {
+                Value::Bool(value) => stack.push(Value::Bool(!value)),
+                Value::Number(value) => stack.push(Value::Number(!(value as i64) as f64)),
+            },
+            "and" | "or" | "xor" => {
+                let (second, first) = (stack.pop()?, stack.pop()?);
+                let value = match (first, second) {
+                    (Value::Bool(first), Value::Bool(second)) => Value::Bool(match operator {
+                        "and" => first && second,
+                        "or" => first || second,
+                        _ => first ^ second,
+                    }),
+                    (first, second) => {
+                        let (first, second) = (as_number(first) as i64, as_number(second) as i64);
+                        Value::Number(match operator {
+                            "and" => first & second,
+                            "or" => first | second,
+                            _ => first ^ second,
+                        } as f64)
+                    }
+                };
+                stack.push(value);
+            }
+            "eq" | "ne" | "gt" | "ge" | "lt" | "le" => {
+                let (second, first) = (pop_number(stack)?, pop_number(stack)?);
+                stack.push(Value::Bool(match operator {
+                    "eq" => first == second,
+                    "ne" => first != second,
+                    "gt" => first > second,
+                    "ge" => first >= second,
+                    "lt" => first < second,
+                    _ => first <= second,
+                }));
+            }
+            "add" | "sub" | "mul" | "div" | "idiv" | "mod" | "exp" | "atan" | "bitshift" => {
+                let (second, first) = (pop_number(stack)?, pop_number(stack)?);
+                let value = match operator {
+                    "add" => first + second,
+                    "sub" => first - second,
+                    "mul" => first * second,
+                    "div" if second != 0.0 => first / second,
+                    "idiv" if second as i64 != 0 => (first as i64 / second as i64) as f64,
+                    "mod" if second as i64 != 0 => (first as i64 % second as i64) as f64,
+                    "exp" => first.powf(second),
+                    "atan" => first.atan2(second).to_degrees().rem_euclid(360.0),
+                    "bitshift" if second >= 0.0 => ((first as i64) << (second as i64).min(63)) as f64,
+                    "bitshift" => ((first as i64) >> (-second as i64).min(63)) as f64,
+                    _ => return None,
+                };
+                stack.push(Value::Number(value));
+            }
+            "abs" | "neg" | "ceiling" | "floor" | "round" | "truncate" | "cvi" | "cvr" | "sqrt" | "sin" | "cos" | "ln" | "log" => {
+                let value = pop_number(stack)?;
+                let value = match operator {
+                    "abs" => value.abs(),
+                    "neg" => -value,
+                    "ceiling" => value.ceil(),
+                    "floor" => value.floor(),
+                    // PostScript rounds halves up, also for negative numbers
+                    "round" => (value + 0.5).floor(),
+                    "truncate" | "cvi" => value.trunc(),
+                    "cvr" => value,
+                    "sqrt" => value.sqrt(),
+                    "sin" => value.to_radians().sin(),
+                    "cos" => value.to_radians().cos(),
+                    "ln" => value.ln(),
+                    _ => value.log10(),
+                };
+                stack.push(Value::Number(value));
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    fn parse(dict: Dictionary) -> Function {
+        Function::parse(&Document::new(), &Object::Dictionary(dict)).unwrap()
+    }
+
+    #[test]
+    fn puts_inverted_domains_and_ranges_in_order() {
+        let exponential = parse(dictionary! {
+            "FunctionType" => 2, "Domain" => vec![1.into(), 0.into()], "C0" => vec![0.into()], "C1" => vec![1.into()], "N" => 1,
+        });
+        assert_eq!(exponential.eval(&[2.0]), vec![1.0]);
+        assert_eq!(exponential.eval(&[-1.0]), vec![0.0]);
+
+        let postscript = Function::parse(&Document::new(), &Object::Stream(lopdf::Stream::new(
+            dictionary! { "FunctionType" => 4, "Domain" => vec![1.into(), 0.into()], "Range" => vec![1.into(), 0.into()] },
+            b"{ 2 mul }".to_vec(),
+        ))).unwrap();
+        assert_eq!(postscript.eval(&[0.25]), vec![0.5]);
+        assert_eq!(postscript.eval(&[0.75]), vec![1.0]);
+    }
+
+    fn assert_near(actual: Vec<f32>, expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        assert!(actual.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} is not {:?}", actual, expected);
+    }
+
+    fn exponential(c0: f32, c1: f32) -> Object {
+        Object::Dictionary(dictionary! { "FunctionType" => 2, "Domain" => vec![0.into(), 1.into()], "C0" => vec![c0.into()], "C1" => vec![c1.into()], "N" => 1 })
+    }
+
+    #[test]
+    fn reads_bits_across_byte_boundaries() {
+        let data = [0b1010_1100, 0b0101_0011];
+        assert_eq!(read_bits(&data, 0, 4), 0b1010);
+        assert_eq!(read_bits(&data, 4, 8), 0b1100_0101);
+        assert_eq!(read_bits(&data, 2, 1), 1);
+        assert_eq!(read_bits(&data, 0, 16), 0xAC53);
+        // bits past the end of the data read as 0
+        assert_eq!(read_bits(&data, 14, 4), 0b1100);
+    }
+
+    #[test]
+    fn evaluates_exponential_functions() {
+        let function = parse(dictionary! {
+            "FunctionType" => 2, "Domain" => vec![0.into(), 1.into()], "C0" => vec![0.into(), 0.5.into()], "C1" => vec![1.into(), 1.into()], "N" => 2,
+        });
+        assert_near(function.eval(&[0.5]), &[0.25, 0.625]);
+        assert_near(function.eval(&[1.0]), &[1.0, 1.0]);
+        // without C0 and C1 the output runs from 0 to 1
+        assert_near(parse(dictionary! { "FunctionType" => 2, "Domain" => vec![0.into(), 1.into()], "N" => 1 }).eval(&[0.3]), &[0.3]);
+    }
+
+    #[test]
+    fn evaluates_stitching_functions() {
+        let function = parse(dictionary! {
+            "FunctionType" => 3,
+            "Domain" => vec![0.into(), 1.into()],
+            "Functions" => vec![exponential(0.0, 1.0), exponential(0.0, 1.0)],
+            "Bounds" => vec![0.5.into()],
+            // the second half runs backwards
+            "Encode" => vec![0.into(), 1.into(), 1.into(), 0.into()],
+        });
+        assert_near(function.eval(&[0.1]), &[0.2]);
+        assert_near(function.eval(&[0.25]), &[0.5]);
+        // a bound belongs to the function above it
+        assert_near(function.eval(&[0.5]), &[1.0]);
+        assert_near(function.eval(&[0.6]), &[0.8]);
+        assert_near(function.eval(&[1.5]), &[0.0]);
+
+        let unencoded = dictionary! { "FunctionType" => 3, "Domain" => vec![0.into(), 1.into()], "Functions" => vec![exponential(0.0, 1.0)] };
+        assert!(Function::parse(&Document::new(), &Object::Dictionary(unencoded)).is_err());
+    }
+
+    #[test]
+    fn runs_postscript_functions() {
+        let program = |program: &[u8]| Function::parse(&Document::new(), &Object::Stream(lopdf::Stream::new(
+            dictionary! { "FunctionType" => 4, "Domain" => vec![0.into(), 1.into(), 0.into(), 1.into()], "Range" => vec![0.into(), 1.into(), 0.into(), 1.into()] },
+            program.to_vec(),
+        ))).unwrap();
+
+        // doubles the first input up to a half and saturates above it, inverts the second
+        let function = program(b"{ exch dup 0.5 gt { pop 1 } { 2 mul } ifelse exch 1 exch sub }");
+        assert_near(function.eval(&[0.25, 0.1]), &[0.5, 0.9]);
+        assert_near(function.eval(&[0.75, 0.4]), &[1.0, 0.6]);
+        assert_near(program(b"{ 2 copy add 3 1 roll mul 1 index add }").eval(&[0.5, 0.25]), &[0.75, 0.875]);
+
+        // a program that fails gives the low end of every range
+        assert_near(program(b"{ add add }").eval(&[0.5, 0.5]), &[0.0, 0.0]);
+        assert!(Function::parse(&Document::new(), &Object::Stream(lopdf::Stream::new(dictionary! { "FunctionType" => 4 }, b"2 mul".to_vec()))).is_err());
+    }
+}
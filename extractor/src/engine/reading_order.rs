@@ -0,0 +1,185 @@
+use crate::engine::layout::{self, Block, LayoutSource, Line, PageLayout};
+
+// Puts the blocks of a page in the order a person would read them. Content streams list text in
+// whatever order the producer wrote it, which interleaves the columns of papers and newsletters.
+//
+// Text layer lines are first regrouped into blocks by position. Blocks are then ordered with the
+// rules from Breuel's "High Performance Document Layout Analysis": a block comes before another
+// one it overlaps horizontally and sits above, and before one entirely to its right unless a
+// block spanning both lies between them, like a full-width heading between two sets of columns.
+
+// relative to the line height
+const MAX_LINE_GAP: f32 = 1.0;
+const MAX_LINE_OVERLAP: f32 = 0.5;
+const MAX_HEIGHT_RATIO: f32 = 1.3;
+// share of the narrower line that has to sit under the wider one to continue its block
+const MIN_SHARED_WIDTH: f32 = 0.5;
+// the pairwise rules are cubic in the number of blocks, pages with more are most likely tables
+// or maps and are read top to bottom instead
+const MAX_ORDERED_BLOCKS: usize = 300;
+
+pub fn order(page_layout: PageLayout) -> PageLayout {
+    // OCR engines already split their output into blocks, only the text layer comes in content order
+    let (text_blocks, mut blocks): (Vec<Block>, Vec<Block>) = page_layout.blocks.into_iter()
+        .partition(|block| block.source == LayoutSource::TextLayer);
+    blocks.extend(regroup(text_blocks.into_iter().flat_map(|block| block.lines).collect()));
+    PageLayout { blocks: sort_blocks(blocks) }
+}
+
+fn regroup(mut lines: Vec<Line>) -> Vec<Block> {
+    lines.sort_by(|a, b| b.bbox[3].total_cmp(&a.bbox[3]).then(a.bbox[0].total_cmp(&b.bbox[0])));
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in lines {
+        let height = line.bbox[3] - line.bbox[1];
+        let continued = blocks.iter_mut()
+            .filter_map(|block| {
+                let previous = block.lines.last()?.bbox;
+                let previous_height = previous[3] - previous[1];
+                let tallest = height.max(previous_height);
+                let gap = previous[1] - line.bbox[3];
+                let shared = previous[2].min(line.bbox[2]) - previous[0].max(line.bbox[0]);
+                let narrowest = (previous[2] - previous[0]).min(line.bbox[2] - line.bbox[0]);
+
+                let fits = gap > -MAX_LINE_OVERLAP * tallest
+                    && gap < MAX_LINE_GAP * tallest
+                    && shared > MIN_SHARED_WIDTH * narrowest
+                    && tallest <= MAX_HEIGHT_RATIO * height.min(previous_height).max(f32::EPSILON);
+                fits.then_some((gap, block))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, block)| block);
+
+        match continued {
+            Some(block) => {
+                block.bbox = layout::union([block.bbox, line.bbox]);
+                block.lines.push(line);
+            }
+            None => blocks.push(Block { bbox: line.bbox, source: LayoutSource::TextLayer, lines: vec![line] }),
+        }
+    }
+    blocks
+}
+
+fn sort_blocks(mut blocks: Vec<Block>) -> Vec<Block> {
+    if blocks.len() > MAX_ORDERED_BLOCKS {
+        blocks.sort_by(|a, b| b.bbox[3].total_cmp(&a.bbox[3]).then(a.bbox[0].total_cmp(&b.bbox[0])));
+        return blocks;
+    }
+
+    let boxes: Vec<[f32; 4]> = blocks.iter().map(|block| block.bbox).collect();
+    let outgoing: Vec<Vec<usize>> = (0..boxes.len())
+        .map(|a| (0..boxes.len()).filter(|b| a != *b && comes_before(&boxes, a, *b)).collect())
+        .collect();
+    let mut incoming = vec![0usize; boxes.len()];
+    for b in outgoing.iter().flatten() {
+        incoming[*b] += 1;
+    }
+
+    // among the blocks free to go next the top-most, then left-most one wins. Overlapping blocks
+    // can make the rules circular, then the best remaining block goes next regardless
+    let mut placed = vec![false; boxes.len()];
+    let mut order = Vec::with_capacity(boxes.len());
+    while order.len() < boxes.len() {
+        let next = (0..boxes.len())
+            .filter(|index| !placed[*index])
+            .min_by(|a, b| {
+                (incoming[*a] > 0).cmp(&(incoming[*b] > 0))
+                    .then(boxes[*b][3].total_cmp(&boxes[*a][3]))
+                    .then(boxes[*a][0].total_cmp(&boxes[*b][0]))
+            });
+        let Some(next) = next else {
+            break;
+        };
+        placed[next] = true;
+        order.push(next);
+        for after in &outgoing[next] {
+            incoming[*after] = incoming[*after].saturating_sub(1);
+        }
+    }
+
+    let mut blocks: Vec<Option<Block>> = blocks.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|index| blocks[index].take()).collect()
+}
+
+fn comes_before(boxes: &[[f32; 4]], a: usize, b: usize) -> bool {
+    let (first, second) = (&boxes[a], &boxes[b]);
+    // tops rather than centres, a tall block starting higher up is read first
+    if overlaps_horizontally(first, second) {
+        return first[3] > second[3];
+    }
+    if first[2] > second[0] {
+        return false;
+    }
+    let (low, high) = (center_y(first).min(center_y(second)), center_y(first).max(center_y(second)));
+    !boxes.iter().enumerate().any(|(c, between)| {
+        c != a && c != b
+            && center_y(between) > low
+            && center_y(between) < high
+            && overlaps_horizontally(between, first)
+            && overlaps_horizontally(between, second)
+    })
+}
+
+fn overlaps_horizontally(a: &[f32; 4], b: &[f32; 4]) -> bool {
+    a[2].min(b[2]) > a[0].max(b[0])
+}
+
+fn center_y(bbox: &[f32; 4]) -> f32 {
+    (bbox[1] + bbox[3]) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::layout::Word;
+
+    // a block of one 10pt line, as the text layer or OCR hands it over
+    fn block(text: &str, x0: f32, top: f32, x1: f32, source: LayoutSource) -> Block {
+        let bbox = [x0, top - 10.0, x1, top];
+        let word = Word { text: text.to_string(), bbox, font_name: None, font_size: Some(10.0), confidence: None };
+        Block { bbox, source, lines: vec![Line { bbox, words: vec![word] }] }
+    }
+
+    fn texts(layout: &PageLayout) -> Vec<String> {
+        layout.blocks.iter().map(Block::text).collect()
+    }
+
+    #[test]
+    fn reads_columns_one_after_the_other() {
+        let line = |text: &str, x0: f32, top: f32| block(text, x0, top, x0 + 200.0, LayoutSource::TextLayer);
+        // in content order the columns are interleaved line by line, around a full-width heading
+        let blocks = vec![
+            line("top left 1", 50.0, 700.0), line("top right 1", 300.0, 700.0),
+            line("top left 2", 50.0, 688.0), line("top right 2", 300.0, 688.0),
+            line("bottom left 1", 50.0, 600.0), line("bottom right 1", 300.0, 600.0),
+            line("bottom left 2", 50.0, 588.0), line("bottom right 2", 300.0, 588.0),
+            block("Heading across both columns", 50.0, 650.0, 500.0, LayoutSource::TextLayer),
+            block("Title", 50.0, 760.0, 500.0, LayoutSource::TextLayer),
+        ];
+        assert_eq!(texts(&order(PageLayout { blocks })), vec![
+            "Title",
+            "top left 1\ntop left 2",
+            "top right 1\ntop right 2",
+            "Heading across both columns",
+            "bottom left 1\nbottom left 2",
+            "bottom right 1\nbottom right 2",
+        ]);
+    }
+
+    #[test]
+    fn reads_crowded_pages_top_to_bottom() {
+        let columns = |rows: usize| {
+            let blocks = (0..rows).flat_map(|row| {
+                let top = 1000.0 - row as f32 * 12.0;
+                [block(&format!("L{}", row), 0.0, top, 100.0, LayoutSource::Ocr), block(&format!("R{}", row), 200.0, top, 300.0, LayoutSource::Ocr)]
+            }).collect();
+            texts(&order(PageLayout { blocks }))
+        };
+        assert_eq!(columns(3), vec!["L0", "L1", "L2", "R0", "R1", "R2"]);
+        // past the limit the columns are no longer worked out
+        let crowded = columns(MAX_ORDERED_BLOCKS / 2 + 1);
+        assert_eq!(crowded.len(), MAX_ORDERED_BLOCKS + 2);
+        assert_eq!(crowded[..4], ["L0", "R0", "L1", "R1"]);
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use pdfium_render::prelude::{PdfDocument, PdfRenderConfig, PdfiumError, PdfiumInternalError};
+
+use crate::{libs::pdfium::get_pdfium, types::extract_error::ExtractError};
+
+pub const DEFAULT_RENDER_DPI: u32 = 300;
+const MIN_RENDER_DPI: u32 = 72;
+const MAX_RENDER_DPI: u32 = 600;
+
+pub fn clamp_dpi(dpi: Option<u32>) -> u32 {
+    dpi.unwrap_or(DEFAULT_RENDER_DPI).clamp(MIN_RENDER_DPI, MAX_RENDER_DPI)
+}
+
+// Rasterise a single page (1-based) through pdfium and save it as an image at `out_path`
+pub fn render_page(pdf_path: &Path, password: Option<&str>, page_num: u32, dpi: u32, out_path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let document = open_document(pdf_path, password)?;
+    let page = document.pages().get(page_index(page_num)?)
+        .map_err(|e| format!("pdfium failed to load page {}: {}", page_num, e))?;
+
+    let config = PdfRenderConfig::new().scale_page_by_factor(dpi as f32 / 72.0);
+    let bitmap = page.render_with_config(&config)
+        .map_err(|e| format!("pdfium failed to render page {}: {}", page_num, e))?;
+
+    bitmap.as_image().into_rgb8().save(out_path)?;
+    Ok(())
+}
+
+pub fn page_count(pdf_path: &Path, password: Option<&str>) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+    let document = open_document(pdf_path, password)?;
+    Ok(document.pages().len() as u32)
+}
+
+pub fn page_text(pdf_path: &Path, password: Option<&str>, page_num: u32) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let document = open_document(pdf_path, password)?;
+    let page = document.pages().get(page_index(page_num)?)
+        .map_err(|e| format!("pdfium failed to load page {}: {}", page_num, e))?;
+    let text = page.text()
+        .map_err(|e| format!("pdfium failed to read the text of page {}: {}", page_num, e))?;
+    Ok(text.all())
+}
+
+pub async fn page_text_async(pdf_path: PathBuf, password: Option<String>, page_num: u32) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    tokio::task::spawn_blocking(move || page_text(&pdf_path, password.as_deref(), page_num)).await?
+}
+
+pub async fn render_page_async(pdf_path: PathBuf, password: Option<String>, page_num: u32, dpi: u32, out_path: PathBuf) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tokio::task::spawn_blocking(move || render_page(&pdf_path, password.as_deref(), page_num, dpi, &out_path)).await?
+}
+
+pub fn open_document<'a>(pdf_path: &Path, password: Option<&'a str>) -> Result<PdfDocument<'a>, Box<dyn std::error::Error + Send + Sync>> {
+    let pdfium = get_pdfium()?;
+    pdfium.load_pdf_from_file(pdf_path, password).map_err(|e| match e {
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => match password {
+            Some(_) => ExtractError::WrongPassword.into(),
+            None => ExtractError::PasswordRequired.into(),
+        },
+        e => format!("pdfium failed to open {:?}: {}", pdf_path, e).into(),
+    })
+}
+
+fn page_index(page_num: u32) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
+    u16::try_from(page_num.saturating_sub(1))
+        .map_err(|_| format!("Page {} is out of range for pdfium", page_num).into())
+}
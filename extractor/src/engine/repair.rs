@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+
+use lopdf::{Document, Object};
+
+// Best-effort reconstruction of PDFs whose cross-reference table or trailer is broken, which is
+// common with scanner firmware and truncated uploads. The file is scanned for `N G obj` headers,
+// and a fresh xref section and trailer pointing at them are appended so lopdf can load it again.
+
+const HEADER_SEARCH_LIMIT: usize = 1024;
+
+pub struct RepairedPdf {
+    pub bytes: Vec<u8>,
+    pub repairs: Vec<String>,
+}
+
+pub fn rebuild_xref(original: &[u8]) -> Option<RepairedPdf> {
+    let mut repairs = Vec::new();
+
+    let mut bytes = match find(original, b"%PDF-", 0) {
+        Some(0) => original.to_vec(),
+        Some(start) if start < HEADER_SEARCH_LIMIT => {
+            repairs.push(format!("stripped {} bytes before the %PDF header", start));
+            original[start..].to_vec()
+        }
+        _ => {
+            repairs.push("added a missing %PDF header".to_string());
+            let mut bytes = b"%PDF-1.4\n".to_vec();
+            bytes.extend_from_slice(original);
+            bytes
+        }
+    };
+
+    let objects = scan_objects(&bytes);
+    if objects.is_empty() {
+        return None;
+    }
+    repairs.push(format!("rebuilt the cross-reference table from {} objects found by scanning the file", objects.len()));
+
+    // a catalog kept inside a compressed object stream is not visible here, `restore_catalog`
+    // finds it once lopdf has unpacked the object streams
+    let root = match find_last_reference(&bytes, b"/Root").filter(|(id, _)| objects.contains_key(id)) {
+        Some(root) => Some(root),
+        None => {
+            let root = find_catalog(&bytes, &objects);
+            if let Some((id, generation)) = root {
+                repairs.push(format!("recovered the document catalog {} {} R by scanning for /Type /Catalog", id, generation));
+            }
+            root
+        }
+    };
+
+    let mut trailer = root.map_or_else(String::new, |(id, generation)| format!("/Root {} {} R", id, generation));
+    for key in [&b"/Info"[..], &b"/Encrypt"[..]] {
+        if let Some((id, generation)) = find_last_reference(&bytes, key) {
+            if objects.contains_key(&id) {
+                trailer.push_str(&format!(" {} {} {} R", String::from_utf8_lossy(key), id, generation));
+            }
+        }
+    }
+    if let Some(id) = find_id_array(&bytes) {
+        trailer.push_str(" /ID ");
+        trailer.push_str(&id);
+    }
+
+    let size = objects.keys().next_back().map_or(1, |max_id| max_id + 1);
+    bytes.push(b'\n');
+    let xref_offset = bytes.len();
+    // a subsection per run of consecutive object numbers, so a stray huge number costs one entry
+    let mut xref = "xref\n0 1\n0000000000 65535 f\r\n".to_string();
+    let found: Vec<(u32, (usize, u16))> = objects.into_iter().collect();
+    for run in found.chunk_by(|previous, next| next.0 == previous.0 + 1) {
+        xref.push_str(&format!("{} {}\n", run[0].0, run.len()));
+        for (_, (offset, generation)) in run {
+            xref.push_str(&format!("{:010} {:05} n\r\n", offset, generation));
+        }
+    }
+    xref.push_str(&format!("trailer\n<< /Size {} {} >>\nstartxref\n{}\n%%EOF\n", size, trailer, xref_offset));
+    bytes.extend_from_slice(xref.as_bytes());
+
+    Some(RepairedPdf { bytes, repairs })
+}
+
+pub fn restore_catalog(doc: &mut Document) -> Option<String> {
+    if doc.trailer.get(b"Root").is_ok() {
+        return None;
+    }
+    let (id, _) = doc.objects.iter()
+        .filter(|(_, object)| object.as_dict().is_ok_and(|dict| dict.type_is(b"Catalog")))
+        .max_by_key(|(id, _)| **id)?;
+    let id = *id;
+    doc.trailer.set("Root", Object::Reference(id));
+    Some(format!("recovered the document catalog {} {} R from an object stream", id.0, id.1))
+}
+
+// object number -> (offset, generation); later definitions win, like incremental updates
+fn scan_objects(bytes: &[u8]) -> BTreeMap<u32, (usize, u16)> {
+    let mut objects = BTreeMap::new();
+    let mut position = 0;
+
+    while let Some(found) = find(bytes, b"obj", position) {
+        position = found + 3;
+        if bytes.get(position).is_some_and(|c| c.is_ascii_alphanumeric()) {
+            continue;
+        }
+        if let Some((start, id, generation)) = parse_object_header(bytes, found) {
+            objects.insert(id, (start, generation));
+        }
+    }
+    objects
+}
+
+// walks back from the `obj` keyword over `<id> <generation> `
+fn parse_object_header(bytes: &[u8], obj_position: usize) -> Option<(usize, u32, u16)> {
+    let mut cursor = obj_position;
+    let generation_end = skip_whitespace_back(bytes, cursor)?;
+    cursor = skip_digits_back(bytes, generation_end);
+    if cursor == generation_end {
+        return None;
+    }
+    let generation = parse_number(&bytes[cursor..generation_end])?;
+
+    let id_end = skip_whitespace_back(bytes, cursor)?;
+    let id_start = skip_digits_back(bytes, id_end);
+    if id_start == id_end {
+        return None;
+    }
+    if id_start > 0 && !is_delimiter(bytes[id_start - 1]) {
+        return None;
+    }
+    // object 0 heads the free list, and the xref Size of the largest number plus one has to fit
+    let id = u32::try_from(parse_number(&bytes[id_start..id_end])?).ok().filter(|id| *id > 0 && *id < u32::MAX)?;
+
+    Some((id_start, id, u16::try_from(generation).ok()?))
+}
+
+fn find_last_reference(bytes: &[u8], key: &[u8]) -> Option<(u32, u16)> {
+    let mut last = None;
+    let mut position = 0;
+    while let Some(found) = find(bytes, key, position) {
+        position = found + key.len();
+        if let Some(reference) = parse_reference(&bytes[position..]) {
+            last = Some(reference);
+        }
+    }
+    last
+}
+
+fn parse_reference(bytes: &[u8]) -> Option<(u32, u16)> {
+    let text = std::str::from_utf8(&bytes[..bytes.len().min(32)]).unwrap_or_else(|e| {
+        std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or("")
+    });
+    let mut parts = text.split_whitespace();
+    let id = parts.next()?.parse::<u32>().ok()?;
+    let generation = parts.next()?.parse::<u16>().ok()?;
+    parts.next()?.starts_with('R').then_some((id, generation))
+}
+
+fn find_catalog(bytes: &[u8], objects: &BTreeMap<u32, (usize, u16)>) -> Option<(u32, u16)> {
+    let mut position = 0;
+    let mut catalog = None;
+    while let Some(found) = find(bytes, b"/Catalog", position) {
+        position = found + 1;
+        let preceding = bytes[..found].iter().rev()
+            .skip_while(|c| c.is_ascii_whitespace())
+            .take(5)
+            .copied()
+            .collect::<Vec<u8>>();
+        if preceding != b"epyT/" {
+            continue;
+        }
+        // the catalog is the object whose header is closest before the match
+        catalog = objects.iter()
+            .filter(|(_, (offset, _))| *offset < found)
+            .max_by_key(|(_, (offset, _))| *offset)
+            .map(|(id, (_, generation))| (*id, *generation));
+    }
+    catalog
+}
+
+fn find_id_array(bytes: &[u8]) -> Option<String> {
+    let mut position = 0;
+    let mut last = None;
+    while let Some(found) = find(bytes, b"/ID", position) {
+        position = found + 3;
+        let rest = &bytes[position..];
+        let start = rest.iter().position(|c| !c.is_ascii_whitespace())?;
+        if rest[start] != b'[' {
+            continue;
+        }
+        let end = rest[start..].iter().position(|c| *c == b']')?;
+        last = Some(String::from_utf8_lossy(&rest[start..=start + end]).into_owned());
+    }
+    last
+}
+
+fn find(bytes: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    if from >= bytes.len() {
+        return None;
+    }
+    bytes[from..].windows(pattern.len())
+        .position(|window| window == pattern)
+        .map(|position| position + from)
+}
+
+fn skip_whitespace_back(bytes: &[u8], end: usize) -> Option<usize> {
+    let mut cursor = end;
+    while cursor > 0 && bytes[cursor - 1].is_ascii_whitespace() {
+        cursor -= 1;
+    }
+    (cursor < end).then_some(cursor)
+}
+
+fn skip_digits_back(bytes: &[u8], end: usize) -> usize {
+    let mut cursor = end;
+    while cursor > 0 && bytes[cursor - 1].is_ascii_digit() {
+        cursor -= 1;
+    }
+    cursor
+}
+
+fn parse_number(digits: &[u8]) -> Option<u64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn is_delimiter(c: u8) -> bool {
+    c.is_ascii_whitespace() || matches!(c, b'>' | b')' | b']' | b'}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+        2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n\
+        3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>\nendobj\n";
+
+    fn page_count(bytes: &[u8]) -> usize {
+        Document::load_mem(bytes).expect("repaired PDF loads").get_pages().len()
+    }
+
+    #[test]
+    fn rebuilds_a_broken_xref_and_trailer() {
+        let damaged = format!("%PDF-1.4\n{}xref\ngarbage\ntrailer\n<< /Root 1 0 R >>\nstartxref\n99999\n%%EOF\n", BODY);
+        let repaired = rebuild_xref(damaged.as_bytes()).expect("objects are found");
+        assert_eq!(page_count(&repaired.bytes), 1);
+        assert_eq!(repaired.repairs.len(), 1);
+    }
+
+    #[test]
+    fn recovers_the_catalog_without_a_trailer() {
+        let truncated = format!("%PDF-1.4\n{}", BODY);
+        let repaired = rebuild_xref(truncated.as_bytes()).expect("objects are found");
+        assert_eq!(page_count(&repaired.bytes), 1);
+        assert!(repaired.repairs.iter().any(|repair| repair.contains("1 0 R")));
+    }
+
+    #[test]
+    fn fixes_the_header() {
+        let prefixed = format!("junk before the header\n%PDF-1.4\n{}", BODY);
+        let repaired = rebuild_xref(prefixed.as_bytes()).expect("objects are found");
+        assert!(repaired.bytes.starts_with(b"%PDF-1.4"));
+        assert!(repaired.repairs[0].starts_with("stripped 23 bytes"));
+
+        let headless = rebuild_xref(BODY.as_bytes()).expect("objects are found");
+        assert!(headless.bytes.starts_with(b"%PDF-1.4"));
+        assert_eq!(headless.repairs[0], "added a missing %PDF header");
+        assert_eq!(page_count(&headless.bytes), 1);
+    }
+
+    #[test]
+    fn gives_up_without_objects() {
+        assert!(rebuild_xref(b"%PDF-1.4\nnothing here\n%%EOF\n").is_none());
+        assert!(rebuild_xref(b"").is_none());
+    }
+
+    #[test]
+    fn later_definitions_win() {
+        let bytes = b"%PDF-1.4\n4 0 obj\n(old)\nendobj\n4 0 obj\n(new)\nendobj\n12 3 obj\nnull\nendobj\nnot5 0 obj 6 0 object\n";
+        let objects = scan_objects(bytes);
+        assert_eq!(objects.keys().copied().collect::<Vec<_>>(), vec![4, 12]);
+        assert_eq!(objects[&4], (find(bytes, b"4 0 obj\n(new)", 0).unwrap(), 0));
+        assert_eq!(objects[&12], (find(bytes, b"12 3 obj", 0).unwrap(), 3));
+    }
+
+    #[test]
+    fn writes_huge_object_numbers_as_their_own_subsection() {
+        let bytes = format!("%PDF-1.4\n{}4294967294 0 obj\nnull\nendobj\n4294967295 0 obj\nnull\nendobj\n99999999999 0 obj\nnull\nendobj\n", BODY);
+        assert_eq!(scan_objects(bytes.as_bytes()).keys().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4294967294]);
+
+        let repaired = rebuild_xref(bytes.as_bytes()).expect("objects are found");
+        assert!(repaired.bytes.len() < bytes.len() + 200);
+        let xref = String::from_utf8_lossy(&repaired.bytes[bytes.len()..]).into_owned();
+        assert!(xref.contains("\n0 1\n") && xref.contains("\n1 3\n") && xref.contains("\n4294967294 1\n"));
+        assert!(xref.contains("/Size 4294967295"));
+        assert_eq!(page_count(&repaired.bytes), 1);
+    }
+
+    #[test]
+    fn parses_references() {
+        assert_eq!(parse_reference(b" 3 0 R"), Some((3, 0)));
+        assert_eq!(parse_reference(b"\n12 5 R/Info"), Some((12, 5)));
+        assert_eq!(parse_reference(b" 3 0 obj"), None);
+        assert_eq!(parse_reference(b" 3 R"), None);
+        assert_eq!(parse_reference(b" 3 70000 R"), None);
+        assert_eq!(find_last_reference(b"/Root 1 0 R /Root 7 0 R", b"/Root"), Some((7, 0)));
+    }
+
+    #[test]
+    fn keeps_the_last_id_array() {
+        let bytes = b"/ID [<01><02>] /IDTree 5 0 R /ID\n[<0a><0b>]";
+        assert_eq!(find_id_array(bytes).as_deref(), Some("[<0a><0b>]"));
+    }
+}
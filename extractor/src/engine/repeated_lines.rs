@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{engine::layout::{self, Block, PageLayout}, types::engine_handler::PageExtractInfo};
+
+// Running titles, page numbers and confidentiality notices repeat at the same spot on every page.
+// They are found by comparing the outermost lines of all pages and moved out of the body text
+// into the `header` and `footer` of each page, so they do not end up in search indexes once per page.
+
+// how many lines from the top and from the bottom of a page can be part of a header or footer
+const EDGE_LINES: usize = 3;
+// how far a repeated line may move between pages, in points
+const POSITION_TOLERANCE: f32 = 6.0;
+// pages a line has to repeat on. On two pages a heading both start with cannot be told from a
+// running title, so shorter documents are left as they are
+const MIN_REPEATS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Edge {
+    Top,
+    Bottom,
+}
+
+struct Candidate {
+    page: usize,
+    block: usize,
+    line: usize,
+    center_y: f32,
+}
+
+pub fn move_headers_and_footers(pages: &mut [PageExtractInfo]) {
+    if pages.iter().filter(|page| page.layout.is_some()).count() < MIN_REPEATS {
+        return;
+    }
+
+    // the same text at the same edge, with digits ignored so "Page 3 of 10" matches "Page 4 of 10"
+    let mut groups: HashMap<(Edge, String), Vec<Candidate>> = HashMap::new();
+    let mut edge_lines: Vec<(Edge, Candidate)> = Vec::new();
+    for (page_index, page) in pages.iter().enumerate() {
+        let Some(page_layout) = &page.layout else {
+            continue;
+        };
+        let mut lines: Vec<(usize, usize, [f32; 4], String)> = page_layout.blocks.iter().enumerate()
+            .flat_map(|(block_index, block)| block.lines.iter().enumerate()
+                .map(move |(line_index, line)| (block_index, line_index, line.bbox, line.text())))
+            .collect();
+        lines.sort_by(|a, b| b.2[3].total_cmp(&a.2[3]));
+
+        // short pages are split between the two edges so no line counts as both
+        let top_count = EDGE_LINES.min(lines.len().div_ceil(2));
+        let bottom_count = EDGE_LINES.min(lines.len() - top_count);
+        let edges = lines.iter().take(top_count).map(|line| (Edge::Top, line))
+            .chain(lines.iter().rev().take(bottom_count).map(|line| (Edge::Bottom, line)));
+        for (edge, (block, line, bbox, text)) in edges {
+            let candidate = || Candidate {
+                page: page_index,
+                block: *block,
+                line: *line,
+                center_y: (bbox[1] + bbox[3]) / 2.0,
+            };
+            edge_lines.push((edge, candidate()));
+            let key = normalize(text);
+            if !key.is_empty() {
+                groups.entry((edge, key)).or_default().push(candidate());
+            }
+        }
+    }
+
+    let mut repeated: HashMap<usize, Vec<(Edge, usize, usize)>> = HashMap::new();
+    let mut baselines: Vec<(usize, Edge, f32)> = Vec::new();
+    for ((edge, _), candidates) in groups {
+        let mut positions: Vec<f32> = candidates.iter().map(|candidate| candidate.center_y).collect();
+        positions.sort_by(f32::total_cmp);
+        let median = positions[positions.len() / 2];
+        let matching: Vec<&Candidate> = candidates.iter()
+            .filter(|candidate| (candidate.center_y - median).abs() <= POSITION_TOLERANCE)
+            .collect();
+
+        let distinct_pages: HashSet<usize> = matching.iter().map(|candidate| candidate.page).collect();
+        if distinct_pages.len() < MIN_REPEATS {
+            continue;
+        }
+        for candidate in matching {
+            baselines.push((candidate.page, edge, candidate.center_y));
+        }
+    }
+
+    // lines sharing a row with a repeated one belong to the same header or footer, like a chapter
+    // title that changes every few pages next to the page number
+    for (edge, candidate) in edge_lines {
+        let on_row = baselines.iter().any(|(page, baseline_edge, center_y)| {
+            *page == candidate.page && *baseline_edge == edge && (candidate.center_y - center_y).abs() <= POSITION_TOLERANCE
+        });
+        if on_row {
+            repeated.entry(candidate.page).or_default().push((edge, candidate.block, candidate.line));
+        }
+    }
+
+    for (page_index, lines) in repeated {
+        move_lines(&mut pages[page_index], &lines);
+    }
+}
+
+fn move_lines(page: &mut PageExtractInfo, lines: &[(Edge, usize, usize)]) {
+    let Some(page_layout) = page.layout.as_mut() else {
+        return;
+    };
+
+    let mut header = Vec::new();
+    let mut footer = Vec::new();
+    let mut edge_blocks = Vec::new();
+    for (block_index, block) in page_layout.blocks.iter_mut().enumerate() {
+        let mut line_index = 0;
+        let mut moved = Vec::new();
+        block.lines.retain(|line| {
+            let edge = lines.iter()
+                .find(|(_, block, line)| (*block, *line) == (block_index, line_index))
+                .map(|(edge, _, _)| *edge);
+            line_index += 1;
+            match edge {
+                Some(Edge::Top) => header.push((line.bbox[3], line.text())),
+                Some(Edge::Bottom) => footer.push((line.bbox[3], line.text())),
+                None => return true,
+            }
+            moved.push(line.clone());
+            false
+        });
+        block.bbox = layout::union(block.lines.iter().map(|line| line.bbox));
+        if !moved.is_empty() {
+            edge_blocks.push(Block { bbox: layout::union(moved.iter().map(|line| line.bbox)), source: block.source, lines: moved });
+        }
+    }
+    page_layout.blocks.retain(|block| !block.lines.is_empty());
+    page.edge_layout = Some(PageLayout { blocks: edge_blocks }).filter(|edge_layout| !edge_layout.blocks.is_empty());
+
+    // headers come out of the text from the start and footers from the end, in case the same
+    // words also appear in the body
+    header.sort_by(|a, b| b.0.total_cmp(&a.0));
+    footer.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (_, line) in &header {
+        page.text = remove_line(&page.text, line, false);
+        page.full_text = remove_line(&page.full_text, line, false);
+    }
+    for (_, line) in footer.iter().rev() {
+        page.text = remove_line(&page.text, line, true);
+        page.full_text = remove_line(&page.full_text, line, true);
+    }
+
+    let joined = |lines: Vec<(f32, String)>| Some(lines.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join("\n"))
+        .filter(|text| !text.is_empty());
+    page.header = joined(header);
+    page.footer = joined(footer);
+}
+
+fn remove_line(text: &str, line: &str, from_end: bool) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    let matches = |candidate: &&str| candidate.trim() == line.trim();
+    let position = if from_end { lines.iter().rposition(matches) } else { lines.iter().position(matches) };
+    let Some(position) = position else {
+        return text.to_string();
+    };
+    lines.remove(position);
+
+    // a removed line should not leave a gap of several blank lines behind
+    let mut cleaned: Vec<&str> = Vec::with_capacity(lines.len());
+    for line in lines {
+        let blank = line.trim().is_empty();
+        if blank && cleaned.last().map_or(true, |previous| previous.trim().is_empty()) {
+            continue;
+        }
+        cleaned.push(line);
+    }
+    while cleaned.last().is_some_and(|line| line.trim().is_empty()) {
+        cleaned.pop();
+    }
+    cleaned.join("\n")
+}
+
+fn normalize(text: &str) -> String {
+    let mut normalized = String::new();
+    let mut previous = ' ';
+    for c in text.to_lowercase().chars() {
+        let c = if c.is_ascii_digit() { '#' } else if c.is_whitespace() { ' ' } else { c };
+        if (c == '#' || c == ' ') && c == previous {
+            continue;
+        }
+        normalized.push(c);
+        previous = c;
+    }
+    normalized.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::layout::{LayoutSource, Line, Word};
+
+    // a page of 10pt lines, each a block of its own, given as text and the top of the line
+    fn page(page_num: u32, lines: &[(&str, f32)]) -> PageExtractInfo {
+        let blocks = lines.iter().map(|(text, top)| {
+            let bbox = [72.0, top - 10.0, 300.0, *top];
+            let word = Word { text: text.to_string(), bbox, font_name: None, font_size: Some(10.0), confidence: None };
+            Block { bbox, source: LayoutSource::TextLayer, lines: vec![Line { bbox, words: vec![word] }] }
+        }).collect();
+        let text = lines.iter().map(|(text, _)| *text).collect::<Vec<_>>().join("\n");
+        PageExtractInfo { page_num, full_text: text.clone(), text, layout: Some(PageLayout { blocks }), ..Default::default() }
+    }
+
+    fn headers(pages: &[PageExtractInfo]) -> Vec<Option<&str>> {
+        pages.iter().map(|page| page.header.as_deref()).collect()
+    }
+
+    #[test]
+    fn ignores_digits_when_matching_lines() {
+        let mut pages = vec![
+            page(1, &[("Quarterly report", 780.0), ("alpha", 600.0), ("beta", 500.0), ("Page 1 of 12", 40.0)]),
+            page(2, &[("Quarterly report", 780.0), ("gamma", 600.0), ("delta", 500.0), ("Page 2 of 12", 40.0)]),
+            page(3, &[("Quarterly report", 780.0), ("epsilon", 600.0), ("zeta", 500.0), ("Page 10 of 12", 40.0)]),
+        ];
+        move_headers_and_footers(&mut pages);
+        assert_eq!(headers(&pages), vec![Some("Quarterly report"); 3]);
+        assert_eq!(pages[2].footer.as_deref(), Some("Page 10 of 12"));
+        assert_eq!(pages[0].text, "alpha\nbeta");
+        assert_eq!(pages[0].full_text, "alpha\nbeta");
+        assert_eq!(pages[0].layout.as_ref().unwrap().text(), "alpha\n\nbeta");
+        assert_eq!(pages[0].edge_layout.as_ref().unwrap().text(), "Quarterly report\n\nPage 1 of 12");
+        assert_eq!(normalize("  Page 12  of\t40 "), "page # of #");
+    }
+
+    #[test]
+    fn allows_repeated_lines_to_move_a_little() {
+        let mut pages = vec![
+            page(1, &[("Annual review", 780.0), ("alpha", 600.0), ("beta", 500.0)]),
+            page(2, &[("Annual review", 784.0), ("gamma", 600.0), ("delta", 500.0)]),
+            page(3, &[("Annual review", 789.0), ("epsilon", 600.0), ("zeta", 500.0)]),
+            // 14pt below where the others sit, so no longer the same header
+            page(4, &[("Annual review", 770.0), ("eta", 600.0), ("theta", 500.0)]),
+        ];
+        move_headers_and_footers(&mut pages);
+        assert_eq!(headers(&pages), vec![Some("Annual review"), Some("Annual review"), Some("Annual review"), None]);
+        assert!(pages[3].text.starts_with("Annual review"));
+    }
+
+    #[test]
+    fn leaves_documents_of_two_pages_alone() {
+        let mut pages = vec![
+            page(1, &[("Introduction", 780.0), ("alpha", 600.0), ("beta", 500.0)]),
+            page(2, &[("Introduction", 780.0), ("gamma", 600.0), ("delta", 500.0)]),
+        ];
+        move_headers_and_footers(&mut pages);
+        assert_eq!(headers(&pages), vec![None, None]);
+        assert!(pages.iter().all(|page| page.text.starts_with("Introduction") && page.edge_layout.is_none()));
+    }
+}
@@ -0,0 +1,334 @@
+use std::{collections::HashMap, path::Path};
+
+use lopdf::{dictionary, Document, Object, ObjectId, Stream, StringFormat};
+
+use crate::{engine::{layout::LayoutSource, loader::{self, LoadedDocument}, metadata}, types::engine_handler::PageExtractInfo};
+
+// A copy of the input with the OCR words written over the images they were read from in
+// invisible text (render mode 3), so the scan stays as it is but can be searched and copied.
+//
+// The text uses a font without visible glyphs in which every character is half an em wide. Each
+// word is stretched over its box, so selections line up with the scan whatever font the page
+// shows. Character codes are handed out in order of appearance and mapped back to Unicode
+// through the font's ToUnicode CMap.
+
+const FONT_NAME: &str = "PdfzOcrText";
+const GLYPH_WIDTH: f32 = 0.5;
+// entries per bfchar block, the CMap format allows no more
+const MAX_BFCHAR_ENTRIES: usize = 100;
+
+pub fn write_searchable_pdf(source: &Path, password: Option<&str>, allow_decrypted: bool, pages: &[PageExtractInfo], output: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (doc, _) = loader::load_document(source, password)?;
+    let LoadedDocument::Lopdf { doc, encryption } = doc else {
+        return Err("The searchable PDF needs a file lopdf can open".into());
+    };
+    // lopdf cannot encrypt, so the copy of an encrypted input could be read without its password
+    if encryption.is_some() && !allow_decrypted {
+        return Err("The input is encrypted and the searchable PDF would be written decrypted, set decrypt_searchable_pdf to allow it".into());
+    }
+    let mut doc = *doc;
+    let page_ids = doc.get_pages();
+    let font_id = doc.new_object_id();
+    let mut codes: HashMap<char, u16> = HashMap::new();
+
+    for page in pages {
+        let Some(page_id) = page_ids.get(&page.page_num).copied() else {
+            continue;
+        };
+        let rotation = metadata::inherited(&doc, page_id, b"Rotate")
+            .and_then(|rotation| rotation.as_i64().ok())
+            .unwrap_or(0)
+            .rem_euclid(360);
+        let Some(content) = page_text(page, rotation, &mut codes) else {
+            continue;
+        };
+        add_font_resource(&mut doc, page_id, font_id)?;
+        add_content(&mut doc, page_id, content)?;
+    }
+
+    add_font(&mut doc, font_id, &codes);
+    // an encrypted input was decrypted while loading, strings inside dictionaries included, and when
+    // allowed is written without its encryption
+    doc.trailer.remove(b"Encrypt");
+    doc.save(output)?;
+    Ok(())
+}
+
+// the content stream drawing the OCR words of a page, None when none of its words are placed
+fn page_text(page: &PageExtractInfo, rotation: i64, codes: &mut HashMap<char, u16>) -> Option<Vec<u8>> {
+    // running heads and page numbers were moved out of the layout, but belong on the page all the same
+    let blocks = page.layout.iter().chain(&page.edge_layout).flat_map(|layout| layout.blocks.iter());
+    let mut content = String::new();
+    for line in blocks.filter(|block| block.source == LayoutSource::Ocr).flat_map(|block| block.lines.iter()) {
+        // text runs along the page as it is shown, which is rotated against user space
+        let shown: Vec<[f32; 4]> = line.words.iter().map(|word| to_shown(word.bbox, rotation)).collect();
+        for (index, word) in line.words.iter().enumerate() {
+            let [x0, y0, x1, y1] = shown[index];
+            // a space after every word but the last, spanning the gap to the next one
+            let next = shown.get(index + 1).map(|next| next[0]);
+            let mut text = word.text.clone();
+            if next.is_some() {
+                text.push(' ');
+            }
+            let count = text.chars().count();
+            let (length, height) = (next.unwrap_or(x1).max(x1) - x0, y1 - y0);
+            if count == 0 || length <= 0.0 || height <= 0.0 {
+                continue;
+            }
+
+            let scale = length / (count as f32 * GLYPH_WIDTH);
+            let (direction, up) = (from_shown(1.0, 0.0, rotation), from_shown(0.0, 1.0, rotation));
+            let origin = from_shown(x0, y0, rotation);
+            let mut hex = String::with_capacity(count * 4);
+            for c in text.chars() {
+                let code = match codes.get(&c) {
+                    Some(code) => *code,
+                    // codes are two bytes, characters beyond the last free one are left out
+                    None => match u16::try_from(codes.len() + 1) {
+                        Ok(code) => *codes.entry(c).or_insert(code),
+                        Err(_) => continue,
+                    },
+                };
+                hex.push_str(&format!("{:04X}", code));
+            }
+            content.push_str(&format!(
+                "{} {} {} {} {} {} Tm <{}> Tj\n",
+                number(direction.0 * scale), number(direction.1 * scale), number(up.0 * height), number(up.1 * height), number(origin.0), number(origin.1), hex
+            ));
+        }
+    }
+    if content.is_empty() {
+        return None;
+    }
+    Some(format!("Q\nq\nBT\n3 Tr\n/{} 1 Tf\n{}ET\nQ\n", FONT_NAME, content).into_bytes())
+}
+
+// user space to the page as shown, which /Rotate turns clockwise
+fn to_shown([x0, y0, x1, y1]: [f32; 4], rotation: i64) -> [f32; 4] {
+    let corners = [(x0, y0), (x1, y1)].map(|(x, y)| match rotation {
+        90 => (y, -x),
+        180 => (-x, -y),
+        270 => (-y, x),
+        _ => (x, y),
+    });
+    [corners[0].0.min(corners[1].0), corners[0].1.min(corners[1].1), corners[0].0.max(corners[1].0), corners[0].1.max(corners[1].1)]
+}
+
+fn from_shown(x: f32, y: f32, rotation: i64) -> (f32, f32) {
+    match rotation {
+        90 => (-y, x),
+        180 => (-x, -y),
+        270 => (y, -x),
+        _ => (x, y),
+    }
+}
+
+fn number(value: f32) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    if rounded == 0.0 { "0".to_string() } else { rounded.to_string() }
+}
+
+// the existing content is wrapped in q/Q so a graphics state it leaves behind cannot move the text
+fn add_content(doc: &mut Document, page_id: ObjectId, content: Vec<u8>) -> lopdf::Result<()> {
+    let mut existing = match doc.get_dictionary(page_id)?.get(b"Contents") {
+        Ok(Object::Array(contents)) => contents.clone(),
+        Ok(contents @ Object::Reference(_)) => vec![contents.clone()],
+        _ => vec![],
+    };
+    let mut text = Stream::new(dictionary! {}, content);
+    let _ = text.compress();
+    let open_id = doc.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+    let text_id = doc.add_object(text);
+
+    existing.insert(0, Object::Reference(open_id));
+    existing.push(Object::Reference(text_id));
+    doc.get_dictionary_mut(page_id)?.set("Contents", Object::Array(existing));
+    Ok(())
+}
+
+fn add_font_resource(doc: &mut Document, page_id: ObjectId, font_id: ObjectId) -> lopdf::Result<()> {
+    // resources inherited from the page tree are copied onto the page before adding to them
+    if !doc.get_dictionary(page_id)?.has(b"Resources") {
+        let inherited = metadata::inherited(doc, page_id, b"Resources").cloned().unwrap_or_else(|| Object::Dictionary(dictionary! {}));
+        doc.get_dictionary_mut(page_id)?.set("Resources", inherited);
+    }
+    let resources_id = doc.get_dictionary(page_id)?.get(b"Resources")?.as_reference().ok();
+    let resources = match resources_id {
+        Some(id) => doc.get_dictionary(id)?,
+        None => doc.get_dictionary(page_id)?.get(b"Resources")?.as_dict()?,
+    };
+    let fonts_id = resources.get(b"Font").and_then(Object::as_reference).ok();
+
+    if let Some(fonts_id) = fonts_id {
+        doc.get_dictionary_mut(fonts_id)?.set(FONT_NAME, font_id);
+        return Ok(());
+    }
+    let resources = match resources_id {
+        Some(id) => doc.get_dictionary_mut(id)?,
+        None => doc.get_dictionary_mut(page_id)?.get_mut(b"Resources")?.as_dict_mut()?,
+    };
+    if !resources.has(b"Font") {
+        resources.set("Font", dictionary! {});
+    }
+    resources.get_mut(b"Font")?.as_dict_mut()?.set(FONT_NAME, font_id);
+    Ok(())
+}
+
+fn add_font(doc: &mut Document, font_id: ObjectId, codes: &HashMap<char, u16>) {
+    let mut font_file = Stream::new(dictionary! { "Length1" => glyphless_font().len() as i64 }, glyphless_font());
+    let _ = font_file.compress();
+    let font_file_id = doc.add_object(font_file);
+    let descriptor_id = doc.add_object(dictionary! {
+        "Type" => "FontDescriptor",
+        "FontName" => "GlyphLessFont",
+        "Flags" => 5,
+        "FontBBox" => vec![0.into(), 0.into(), 500.into(), 1000.into()],
+        "ItalicAngle" => 0,
+        "Ascent" => 1000,
+        "Descent" => 0,
+        "CapHeight" => 1000,
+        "StemV" => 80,
+        "FontFile2" => font_file_id,
+    });
+    // every code is drawn with the second glyph, the first is the .notdef glyph
+    let mut gid_map = Stream::new(dictionary! {}, [0u8, 1].repeat(codes.len() + 1));
+    let _ = gid_map.compress();
+    let gid_map_id = doc.add_object(gid_map);
+    let cid_font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "CIDFontType2",
+        "BaseFont" => "GlyphLessFont",
+        "CIDSystemInfo" => dictionary! {
+            "Registry" => Object::String(b"Adobe".to_vec(), StringFormat::Literal),
+            "Ordering" => Object::String(b"Identity".to_vec(), StringFormat::Literal),
+            "Supplement" => 0,
+        },
+        "FontDescriptor" => descriptor_id,
+        "DW" => (GLYPH_WIDTH * 1000.0) as i64,
+        "CIDToGIDMap" => gid_map_id,
+    });
+    let mut to_unicode = Stream::new(dictionary! {}, to_unicode_cmap(codes).into_bytes());
+    let _ = to_unicode.compress();
+    let to_unicode_id = doc.add_object(to_unicode);
+
+    doc.objects.insert(font_id, Object::Dictionary(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type0",
+        "BaseFont" => "GlyphLessFont",
+        "Encoding" => "Identity-H",
+        "DescendantFonts" => vec![cid_font_id.into()],
+        "ToUnicode" => to_unicode_id,
+    }));
+}
+
+fn to_unicode_cmap(codes: &HashMap<char, u16>) -> String {
+    let mut entries: Vec<(u16, char)> = codes.iter().map(|(c, code)| (*code, *c)).collect();
+    entries.sort();
+
+    let mut cmap = String::from("/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n");
+    cmap.push_str("/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n");
+    cmap.push_str("/CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n");
+    cmap.push_str("1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n");
+    for chunk in entries.chunks(MAX_BFCHAR_ENTRIES) {
+        cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+        for (code, c) in chunk {
+            let utf16: String = c.encode_utf16(&mut [0; 2]).iter().map(|unit| format!("{:04X}", unit)).collect();
+            cmap.push_str(&format!("<{:04X}> <{}>\n", code, utf16));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    cmap
+}
+
+// A TrueType font with two empty glyphs half an em wide, only there so viewers have no font to
+// substitute for the text
+fn glyphless_font() -> Vec<u8> {
+    let be16 = |values: &[i32]| values.iter().flat_map(|value| (*value as u16).to_be_bytes()).collect::<Vec<u8>>();
+    let width = (GLYPH_WIDTH * 1000.0) as i32;
+
+    let mut head = be16(&[1, 0, 1, 0, 0, 0, 0x5F0F, 0x3CF5, 0x000B, 1000]);
+    head.extend([0u8; 16]);
+    head.extend(be16(&[0, 0, width, 1000, 0, 8, 2, 0, 0]));
+    let mut hhea = be16(&[1, 0, 1000, 0, 0, width, 0, 0, width, 1, 0, 0, 0, 0, 0, 0, 0]);
+    hhea.extend(be16(&[2]));
+    let maxp = be16(&[1, 0, 2, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let hmtx = be16(&[width, 0, width, 0]);
+    let loca = be16(&[0, 0, 0]);
+    let glyf = vec![0u8; 4];
+    let mut post = be16(&[3, 0, 0, 0, -100, 50, 0, 1]);
+    post.extend([0u8; 16]);
+
+    let mut tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"glyf", glyf),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"loca", loca),
+        (b"maxp", maxp),
+        (b"post", post),
+    ];
+    let count = tables.len() as i32;
+    let entry_selector = (count as f32).log2().floor() as i32;
+    let search_range = (1 << entry_selector) * 16;
+    let mut font = be16(&[1, 0, count, search_range, entry_selector, count * 16 - search_range]);
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut data = Vec::new();
+    for (tag, table) in tables.iter_mut() {
+        let length = table.len();
+        table.resize(length.div_ceil(4) * 4, 0);
+        font.extend_from_slice(*tag);
+        font.extend(checksum(table).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((length as u32).to_be_bytes());
+        offset += table.len();
+        data.extend_from_slice(table);
+    }
+    font.extend(data);
+
+    // checkSumAdjustment in head makes the whole font sum up to a fixed value
+    let head_offset = u32::from_be_bytes(font[12 + 16 + 8..12 + 16 + 12].try_into().unwrap_or_default()) as usize;
+    let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&font));
+    font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    font
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0u32, u32::wrapping_add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::loader::tests::encrypted_pdf, helper::file_helper};
+
+    #[test]
+    fn writes_encrypted_inputs_decrypted_only_when_allowed() {
+        let source = file_helper::get_temp_path("searchable_test").with_extension("pdf");
+        let output = file_helper::get_temp_path("searchable_test").with_extension("pdf");
+        std::fs::write(&source, encrypted_pdf()).unwrap();
+
+        let refused = write_searchable_pdf(&source, None, false, &[], &output);
+        let written = write_searchable_pdf(&source, None, true, &[], &output);
+        let doc = Document::load(&output);
+        for path in [&source, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+        assert!(refused.is_err());
+        written.unwrap();
+
+        let doc = doc.unwrap();
+        assert!(!doc.is_encrypted());
+        let info = doc.trailer.get(b"Info").and_then(Object::as_reference).unwrap();
+        assert_eq!(doc.get_dictionary(info).unwrap().get(b"Title").unwrap().as_str().unwrap(), b"Annual report");
+        let annots = doc.get_dictionary((3, 0)).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annots[0].as_dict().unwrap().get(b"Contents").unwrap().as_str().unwrap(), b"A note");
+    }
+}
@@ -1,8 +1,15 @@
 use image::ImageReader;
 use rusty_tesseract::{Image, Args, TessResult};
-use std::{error::Error, future::Future, pin::Pin};
+use std::{error::Error, future::Future, pin::Pin, sync::OnceLock};
 
-use crate::types::engine_handler::EngineHandler;
+use crate::{engine::language, types::engine_handler::{EngineHandler, OcrFuture, OcrOutput, OcrWord}};
+
+// rows of the TSV output at word level, the others describe pages, blocks, paragraphs and lines
+const TSV_WORD_LEVEL: i32 = 5;
+const DEFAULT_LANGUAGE: &str = "eng";
+
+// the traineddata installed next to tesseract, read once per worker
+static INSTALLED_LANGUAGES: OnceLock<Option<Vec<String>>> = OnceLock::new();
 
 #[derive(Debug, Clone)]
 pub struct TesseractEngine;
@@ -12,21 +19,17 @@ impl EngineHandler for TesseractEngine {
         TesseractEngine
     }
 
+    fn name(&self) -> String {
+        "tesseract".to_string()
+    }
+
     fn extract_text_from_image(&self, image_path: String) 
         -> Pin<Box<dyn Future<Output = Result<String, Box<dyn Error + Send>>> + Send>> 
     {
         let image_path = image_path.to_owned();
         Box::pin(async move {
             tokio::task::spawn_blocking(move || -> Result<String, Box<dyn Error + Send>> {
-                let img = ImageReader::open(&image_path)
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?  // Convert image error
-                    .decode()
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?  // Convert decode error
-                    .grayscale();
-                
-                let tesseract_img = Image::from_dynamic_image(&img)
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;  // Convert Tesseract image error
-                
+                let tesseract_img = load_image(&image_path)?;
                 let args = Args::default();
                 rusty_tesseract::image_to_string(&tesseract_img, &args)
                     .map_err(|e| Box::new(e) as Box<dyn Error + Send>)  // Convert Tesseract OCR error
@@ -35,4 +38,88 @@ impl EngineHandler for TesseractEngine {
             .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?  // Convert JoinError
         })
     }
-}
\ No newline at end of file
+
+    // one TSV run gives both the words and, rebuilt from them, the text
+    fn extract_from_image(&self, image_path: String, languages: Option<String>) -> OcrFuture {
+        let lang = self.ocr_languages(languages.as_deref()).unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || -> Result<OcrOutput, Box<dyn Error + Send>> {
+                let tesseract_img = load_image(&image_path)?;
+                let args = Args { lang, ..Args::default() };
+                let data = rusty_tesseract::image_to_data(&tesseract_img, &args)
+                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+
+                let words: Vec<OcrWord> = data.data.into_iter()
+                    .filter(|row| row.level == TSV_WORD_LEVEL && !row.text.trim().is_empty())
+                    .map(|row| OcrWord {
+                        text: row.text,
+                        block: row.block_num.max(0) as u32,
+                        paragraph: row.par_num.max(0) as u32,
+                        line: row.line_num.max(0) as u32,
+                        left: row.left.max(0) as u32,
+                        top: row.top.max(0) as u32,
+                        width: row.width.max(0) as u32,
+                        height: row.height.max(0) as u32,
+                        confidence: row.conf,
+                    })
+                    .collect();
+                Ok(OcrOutput { text: words_to_text(&words), words: Some(words) })
+            })
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?
+        })
+    }
+
+    // requested languages without traineddata are dropped, English is used when none is left
+    fn ocr_languages(&self, requested: Option<&str>) -> Option<String> {
+        let installed = INSTALLED_LANGUAGES.get_or_init(|| match rusty_tesseract::get_tesseract_langs() {
+            Ok(languages) => Some(languages),
+            Err(e) => {
+                println!("Failed to list tesseract languages: {}", e);
+                None
+            }
+        });
+        let mut languages: Vec<&str> = Vec::new();
+        for code in requested.unwrap_or(DEFAULT_LANGUAGE).split('+').map(str::trim).filter(|code| !code.is_empty()) {
+            let code = language::tesseract_code(code);
+            let available = installed.as_ref().map_or(true, |installed| installed.iter().any(|name| name == code));
+            if available && !languages.contains(&code) {
+                languages.push(code);
+            }
+        }
+        if languages.is_empty() {
+            languages.push(DEFAULT_LANGUAGE);
+        }
+        Some(languages.join("+"))
+    }
+}
+
+fn load_image(image_path: &str) -> Result<Image, Box<dyn Error + Send>> {
+    let img = ImageReader::open(image_path)
+        .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?  // Convert image error
+        .decode()
+        .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?  // Convert decode error
+        .grayscale();
+
+    Image::from_dynamic_image(&img)
+        .map_err(|e| Box::new(e) as Box<dyn Error + Send>)  // Convert Tesseract image error
+}
+
+// the same shape image_to_string produces: words on a line, lines on their own row and a blank
+// row between paragraphs
+fn words_to_text(words: &[OcrWord]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<(u32, u32, u32)> = None;
+    for word in words {
+        let position = (word.block, word.paragraph, word.line);
+        match previous {
+            Some((block, paragraph, _)) if (block, paragraph) != (position.0, position.1) => text.push_str("\n\n"),
+            Some(line) if line != position => text.push('\n'),
+            Some(_) => text.push(' '),
+            None => {}
+        }
+        text.push_str(&word.text);
+        previous = Some(position);
+    }
+    text
+}
@@ -0,0 +1,568 @@
+use std::{collections::HashMap, rc::Rc};
+
+use lopdf::{content::Content, Dictionary, Document, Encoding, Object, ObjectId};
+
+// Walks the content stream of a page the way a renderer would, tracking the graphics and text
+// state, so every shown glyph gets a position in PDF user space. lopdf's `extract_text` only
+// concatenates the strings, which loses the coordinates the layout model needs.
+
+pub type Matrix = [f32; 6];
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+// form XObjects can nest, and broken files make them include themselves
+const MAX_FORM_DEPTH: usize = 8;
+// fallbacks for fonts that do not describe themselves, in thousandths of the font size
+const DEFAULT_GLYPH_WIDTH: f32 = 500.0;
+const DEFAULT_ASCENT: f32 = 800.0;
+const DEFAULT_DESCENT: f32 = -200.0;
+
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub text: String,
+    // where the glyph starts and where the next one would start, on the baseline
+    pub origin: (f32, f32),
+    pub end: (f32, f32),
+    // [x0, y0, x1, y1] of the glyph cell
+    pub bbox: [f32; 4],
+    pub font_name: Option<String>,
+    // effective size on the page, after the text and transformation matrices
+    pub font_size: f32,
+}
+
+impl Glyph {
+    // unit vector along the baseline
+    pub fn direction(&self) -> (f32, f32) {
+        let (dx, dy) = (self.end.0 - self.origin.0, self.end.1 - self.origin.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < f32::EPSILON {
+            (1.0, 0.0)
+        } else {
+            (dx / length, dy / length)
+        }
+    }
+}
+
+// where an image XObject was drawn, mapping its unit square onto the page
+#[derive(Debug, Clone)]
+pub struct ImagePlacement {
+    pub image_id: ObjectId,
+    pub matrix: Matrix,
+}
+
+#[derive(Debug, Default)]
+pub struct WalkedPage {
+    pub glyphs: Vec<Glyph>,
+    pub images: Vec<ImagePlacement>,
+}
+
+enum Widths {
+    Simple { first_char: u32, widths: Vec<f32>, missing: f32 },
+    Cid { widths: HashMap<u32, f32>, default: f32 },
+}
+
+struct FontInfo<'a> {
+    name: Option<String>,
+    encoding: Option<Encoding<'a>>,
+    // composite (Type0) fonts use two byte codes, everything else one
+    two_byte: bool,
+    widths: Widths,
+    // glyph space to text space, 1/1000 except for Type3 fonts
+    scale: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+impl FontInfo<'_> {
+    fn width(&self, code: u32) -> f32 {
+        match &self.widths {
+            Widths::Simple { first_char, widths, missing } => code.checked_sub(*first_char)
+                .and_then(|index| widths.get(index as usize).copied())
+                .unwrap_or(*missing),
+            Widths::Cid { widths, default } => widths.get(&code).copied().unwrap_or(*default),
+        }
+    }
+
+    fn decode(&self, code: u32) -> String {
+        match &self.encoding {
+            Some(Encoding::UnicodeMapEncoding(cmap)) => match cmap.get(code as u16) {
+                Some(units) => String::from_utf16_lossy(&units),
+                None if !self.two_byte => latin1(code),
+                None => '\u{FFFD}'.to_string(),
+            },
+            Some(encoding) => {
+                let bytes = if self.two_byte { vec![(code >> 8) as u8, code as u8] } else { vec![code as u8] };
+                encoding.bytes_to_string(&bytes).unwrap_or_else(|_| self.fallback(code))
+            }
+            None => self.fallback(code),
+        }
+    }
+
+    fn fallback(&self, code: u32) -> String {
+        if self.two_byte { '\u{FFFD}'.to_string() } else { latin1(code) }
+    }
+}
+
+fn latin1(code: u32) -> String {
+    char::from_u32(code).filter(|c| !c.is_control()).unwrap_or('\u{FFFD}').to_string()
+}
+
+#[derive(Clone)]
+struct GraphicsState<'a> {
+    ctm: Matrix,
+    font: Option<Rc<FontInfo<'a>>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl GraphicsState<'_> {
+    fn new(ctm: Matrix) -> Self {
+        Self {
+            ctm,
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+struct Walker<'a> {
+    doc: &'a Document,
+    fonts: HashMap<ObjectId, Rc<FontInfo<'a>>>,
+    page: WalkedPage,
+}
+
+pub fn walk_page(doc: &Document, page_id: ObjectId) -> lopdf::Result<WalkedPage> {
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+    let resources = page_resources(doc, page_id);
+
+    let mut walker = Walker { doc, fonts: HashMap::new(), page: WalkedPage::default() };
+    walker.run(&content, &resources, IDENTITY, 0);
+    Ok(walker.page)
+}
+
+fn page_resources(doc: &Document, page_id: ObjectId) -> Vec<&Dictionary> {
+    let Ok((direct, inherited)) = doc.get_page_resources(page_id) else {
+        return vec![];
+    };
+    direct.into_iter()
+        .chain(inherited.into_iter().filter_map(|id| doc.get_dictionary(id).ok()))
+        .collect()
+}
+
+impl<'a> Walker<'a> {
+    fn run(&mut self, content: &Content, resources: &[&'a Dictionary], ctm: Matrix, depth: usize) {
+        let mut state = GraphicsState::new(ctm);
+        let mut stack: Vec<GraphicsState<'a>> = Vec::new();
+        let mut text_matrix = IDENTITY;
+        let mut line_matrix = IDENTITY;
+
+        for operation in &content.operations {
+            let operands = &operation.operands;
+            let number = |index: usize| operands.get(index).and_then(|n| n.as_float().ok()).unwrap_or(0.0);
+
+            match operation.operator.as_str() {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(previous) = stack.pop() {
+                        state = previous;
+                    }
+                }
+                "cm" => state.ctm = multiply(&matrix_operands(operands), &state.ctm),
+                "BT" => {
+                    text_matrix = IDENTITY;
+                    line_matrix = IDENTITY;
+                }
+                "Tf" => {
+                    state.font = operands.first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| self.font(resources, name));
+                    state.font_size = number(1);
+                }
+                "Tc" => state.char_spacing = number(0),
+                "Tw" => state.word_spacing = number(0),
+                "Tz" => state.horizontal_scale = number(0) / 100.0,
+                "TL" => state.leading = number(0),
+                "Ts" => state.rise = number(0),
+                "Td" => {
+                    line_matrix = multiply(&translation(number(0), number(1)), &line_matrix);
+                    text_matrix = line_matrix;
+                }
+                "TD" => {
+                    state.leading = -number(1);
+                    line_matrix = multiply(&translation(number(0), number(1)), &line_matrix);
+                    text_matrix = line_matrix;
+                }
+                "Tm" => {
+                    line_matrix = matrix_operands(operands);
+                    text_matrix = line_matrix;
+                }
+                "T*" => {
+                    line_matrix = multiply(&translation(0.0, -state.leading), &line_matrix);
+                    text_matrix = line_matrix;
+                }
+                "Tj" => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show(bytes, &state, &mut text_matrix);
+                    }
+                }
+                "'" | "\"" => {
+                    if operation.operator == "\"" {
+                        state.word_spacing = number(0);
+                        state.char_spacing = number(1);
+                    }
+                    line_matrix = multiply(&translation(0.0, -state.leading), &line_matrix);
+                    text_matrix = line_matrix;
+                    if let Some(Object::String(bytes, _)) = operands.last() {
+                        self.show(bytes, &state, &mut text_matrix);
+                    }
+                }
+                "TJ" => {
+                    for item in operands.first().and_then(|array| array.as_array().ok()).into_iter().flatten() {
+                        match item {
+                            Object::String(bytes, _) => self.show(bytes, &state, &mut text_matrix),
+                            // kerning, in thousandths of the font size, moving against the text
+                            adjustment => {
+                                let amount = adjustment.as_float().unwrap_or(0.0);
+                                let tx = -amount / 1000.0 * state.font_size * state.horizontal_scale;
+                                text_matrix = multiply(&translation(tx, 0.0), &text_matrix);
+                            }
+                        }
+                    }
+                }
+                "Do" => {
+                    if let Some(name) = operands.first().and_then(|name| name.as_name().ok()) {
+                        self.draw_xobject(resources, name, &state, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn show(&mut self, bytes: &[u8], state: &GraphicsState<'a>, text_matrix: &mut Matrix) {
+        let Some(font) = state.font.clone() else {
+            return;
+        };
+        let codes: Vec<u32> = if font.two_byte {
+            bytes.chunks(2).map(|pair| pair.iter().fold(0, |code, byte| (code << 8) | *byte as u32)).collect()
+        } else {
+            bytes.iter().map(|byte| *byte as u32).collect()
+        };
+
+        for code in codes {
+            let width = font.width(code) * font.scale;
+            let parameters = [state.font_size * state.horizontal_scale, 0.0, 0.0, state.font_size, 0.0, state.rise];
+            let rendering = multiply(&multiply(&parameters, text_matrix), &state.ctm);
+
+            let origin = apply(&rendering, 0.0, 0.0);
+            let end = apply(&rendering, width, 0.0);
+            let corners = [
+                apply(&rendering, 0.0, font.descent),
+                apply(&rendering, width, font.descent),
+                apply(&rendering, 0.0, font.ascent),
+                apply(&rendering, width, font.ascent),
+            ];
+            let bbox = corners.iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |b, (x, y)| {
+                [b[0].min(*x), b[1].min(*y), b[2].max(*x), b[3].max(*y)]
+            });
+            // the vertical scale of the rendering matrix is the size the glyph appears at
+            let font_size = (rendering[2] * rendering[2] + rendering[3] * rendering[3]).sqrt();
+
+            self.page.glyphs.push(Glyph {
+                text: font.decode(code),
+                origin,
+                end,
+                bbox,
+                font_name: font.name.clone(),
+                font_size,
+            });
+
+            let word_spacing = if !font.two_byte && code == 32 { state.word_spacing } else { 0.0 };
+            let advance = (width * state.font_size + state.char_spacing + word_spacing) * state.horizontal_scale;
+            *text_matrix = multiply(&translation(advance, 0.0), text_matrix);
+        }
+    }
+
+    fn draw_xobject(&mut self, resources: &[&'a Dictionary], name: &[u8], state: &GraphicsState<'a>, depth: usize) {
+        let doc = self.doc;
+        let Some(reference) = resources.iter().find_map(|resources| {
+            resources.get(b"XObject").ok()
+                .and_then(|xobjects| doc.dereference(xobjects).ok())
+                .and_then(|(_, xobjects)| xobjects.as_dict().ok())
+                .and_then(|xobjects| xobjects.get(name).ok())
+        }) else {
+            return;
+        };
+        let Ok((Some(id), Object::Stream(stream))) = doc.dereference(reference) else {
+            return;
+        };
+
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Image") => self.page.images.push(ImagePlacement { image_id: id, matrix: state.ctm }),
+            Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                let form_matrix = stream.dict.get(b"Matrix").ok()
+                    .and_then(|matrix| matrix.as_array().ok())
+                    .map(|matrix| matrix_operands(matrix))
+                    .unwrap_or(IDENTITY);
+                let data = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                let Ok(content) = Content::decode(&data) else {
+                    return;
+                };
+                // forms without their own resources use the ones of the page drawing them
+                let form_resources = stream.dict.get(b"Resources").ok()
+                    .and_then(|form_resources| doc.dereference(form_resources).ok())
+                    .and_then(|(_, form_resources)| form_resources.as_dict().ok());
+                let resources: Vec<&'a Dictionary> = form_resources.into_iter().chain(resources.iter().copied()).collect();
+                self.run(&content, &resources, multiply(&form_matrix, &state.ctm), depth + 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn font(&mut self, resources: &[&'a Dictionary], name: &[u8]) -> Option<Rc<FontInfo<'a>>> {
+        let doc = self.doc;
+        let reference = resources.iter().find_map(|resources| {
+            resources.get(b"Font").ok()
+                .and_then(|fonts| doc.dereference(fonts).ok())
+                .and_then(|(_, fonts)| fonts.as_dict().ok())
+                .and_then(|fonts| fonts.get(name).ok())
+        })?;
+        let (id, font) = doc.dereference(reference).ok()?;
+        if let Some(cached) = id.and_then(|id| self.fonts.get(&id)) {
+            return Some(cached.clone());
+        }
+
+        let font = Rc::new(read_font(doc, font.as_dict().ok()?));
+        if let Some(id) = id {
+            self.fonts.insert(id, font.clone());
+        }
+        Some(font)
+    }
+}
+
+fn read_font<'a>(doc: &'a Document, font: &'a Dictionary) -> FontInfo<'a> {
+    let get = |dict: &'a Dictionary, key: &[u8]| dict.get(key).ok().and_then(|value| doc.dereference(value).ok()).map(|(_, value)| value);
+    let two_byte = font.get(b"Subtype").and_then(Object::as_name).is_ok_and(|subtype| subtype == b"Type0");
+    // subset fonts carry a six letter tag, as in ABCDEF+Helvetica
+    let name = font.get(b"BaseFont").and_then(Object::as_name_str).ok().map(|name| match name.split_once('+') {
+        Some((tag, rest)) if tag.len() == 6 => rest.to_string(),
+        _ => name.to_string(),
+    });
+
+    let descendant = two_byte.then(|| get(font, b"DescendantFonts")
+        .and_then(|fonts| fonts.as_array().ok())
+        .and_then(|fonts| fonts.first())
+        .and_then(|first| doc.dereference(first).ok())
+        .and_then(|(_, first)| first.as_dict().ok()))
+        .flatten();
+    let descriptor = get(descendant.unwrap_or(font), b"FontDescriptor").and_then(|descriptor| descriptor.as_dict().ok());
+    let descriptor_number = |key: &[u8]| descriptor.and_then(|descriptor| get(descriptor, key)).and_then(|n| n.as_float().ok());
+
+    let widths = match descendant {
+        Some(descendant) => Widths::Cid {
+            widths: cid_widths(get(descendant, b"W")),
+            default: get(descendant, b"DW").and_then(|n| n.as_float().ok()).unwrap_or(1000.0),
+        },
+        None => Widths::Simple {
+            first_char: get(font, b"FirstChar").and_then(|n| n.as_i64().ok()).unwrap_or(0).max(0) as u32,
+            widths: get(font, b"Widths")
+                .and_then(|widths| widths.as_array().ok())
+                .map(|widths| widths.iter().map(|w| doc.dereference(w).ok().and_then(|(_, w)| w.as_float().ok()).unwrap_or(0.0)).collect())
+                .unwrap_or_default(),
+            missing: descriptor_number(b"MissingWidth").filter(|width| *width > 0.0).unwrap_or(DEFAULT_GLYPH_WIDTH),
+        },
+    };
+    let scale = get(font, b"FontMatrix")
+        .and_then(|matrix| matrix.as_array().ok())
+        .and_then(|matrix| matrix.first())
+        .and_then(|a| a.as_float().ok())
+        .unwrap_or(0.001);
+
+    FontInfo {
+        name,
+        encoding: font.get_font_encoding(doc).ok(),
+        two_byte,
+        widths,
+        scale,
+        ascent: descriptor_number(b"Ascent").filter(|ascent| *ascent > 0.0).unwrap_or(DEFAULT_ASCENT) / 1000.0,
+        descent: descriptor_number(b"Descent").filter(|descent| *descent < 0.0).unwrap_or(DEFAULT_DESCENT) / 1000.0,
+    }
+}
+
+// /W lists either `first [w1 w2 ...]` or `first last w`
+fn cid_widths(w: Option<&Object>) -> HashMap<u32, f32> {
+    let mut widths = HashMap::new();
+    let Some(items) = w.and_then(|w| w.as_array().ok()) else {
+        return widths;
+    };
+
+    let mut index = 0;
+    while index + 1 < items.len() {
+        let Ok(first) = items[index].as_i64() else {
+            break;
+        };
+        let first = first.max(0) as u32;
+        match &items[index + 1] {
+            Object::Array(run) => {
+                for (offset, width) in run.iter().enumerate() {
+                    if let Ok(width) = width.as_float() {
+                        widths.insert(first + offset as u32, width);
+                    }
+                }
+                index += 2;
+            }
+            last => {
+                let (Ok(last), Some(Ok(width))) = (last.as_i64(), items.get(index + 2).map(Object::as_float)) else {
+                    break;
+                };
+                // a broken range should not allocate the whole code space
+                for code in first..=(last.max(0) as u32).min(first + 0xFFFF) {
+                    widths.insert(code, width);
+                }
+                index += 3;
+            }
+        }
+    }
+    widths
+}
+
+fn matrix_operands(operands: &[Object]) -> Matrix {
+    let mut matrix = IDENTITY;
+    if operands.len() >= 6 {
+        for (slot, operand) in matrix.iter_mut().zip(operands) {
+            *slot = operand.as_float().unwrap_or(*slot);
+        }
+    }
+    matrix
+}
+
+fn translation(tx: f32, ty: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+// `first` applied before `second`, as in the PDF specification's `first × second`
+pub fn multiply(first: &Matrix, second: &Matrix) -> Matrix {
+    [
+        first[0] * second[0] + first[1] * second[2],
+        first[0] * second[1] + first[1] * second[3],
+        first[2] * second[0] + first[3] * second[2],
+        first[2] * second[1] + first[3] * second[3],
+        first[4] * second[0] + first[5] * second[2] + second[4],
+        first[4] * second[1] + first[5] * second[3] + second[5],
+    ]
+}
+
+pub fn apply(matrix: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    // a page with a Helvetica subset without widths, so every glyph is half an em wide, an image
+    // and whatever form XObjects the test adds
+    fn page(content: &str, forms: Vec<(&str, Dictionary, &str)>) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.5");
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "ABCDEF+Helvetica", "Encoding" => "WinAnsiEncoding" });
+        let image_id = doc.add_object(Stream::new(dictionary! { "Subtype" => "Image", "Width" => 1, "Height" => 1 }, vec![0]));
+        let mut xobjects = dictionary! { "Im1" => image_id };
+        for (name, mut dict, form_content) in forms {
+            dict.set("Subtype", "Form");
+            let form_id = doc.add_object(Stream::new(dict, form_content.as_bytes().to_vec()));
+            xobjects.set(name, form_id);
+        }
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content.as_bytes().to_vec()));
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page", "Parent" => pages_id, "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id }, "XObject" => xobjects },
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, page_id)
+    }
+
+    fn walk(content: &str) -> WalkedPage {
+        let (doc, page_id) = page(content, vec![]);
+        walk_page(&doc, page_id).unwrap()
+    }
+
+    fn origins(page: &WalkedPage) -> Vec<(String, (f32, f32))> {
+        page.glyphs.iter().map(|glyph| (glyph.text.clone(), glyph.origin)).collect()
+    }
+
+    #[test]
+    fn positions_glyphs_through_tm_td_and_tj_kerning() {
+        let page = walk("BT /F1 10 Tf 1 0 0 1 100 700 Tm (AB) Tj 0 -20 Td (C) Tj [(D) -500 (E) 250 (F)] TJ ET");
+        assert_eq!(origins(&page), vec![
+            ("A".to_string(), (100.0, 700.0)),
+            ("B".to_string(), (105.0, 700.0)),
+            ("C".to_string(), (100.0, 680.0)),
+            ("D".to_string(), (105.0, 680.0)),
+            ("E".to_string(), (115.0, 680.0)),
+            ("F".to_string(), (117.5, 680.0)),
+        ]);
+        let a = &page.glyphs[0];
+        assert_eq!((a.end, a.bbox, a.font_size), ((105.0, 700.0), [100.0, 698.0, 105.0, 708.0], 10.0));
+        assert_eq!(a.font_name.as_deref(), Some("Helvetica"));
+    }
+
+    #[test]
+    fn applies_spacing_and_horizontal_scaling() {
+        // Tc after every glyph, Tw after spaces only, both scaled by Tz like the glyph widths
+        let page = walk("BT /F1 10 Tf 50 Tz 2 Tc 3 Tw (A B) Tj ET");
+        assert_eq!(origins(&page), vec![("A".to_string(), (0.0, 0.0)), (" ".to_string(), (3.5, 0.0)), ("B".to_string(), (8.5, 0.0))]);
+        assert_eq!(page.glyphs[0].end, (2.5, 0.0));
+
+        // T* and ' move down by the leading TD sets
+        let page = walk("BT /F1 10 Tf 0 -12 TD (A) Tj T* (B) Tj (C) ' ET");
+        assert_eq!(origins(&page).into_iter().map(|(_, origin)| origin).collect::<Vec<_>>(), vec![(0.0, -12.0), (0.0, -24.0), (0.0, -36.0)]);
+    }
+
+    #[test]
+    fn follows_the_ctm_and_the_graphics_state_stack() {
+        let page = walk("q 0 1 -1 0 300 0 cm BT /F1 10 Tf (A) Tj ET Q BT /F1 10 Tf (B) Tj ET");
+        let (a, b) = (&page.glyphs[0], &page.glyphs[1]);
+        assert_eq!((a.origin, a.end, a.font_size), ((300.0, 0.0), (300.0, 5.0), 10.0));
+        assert_eq!(a.direction(), (0.0, 1.0));
+        assert_eq!((b.origin, b.end), ((0.0, 0.0), (5.0, 0.0)));
+    }
+
+    #[test]
+    fn walks_into_form_xobjects() {
+        let form = dictionary! { "Matrix" => vec![1.into(), 0.into(), 0.into(), 1.into(), 10.into(), 20.into()] };
+        let (doc, page_id) = page("q 2 0 0 2 0 0 cm /Fm1 Do Q /Im1 Do", vec![("Fm1", form, "BT /F1 5 Tf (X) Tj ET /Im1 Do")]);
+        let page = walk_page(&doc, page_id).unwrap();
+
+        // the form matrix comes before the CTM it was drawn with, and the form uses the page's resources
+        assert_eq!(origins(&page), vec![("X".to_string(), (20.0, 40.0))]);
+        assert_eq!(page.glyphs[0].font_size, 10.0);
+        let matrices: Vec<Matrix> = page.images.iter().map(|image| image.matrix).collect();
+        assert_eq!(matrices, vec![[2.0, 0.0, 0.0, 2.0, 20.0, 40.0], IDENTITY]);
+    }
+
+    #[test]
+    fn stops_forms_that_draw_themselves() {
+        let (doc, page_id) = page("/Fm1 Do", vec![("Fm1", Dictionary::new(), "BT /F1 5 Tf (X) Tj ET /Fm1 Do")]);
+        assert_eq!(walk_page(&doc, page_id).unwrap().glyphs.len(), MAX_FORM_DEPTH);
+    }
+
+    #[test]
+    fn reads_cid_width_ranges() {
+        let w = Object::Array(vec![1.into(), vec![100.into(), 200.into()].into(), 10.into(), 12.into(), 300.into()]);
+        let widths = cid_widths(Some(&w));
+        assert_eq!((widths[&1], widths[&2], widths[&10], widths[&12]), (100.0, 200.0, 300.0, 300.0));
+        assert_eq!(widths.len(), 5);
+    }
+}
@@ -0,0 +1,119 @@
+// Heuristics for deciding whether the text layer lopdf gives us is worth keeping or whether
+// the page needs OCR. Scanned pages usually have no text at all, while broken font encodings
+// produce replacement glyphs, private-use code points or runs of symbols.
+
+const MIN_USABLE_CHARS: usize = 20;
+const MIN_PRINTABLE_RATIO: f32 = 0.9;
+const MIN_ALPHANUMERIC_RATIO: f32 = 0.5;
+const MAX_GARBAGE_RATIO: f32 = 0.05;
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TextQuality {
+    pub char_count: usize,
+    pub printable_ratio: f32,
+    pub alphanumeric_ratio: f32,
+    pub garbage_ratio: f32,
+}
+
+impl TextQuality {
+    pub fn score(text: &str) -> Self {
+        let mut char_count = 0;
+        let mut printable = 0;
+        let mut alphanumeric = 0;
+        let mut garbage = 0;
+
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            char_count += 1;
+            if is_garbage(c) {
+                garbage += 1;
+            } else if !c.is_control() {
+                printable += 1;
+            }
+            if c.is_alphanumeric() {
+                alphanumeric += 1;
+            }
+        }
+
+        let ratio = |n: usize| if char_count == 0 { 0.0 } else { n as f32 / char_count as f32 };
+        Self {
+            char_count,
+            printable_ratio: ratio(printable),
+            alphanumeric_ratio: ratio(alphanumeric),
+            garbage_ratio: ratio(garbage),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.char_count == 0
+    }
+
+    // text that came out of a broken encoding and should not be shown to anyone
+    pub fn is_garbage(&self) -> bool {
+        !self.is_empty()
+            && (self.printable_ratio < MIN_PRINTABLE_RATIO
+                || self.garbage_ratio > MAX_GARBAGE_RATIO
+                || self.alphanumeric_ratio < MIN_ALPHANUMERIC_RATIO)
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.char_count >= MIN_USABLE_CHARS && !self.is_garbage()
+    }
+}
+
+fn is_garbage(c: char) -> bool {
+    matches!(c,
+        '\u{FFFD}'                      // replacement character
+        | '\u{E000}'..='\u{F8FF}'       // private use area, common with unmapped Type3/CID fonts
+        | '\u{0}'..='\u{8}'
+        | '\u{E}'..='\u{1F}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_text_is_neither_usable_nor_garbage() {
+        for text in ["", " \n\t "] {
+            let quality = TextQuality::score(text);
+            assert!(quality.is_empty() && !quality.is_garbage() && !quality.is_usable());
+        }
+    }
+
+    #[test]
+    fn keeps_normal_text() {
+        let quality = TextQuality::score("The quarterly report covers revenue, costs (2023–2024) and outlook.");
+        assert!(quality.is_usable());
+        assert_eq!(quality.garbage_ratio, 0.0);
+
+        // too short to tell a text layer from a stray page number or watermark
+        let short = TextQuality::score("Page 12 of 40");
+        assert!(!short.is_garbage() && !short.is_usable());
+    }
+
+    #[test]
+    fn rejects_broken_encodings() {
+        let private_use = TextQuality::score(&"\u{E001}\u{E002}\u{E003} word ".repeat(10));
+        assert!(private_use.is_garbage() && !private_use.is_usable());
+
+        // one replacement character in twenty is still tolerated, more is not
+        let replaced = format!("{}\u{FFFD}", "a".repeat(19));
+        assert!(!TextQuality::score(&replaced).is_garbage());
+        let replaced = format!("{}\u{FFFD}\u{FFFD}", "a".repeat(19));
+        assert!(TextQuality::score(&replaced).is_garbage());
+
+        let control = format!("{}\u{1}\u{2}", "text ".repeat(5));
+        assert!(TextQuality::score(&control).is_garbage());
+    }
+
+    #[test]
+    fn rejects_symbol_heavy_text() {
+        let symbols = TextQuality::score("!#$%&*+,-./:;<=>?@[]^_{|}~ ab !#$%&*+,-./");
+        assert!(symbols.alphanumeric_ratio < MIN_ALPHANUMERIC_RATIO);
+        assert!(symbols.is_garbage() && !symbols.is_usable());
+
+        let balanced = TextQuality::score("a+b=c; d*e=f; g/h=i; j-k=l; m<n>o");
+        assert!(balanced.is_usable());
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::types::engine_handler::PageExtractInfo;
+use crate::types::engine_handler::{DocumentExtractInfo, PageExtractInfo};
+use crate::worker::NewFileProcessQueue;
 
 
 pub fn get_upload_path(file: &str) -> PathBuf {
@@ -51,8 +54,17 @@ pub fn get_pdf_image_process_path(file: &str) -> PathBuf {
     path
 }
 
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
 
-pub fn save_processed_json(data : Vec<PageExtractInfo>, file_id : &str){
+// a path in the system temp folder that no other call in this process gets, for handing data to
+// command line decoders; the caller adds the extension the tool expects
+pub fn get_temp_path(prefix: &str) -> PathBuf {
+    let count = NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("pdfz_{}_{}_{}", prefix, std::process::id(), count))
+}
+
+
+pub fn save_processed_json(data : DocumentExtractInfo, file_id : &str){
     let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
     let folder_path = Path::new(&base_path).join("processed");
     if !folder_path.exists() {
@@ -74,4 +86,148 @@ pub fn save_processed_json(data : Vec<PageExtractInfo>, file_id : &str){
 
     println!("Processed JSON saved to {:?}", json_path);
 
-}
\ No newline at end of file
+}
+
+pub fn save_processed_markdown(markdown: &str, file_id: &str) -> std::io::Result<PathBuf> {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let folder_path = Path::new(&base_path).join("processed");
+    fs::create_dir_all(&folder_path)?;
+    let clean_id = file_id.split('.').next().unwrap_or(file_id);
+    let markdown_path = folder_path.join(format!("{}.md", clean_id));
+    fs::write(&markdown_path, markdown)?;
+    println!("Processed Markdown saved to {:?}", markdown_path);
+    Ok(markdown_path)
+}
+
+pub fn get_processed_path(file: &str) -> PathBuf {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let folder_path = Path::new(&base_path).join("processed");
+    if !folder_path.exists() {
+        fs::create_dir_all(&folder_path).expect("Failed to create processed directory");
+    }
+    folder_path.join(file)
+}
+
+// attachments found in a processed file are kept next to its other outputs
+pub fn save_attachment(file_id: &str, file_name: &str, data: &[u8]) -> std::io::Result<(PathBuf, String)> {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let relative = Path::new("attachments").join(file_id).join(file_name);
+    let path = Path::new(&base_path).join(&relative);
+    if let Some(folder_path) = path.parent() {
+        fs::create_dir_all(folder_path)?;
+    }
+    fs::write(&path, data)?;
+    Ok((path, relative.to_string_lossy().into_owned()))
+}
+
+// per-image OCR output such as hOCR, kept in a folder of its own next to the processed JSON
+pub fn save_ocr_output(file_id: &str, file_name: &str, content: &str) -> std::io::Result<String> {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let relative = Path::new("processed").join(file_id).join(file_name);
+    let path = Path::new(&base_path).join(&relative);
+    if let Some(folder_path) = path.parent() {
+        fs::create_dir_all(folder_path)?;
+    }
+    fs::write(&path, content)?;
+    Ok(relative.to_string_lossy().into_owned())
+}
+
+// images kept for export, such as embedded images with their transparency, next to the OCR output
+pub fn save_processed_image(file_id: &str, file_name: &str, image: &image::DynamicImage) -> image::ImageResult<String> {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let relative = Path::new("processed").join(file_id).join(file_name);
+    let path = Path::new(&base_path).join(&relative);
+    if let Some(folder_path) = path.parent() {
+        fs::create_dir_all(folder_path)?;
+    }
+    image.save(&path)?;
+    Ok(relative.to_string_lossy().into_owned())
+}
+
+// Per-page results are checkpointed under `checkpoints/<file_id>/` while a job runs so an
+// interrupted job can pick up where it stopped instead of starting from page one.
+pub fn get_checkpoint_path(file_id: &str) -> PathBuf {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    Path::new(&base_path).join("checkpoints").join(file_id)
+}
+
+pub fn save_job_checkpoint(message: &NewFileProcessQueue, file_id: &str) -> std::io::Result<()> {
+    let folder_path = get_checkpoint_path(file_id);
+    fs::create_dir_all(&folder_path)?;
+    write_atomic(&folder_path.join("job.json"), &serde_json::to_vec_pretty(message)?)
+}
+
+// returns the message of the last run, whether it was interrupted or failed
+pub fn load_job_checkpoint(file_id: &str) -> Option<NewFileProcessQueue> {
+    let folder_path = get_checkpoint_path(file_id);
+    let content = fs::read(folder_path.join("job.json"))
+        .or_else(|_| fs::read(folder_path.join("failed_job.json")))
+        .ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+// `job.json` is what marks a job as unfinished, so a failed job is moved aside to stop it being
+// resumed on every restart while its page results stay around for a redelivery to reuse
+pub fn mark_job_checkpoint_failed(file_id: &str) -> std::io::Result<()> {
+    let folder_path = get_checkpoint_path(file_id);
+    fs::rename(folder_path.join("job.json"), folder_path.join("failed_job.json"))
+}
+
+pub fn save_page_checkpoint(file_id: &str, page: &PageExtractInfo) -> std::io::Result<()> {
+    let folder_path = get_checkpoint_path(file_id);
+    fs::create_dir_all(&folder_path)?;
+    let page_path = folder_path.join(format!("page_{}.json", page.page_num));
+    write_atomic(&page_path, &serde_json::to_vec(page)?)
+}
+
+pub fn load_page_checkpoints(file_id: &str) -> HashMap<u32, PageExtractInfo> {
+    let mut pages = HashMap::new();
+    let Ok(entries) = fs::read_dir(get_checkpoint_path(file_id)) else {
+        return pages;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_page = path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("page_") && name.ends_with(".json"));
+        if !is_page {
+            continue;
+        }
+        match fs::read(&path).map(|content| serde_json::from_slice::<PageExtractInfo>(&content)) {
+            Ok(Ok(page)) => {
+                pages.insert(page.page_num, page);
+            }
+            _ => println!("Ignoring unreadable checkpoint {:?}", path),
+        }
+    }
+    pages
+}
+
+pub fn clear_checkpoints(file_id: &str) -> std::io::Result<()> {
+    let folder_path = get_checkpoint_path(file_id);
+    if folder_path.exists() {
+        fs::remove_dir_all(folder_path)?;
+    }
+    Ok(())
+}
+
+// every job that still has a job file was interrupted before it finished
+pub fn list_interrupted_jobs() -> Vec<NewFileProcessQueue> {
+    let base_path = std::env::var("SHARED_STORAGE_PATH").unwrap();
+    let Ok(entries) = fs::read_dir(Path::new(&base_path).join("checkpoints")) else {
+        return vec![];
+    };
+
+    entries.flatten()
+        .filter_map(|entry| fs::read(entry.path().join("job.json")).ok())
+        .filter_map(|content| serde_json::from_slice(&content).ok())
+        .collect()
+}
+
+// write to a temporary file first so a crash mid-write never leaves a truncated checkpoint
+fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(tmp_path, path)
+}
@@ -1 +1,2 @@
-pub mod file_helper;
\ No newline at end of file
+pub mod file_helper;
+pub mod page_selection;
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Page selection expressions accepted in job messages, for example "1-3,7,10-", "odd",
+// "even", "first 5", "last 5" or "all". Terms are separated by commas, page numbers are
+// 1-based and the resulting set is always returned in ascending order.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageSelectionError {
+    Empty,
+    InvalidTerm(String),
+    OutOfRange { page: u32, page_count: u32 },
+    ReversedRange { start: u32, end: u32 },
+    NoPagesSelected(String),
+}
+
+impl fmt::Display for PageSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageSelectionError::Empty => write!(f, "page selection is empty"),
+            PageSelectionError::InvalidTerm(term) => write!(f, "invalid page selection term '{}'", term),
+            PageSelectionError::OutOfRange { page, page_count } => {
+                write!(f, "page {} is out of range, the document has {} pages", page, page_count)
+            }
+            PageSelectionError::ReversedRange { start, end } => {
+                write!(f, "page range {}-{} ends before it starts", start, end)
+            }
+            PageSelectionError::NoPagesSelected(expression) => {
+                write!(f, "page selection '{}' does not select any page", expression)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PageSelectionError {}
+
+pub fn parse_page_selection(expression: &str, page_count: u32) -> Result<Vec<u32>, PageSelectionError> {
+    if expression.trim().is_empty() {
+        return Err(PageSelectionError::Empty);
+    }
+
+    let mut pages = BTreeSet::new();
+    for term in expression.split(',').map(str::trim) {
+        if term.is_empty() {
+            continue;
+        }
+        pages.extend(parse_term(term, page_count)?);
+    }
+
+    if pages.is_empty() {
+        return Err(PageSelectionError::NoPagesSelected(expression.to_string()));
+    }
+    Ok(pages.into_iter().collect())
+}
+
+// the selection described by the older `start_page` + `page_count` fields, clamped to the
+// document instead of rejected so existing clients keep working
+pub fn legacy_page_selection(start_page: u32, page_count: u32, document_pages: u32) -> Vec<u32> {
+    let start = start_page.max(1);
+    let end = match page_count {
+        0 => document_pages,
+        count => start.saturating_add(count - 1).min(document_pages),
+    };
+    (start..=end).collect()
+}
+
+fn parse_term(term: &str, page_count: u32) -> Result<Vec<u32>, PageSelectionError> {
+    let lowered = term.to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["all"] => Ok((1..=page_count).collect()),
+        ["odd"] => Ok((1..=page_count).step_by(2).collect()),
+        ["even"] => Ok((2..=page_count).step_by(2).collect()),
+        ["first", count] => {
+            let count = parse_page_number(count, term)?;
+            Ok((1..=count.min(page_count)).collect())
+        }
+        ["last", count] => {
+            let count = parse_page_number(count, term)?.min(page_count);
+            Ok((page_count - count + 1..=page_count).collect())
+        }
+        _ if lowered.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+            parse_range(&words.concat(), term, page_count)
+        }
+        _ => Err(PageSelectionError::InvalidTerm(term.to_string())),
+    }
+}
+
+fn parse_range(range: &str, term: &str, page_count: u32) -> Result<Vec<u32>, PageSelectionError> {
+    let (start, end) = match range.split_once('-') {
+        None => {
+            let page = parse_page_number(range, term)?;
+            (page, page)
+        }
+        Some((start, end)) => {
+            let start = if start.is_empty() { 1 } else { parse_page_number(start, term)? };
+            let end = if end.is_empty() { page_count } else { parse_page_number(end, term)? };
+            (start, end)
+        }
+    };
+
+    for page in [start, end] {
+        if page > page_count {
+            return Err(PageSelectionError::OutOfRange { page, page_count });
+        }
+    }
+    if start > end {
+        return Err(PageSelectionError::ReversedRange { start, end });
+    }
+    Ok((start..=end).collect())
+}
+
+fn parse_page_number(value: &str, term: &str) -> Result<u32, PageSelectionError> {
+    match value.trim().parse::<u32>() {
+        Ok(page) if page > 0 => Ok(page),
+        _ => Err(PageSelectionError::InvalidTerm(term.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pages_and_ranges() {
+        assert_eq!(parse_page_selection("1-3,7,10-", 12), Ok(vec![1, 2, 3, 7, 10, 11, 12]));
+        assert_eq!(parse_page_selection("5", 12), Ok(vec![5]));
+        assert_eq!(parse_page_selection("-3", 12), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_page_selection(" 2 - 4 , 9 ", 12), Ok(vec![2, 3, 4, 9]));
+    }
+
+    #[test]
+    fn merges_overlapping_terms_in_order() {
+        assert_eq!(parse_page_selection("7,1-3,2,3-4", 10), Ok(vec![1, 2, 3, 4, 7]));
+        assert_eq!(parse_page_selection("1,,2,", 10), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(parse_page_selection("all", 4), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(parse_page_selection("odd", 5), Ok(vec![1, 3, 5]));
+        assert_eq!(parse_page_selection("even", 5), Ok(vec![2, 4]));
+        assert_eq!(parse_page_selection("First 2", 5), Ok(vec![1, 2]));
+        assert_eq!(parse_page_selection("last 2", 5), Ok(vec![4, 5]));
+        assert_eq!(parse_page_selection("first 2, last 1", 5), Ok(vec![1, 2, 5]));
+    }
+
+    #[test]
+    fn clamps_first_and_last_to_the_document() {
+        assert_eq!(parse_page_selection("first 10", 3), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_page_selection("last 10", 3), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn rejects_out_of_range_pages() {
+        assert_eq!(parse_page_selection("4", 3), Err(PageSelectionError::OutOfRange { page: 4, page_count: 3 }));
+        assert_eq!(parse_page_selection("2-8", 3), Err(PageSelectionError::OutOfRange { page: 8, page_count: 3 }));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(parse_page_selection("", 3), Err(PageSelectionError::Empty));
+        assert_eq!(parse_page_selection("  ", 3), Err(PageSelectionError::Empty));
+        assert_eq!(parse_page_selection("3-1", 3), Err(PageSelectionError::ReversedRange { start: 3, end: 1 }));
+        assert_eq!(parse_page_selection("0", 3), Err(PageSelectionError::InvalidTerm("0".to_string())));
+        assert_eq!(parse_page_selection("1-x", 3), Err(PageSelectionError::InvalidTerm("1-x".to_string())));
+        assert_eq!(parse_page_selection("first", 3), Err(PageSelectionError::InvalidTerm("first".to_string())));
+        assert_eq!(parse_page_selection("last 0", 3), Err(PageSelectionError::InvalidTerm("last 0".to_string())));
+        assert_eq!(parse_page_selection("pages", 3), Err(PageSelectionError::InvalidTerm("pages".to_string())));
+        assert_eq!(parse_page_selection(",", 3), Err(PageSelectionError::NoPagesSelected(",".to_string())));
+        assert_eq!(parse_page_selection("even", 1), Err(PageSelectionError::NoPagesSelected("even".to_string())));
+    }
+
+    #[test]
+    fn legacy_selection_is_clamped_to_the_document() {
+        assert_eq!(legacy_page_selection(1, 0, 3), vec![1, 2, 3]);
+        assert_eq!(legacy_page_selection(0, 2, 3), vec![1, 2]);
+        assert_eq!(legacy_page_selection(2, 10, 4), vec![2, 3, 4]);
+        assert_eq!(legacy_page_selection(5, 2, 4), Vec::<u32>::new());
+        assert_eq!(legacy_page_selection(u32::MAX, 2, 4), Vec::<u32>::new());
+    }
+}
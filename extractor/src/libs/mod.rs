@@ -1,2 +1,3 @@
 pub mod extractor;
+pub mod pdfium;
 pub mod redis;
\ No newline at end of file
@@ -0,0 +1,20 @@
+use std::sync::OnceLock;
+use pdfium_render::prelude::Pdfium;
+
+// pdfium must only be initialised once per process, so every render shares this instance
+static PDFIUM: OnceLock<Result<Pdfium, String>> = OnceLock::new();
+
+pub fn get_pdfium() -> Result<&'static Pdfium, String> {
+    PDFIUM.get_or_init(|| {
+        let bindings = match std::env::var("PDFIUM_LIB_PATH") {
+            Ok(path) => Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&path))
+                .or_else(|_| Pdfium::bind_to_system_library()),
+            Err(_) => Pdfium::bind_to_system_library(),
+        };
+        bindings
+            .map(Pdfium::new)
+            .map_err(|e| format!("Failed to load pdfium library: {}", e))
+    })
+    .as_ref()
+    .map_err(|e| e.clone())
+}
@@ -12,6 +12,8 @@ pub enum Status {
     Pending,
     Done,
     Failed,
+    PasswordRequired,
+    WrongPassword,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -28,6 +30,8 @@ impl Status {
             Status::Pending => "pending".to_string(),
             Status::Done => "done".to_string(),
             Status::Failed => "failed".to_string(),
+            Status::PasswordRequired => "password_required".to_string(),
+            Status::WrongPassword => "wrong_password".to_string(),
         }
     }
 
@@ -35,6 +39,8 @@ impl Status {
         match s {
             "done" => Status::Done,
             "failed" => Status::Failed,
+            "password_required" => Status::PasswordRequired,
+            "wrong_password" => Status::WrongPassword,
             _ => Status::Pending,
         }
     }
@@ -122,6 +128,12 @@ impl RedisProgressManager {
         Ok(progress.unwrap_or(0))
     }
 
+    pub async fn set_error(&self, id: &str, reason: &str) -> RedisResult<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("{}:error:{}", self.prefix, id);
+        con.set(key, reason).await
+    }
+
     pub async fn set_with_ttl(&self, id: &str, status: impl ToString, ttl: u64) -> RedisResult<()> {
         let mut con = self.client.get_multiplexed_async_connection().await?;
         let key = format!("{}:status:{}", self.prefix, id);
@@ -159,22 +171,23 @@ impl FileProcessingManager {
     }
 
     pub async fn mark_as_done(file_id: &str) -> RedisResult<()> {
-        Self::instance().await.unwrap().redis.set_status(file_id, Status::Done).await
+        let instance = Self::instance().await.unwrap();
+        instance.redis.set_progress(file_id, 100).await?;
+        instance.redis.set_status(file_id, Status::Done).await
     }
 
-    pub async fn mark_as_failed(file_id: &str) -> RedisResult<()> {
-        Self::instance().await.unwrap().redis.set_status(file_id, Status::Failed).await
+    pub async fn mark_as_failed_with(file_id: &str, status: Status, reason: &str) -> RedisResult<()> {
+        let instance = Self::instance().await?;
+        instance.redis.set_error(file_id, reason).await?;
+        instance.redis.set_status(file_id, status).await
     }
 
     pub async fn mark_progress(file_id: &str, page: u32, total: u32) -> RedisResult<()> {
         let instance = Self::instance().await.unwrap();
-        let progress = if total == 0 { 0 } else { (page * 100) / total };
-        instance.redis.set_progress(file_id, progress).await?;
-        
-        if progress == 100 {
-            FileProcessingManager::mark_as_done(file_id).await?;
-        }
-        Ok(())
+        // the last page still leaves attachments and the output files to write, only
+        // mark_as_done reports 100 and the job as done
+        let progress = if total == 0 { 0 } else { ((page * 100) / total).min(99) };
+        instance.redis.set_progress(file_id, progress).await
     }
 
     pub async fn get_progress(file_id: &str) -> RedisResult<u32> {
@@ -292,8 +305,8 @@ pub async fn mark_as_done(file_id: &str) -> RedisResult<()> {
     FileProcessingManager::mark_as_done(file_id).await
 }
 
-pub async fn mark_as_failed(file_id: &str) -> RedisResult<()> {
-    FileProcessingManager::mark_as_failed(file_id).await
+pub async fn mark_as_failed_with(file_id: &str, status: Status, reason: &str) -> RedisResult<()> {
+    FileProcessingManager::mark_as_failed_with(file_id, status, reason).await
 }
 
 pub async fn is_model_downloading(model_name: &str) -> RedisResult<bool> {
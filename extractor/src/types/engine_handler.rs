@@ -6,14 +6,132 @@ use ollama_rs::Ollama;
 use redis::Client;
 use tokio::{sync::Semaphore, task};
 use std::future::Future;
-use crate::{engine::{ollama::OllamaEngine, tesseract::TesseractEngine, MainEngine}, helper::file_helper::{self, save_processed_json}, libs::redis::{ mark_as_done, mark_as_failed, mark_progress, Status}, worker::NewFileProcessQueue};
+use crate::{engine::{annotations::Annotation, attachments::{AttachmentKind, AttachmentSource}, forms::FormField, language::PageLanguage, layout::PageLayout, loader::LoaderInfo, metadata::DocumentMetadata, outline::OutlineItem, ollama::OllamaEngine, tesseract::TesseractEngine, text_quality::TextQuality, MainEngine}, helper::file_helper::{self, save_processed_json}, libs::redis::{ mark_as_done, mark_progress, Status}, worker::NewFileProcessQueue};
 use std::pin::Pin;
 
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageKind {
+    Embedded,
+    PageRender,
+    Attachment,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImageExtractInfo {
+    pub image_name: String,
+    pub kind: ImageKind,
+    pub text: String,
+    pub engine: String,
+    pub error: Option<String>,
+    // traineddata the engine read the image with, such as "fra+eng"
+    #[serde(default)]
+    pub languages: Option<String>,
+    // relative to SHARED_STORAGE_PATH, when the job asks for hOCR and ALTO and the engine gave word boxes
+    #[serde(default)]
+    pub hocr: Option<String>,
+    #[serde(default)]
+    pub alto: Option<String>,
+    // relative to SHARED_STORAGE_PATH, the embedded image with its transparency when the job keeps it
+    #[serde(default)]
+    pub rgba: Option<String>,
+    // positional output of the engine, turned into the page layout and not stored on its own
+    #[serde(skip)]
+    pub words: Option<Vec<OcrWord>>,
+}
+
+// a word recognised in an image, in pixels from the top left corner of that image
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub block: u32,
+    pub paragraph: u32,
+    pub line: u32,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    // 0 to 100
+    pub confidence: f32,
+}
+
+pub type OcrFuture = Pin<Box<dyn Future<Output = Result<OcrOutput, Box<dyn Error + Send>>> + Send>>;
+
+pub struct OcrOutput {
+    pub text: String,
+    // None for engines that only return text
+    pub words: Option<Vec<OcrWord>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    TextLayer,
+    Ocr,
+    Merged,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct PageExtractInfo {
     pub page_num: u32,
-    pub text:  String
+    pub text:  String,
+    pub text_quality: TextQuality,
+    // which of the text layer and OCR output ended up in `full_text`
+    pub source: Option<TextSource>,
+    pub images: Vec<ImageExtractInfo>,
+    // text layer followed by the OCR text of every image on the page
+    pub full_text: String,
+    // outline titles from chapter down to the innermost section the page belongs to
+    #[serde(default)]
+    pub sections: Vec<String>,
+    #[serde(default)]
+    pub form_fields: Vec<FormField>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    // lines repeated at the top and bottom of most pages, taken out of `text` and `full_text`
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default)]
+    pub footer: Option<String>,
+    // blocks, lines and words with their positions, only kept when the job asks for it
+    #[serde(default)]
+    pub layout: Option<PageLayout>,
+    // the header and footer lines taken out of `layout`, still placed in the searchable PDF
+    #[serde(skip)]
+    pub edge_layout: Option<PageLayout>,
+    // dominant language of `full_text`, None when there is too little text to tell
+    #[serde(default)]
+    pub language: Option<PageLanguage>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DocumentExtractInfo {
+    // which loader opened the file and what had to be repaired on the way
+    pub loader: LoaderInfo,
+    pub metadata: DocumentMetadata,
+    pub outline: Vec<OutlineItem>,
+    pub pages: Vec<PageExtractInfo>,
+    pub attachments: Vec<AttachmentExtractInfo>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AttachmentExtractInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+    pub kind: AttachmentKind,
+    pub source: AttachmentSource,
+    // page of the file attachment annotation
+    pub page_num: Option<u32>,
+    pub size: usize,
+    // relative to SHARED_STORAGE_PATH
+    pub saved_as: Option<String>,
+    // set for processed PDF attachments
+    pub document: Option<Box<DocumentExtractInfo>>,
+    // set for processed image attachments
+    pub image: Option<ImageExtractInfo>,
+    pub error: Option<String>,
 }
 pub enum Engines {
     Tesseract,
@@ -51,7 +169,23 @@ impl Engines {
 
 pub trait EngineHandler: Send + Sync + Debug {
     fn new(model: Option<String>) -> Self where Self: Sized;
+
+    fn name(&self) -> String;
     
     fn extract_text_from_image(&self, image_path: String) 
         -> Pin<Box<dyn Future<Output = Result<String, Box<dyn Error + Send>>> + Send>>;
+
+    // engines that know where the text sits override this to return word boxes as well.
+    // `languages` are ISO 639-3 codes joined by "+", such as "fra+eng"
+    fn extract_from_image(&self, image_path: String, _languages: Option<String>) -> OcrFuture {
+        let text = self.extract_text_from_image(image_path);
+        Box::pin(async move {
+            Ok(OcrOutput { text: text.await?, words: None })
+        })
+    }
+
+    // what extract_from_image would read `requested` with, None for engines that take no languages
+    fn ocr_languages(&self, _requested: Option<&str>) -> Option<String> {
+        None
+    }
 }
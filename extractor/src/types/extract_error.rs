@@ -0,0 +1,33 @@
+use std::fmt;
+
+use crate::libs::redis::Status;
+
+// Failures that the API needs to tell apart from a generic failed job
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractError {
+    PasswordRequired,
+    WrongPassword,
+    UnsupportedEncryption(String),
+}
+
+impl ExtractError {
+    pub fn status(&self) -> Status {
+        match self {
+            ExtractError::PasswordRequired => Status::PasswordRequired,
+            ExtractError::WrongPassword => Status::WrongPassword,
+            ExtractError::UnsupportedEncryption(_) => Status::Failed,
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::PasswordRequired => write!(f, "the PDF is encrypted and a password is required"),
+            ExtractError::WrongPassword => write!(f, "the password supplied for the PDF is incorrect"),
+            ExtractError::UnsupportedEncryption(reason) => write!(f, "the PDF uses an unsupported encryption: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
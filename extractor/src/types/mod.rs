@@ -1 +1,2 @@
-pub mod engine_handler;
\ No newline at end of file
+pub mod engine_handler;
+pub mod extract_error;
\ No newline at end of file
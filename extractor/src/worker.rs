@@ -1,13 +1,177 @@
 use amiquip::Connection;
 use crate::controllers::queue_handler::RabbitMQFileProcessor;
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    // OCR the image XObjects embedded in the page
+    #[default]
+    Images,
+    // rasterise the whole page through pdfium and OCR the bitmap
+    Page,
+    Both,
+}
+
+impl RenderMode {
+    pub fn uses_images(&self) -> bool {
+        matches!(self, RenderMode::Images | RenderMode::Both)
+    }
+
+    pub fn uses_page_render(&self) -> bool {
+        matches!(self, RenderMode::Page | RenderMode::Both)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OcrMode {
+    // OCR every page regardless of its text layer
+    #[default]
+    Always,
+    // only OCR pages whose text layer is missing or unusable
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeForm {
+    // composes accents with their letters
+    Nfc,
+    // also folds compatibility characters such as ligatures, full-width letters and superscripts
+    Nfkc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    // processed/<id>.json with everything extracted
+    Json,
+    // processed/<id>.md with headings, lists and tables
+    Markdown,
+    // processed/<id>/<image>.hocr for every image Tesseract read
+    Hocr,
+    // processed/<id>/<image>.alto.xml for every image Tesseract read
+    Alto,
+    // processed/<id>.pdf, the input with the OCR text laid invisibly over its images
+    SearchablePdf,
+}
+
+// text clean-up steps, each one off unless the job turns it on
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NormalizeOptions {
+    // ﬁ and ﬂ and the other latin ligatures become separate letters
+    pub ligatures: bool,
+    // words hyphenated across a line break are joined
+    pub dehyphenate: bool,
+    pub unicode: Option<UnicodeForm>,
+    // UTF-8 that was read as Windows-1252, such as "â€™" for "’"
+    pub mojibake: bool,
+    // runs of spaces and blank lines are collapsed
+    pub whitespace: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NewFileProcessQueue {
     pub file: String,
     pub start_page: u32,
     pub page_count: u32,
     pub engine: String,
-    pub model : Option<String>
+    pub model : Option<String>,
+    // never written to the job checkpoint, a resumed job without it reports password_required
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    // page selection expression such as "1-3,7,10-", takes precedence over start_page/page_count
+    pub pages: Option<String>,
+    #[serde(default)]
+    pub render_mode: RenderMode,
+    pub render_dpi: Option<u32>,
+    #[serde(default)]
+    pub ocr_mode: OcrMode,
+    // how many pages of this document may be in flight at once
+    pub page_concurrency: Option<usize>,
+    // run the extraction on attached PDFs and images too, not just save them
+    #[serde(default)]
+    pub process_attachments: bool,
+    // how many levels of attachments inside attachments are processed
+    pub attachment_depth: Option<u32>,
+    // keep the blocks, lines and words of every page with their positions
+    #[serde(default)]
+    pub layout: bool,
+    // order text by columns and blocks rather than as the content stream lists it, on by default
+    pub reading_order: Option<bool>,
+    #[serde(default)]
+    pub normalize: NormalizeOptions,
+    // OCR languages as ISO 639-3 codes joined by "+", such as "deu+fra". Without them every page
+    // is read in the language detected from its text layer or from a first OCR pass
+    pub languages: Option<String>,
+    // files written to the processed directory, only JSON when empty
+    #[serde(default)]
+    pub formats: Vec<OutputFormat>,
+    // also save embedded images that have transparency with their alpha channel, next to the OCR output
+    #[serde(default)]
+    pub keep_image_alpha: bool,
+    // write the searchable PDF of an encrypted input even though it comes out without its encryption
+    #[serde(default)]
+    pub decrypt_searchable_pdf: bool,
+}
+
+const DEFAULT_ATTACHMENT_DEPTH: u32 = 1;
+const MAX_ATTACHMENT_DEPTH: u32 = 3;
+
+// options that only decide which pages run and how fast, not what a single page produces
+const SCHEDULING_OPTIONS: [&str; 4] = ["start_page", "page_count", "pages", "page_concurrency"];
+
+impl NormalizeOptions {
+    pub fn is_enabled(&self) -> bool {
+        *self != NormalizeOptions::default()
+    }
+}
+
+impl NewFileProcessQueue {
+    pub fn page_concurrency(&self) -> usize {
+        self.page_concurrency
+            .unwrap_or_else(default_page_concurrency)
+            .max(1)
+    }
+
+    pub fn attachment_depth(&self) -> u32 {
+        self.attachment_depth
+            .unwrap_or(DEFAULT_ATTACHMENT_DEPTH)
+            .min(MAX_ATTACHMENT_DEPTH)
+    }
+
+    pub fn reading_order(&self) -> bool {
+        self.reading_order.unwrap_or(true)
+    }
+
+    pub fn writes(&self, format: OutputFormat) -> bool {
+        if self.formats.is_empty() {
+            return format == OutputFormat::Json;
+        }
+        self.formats.contains(&format)
+    }
+
+    // whether page results checkpointed for `other` can be reused for this job
+    pub fn produces_same_pages(&self, other: &NewFileProcessQueue) -> bool {
+        let output_options = |message: &NewFileProcessQueue| {
+            let mut value = serde_json::to_value(message).unwrap_or_default();
+            if let Some(fields) = value.as_object_mut() {
+                for option in SCHEDULING_OPTIONS {
+                    fields.remove(option);
+                }
+            }
+            value
+        };
+        output_options(self) == output_options(other)
+    }
+}
+
+fn default_page_concurrency() -> usize {
+    std::env::var("PAGE_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(1)
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]